@@ -1,4 +1,11 @@
-use linked_list_allocator::LockedHeap;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use linked_list_allocator::Heap;
+use spin::Mutex;
 use x86_64::{
     VirtAddr,
     structures::paging::{
@@ -6,11 +13,210 @@ use x86_64::{
     },
 };
 
+use crate::memory;
+
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: CountingHeap = CountingHeap {
+    heap: SlabHeap::empty(),
+};
+
+/// The total number of bytes allocated and freed since boot. Each is only ever
+/// added to, so the bytes in use are the difference between the two.
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static FREED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Wraps the heap to keep track of how much of it is in use
+struct CountingHeap {
+    heap: SlabHeap,
+}
+
+unsafe impl GlobalAlloc for CountingHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: the caller upholds the contract of GlobalAlloc::alloc
+        let ptr = unsafe { self.heap.alloc(layout) };
+
+        if !ptr.is_null() {
+            ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: the caller upholds the contract of GlobalAlloc::dealloc
+        unsafe { self.heap.dealloc(ptr, layout) };
+
+        FREED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+    }
+}
+
+/* Slabs */
+
+/// The block sizes which small allocations are rounded up to. Each has its own
+/// free list, so that the many short-lived small allocations (nodes, names,
+/// cache entries) reuse blocks of the same size rather than splitting up the
+/// free space of the heap.
+///
+/// NOTE: blocks are carved out of the 1 MiB heap when a free list runs empty
+/// and are never given back to it, even once all of them are free again. The
+/// memory of a burst of small allocations can only be reused by allocations
+/// of the same slab size afterwards.
+pub const SLAB_SIZES: [usize; 4] = [16, 32, 64, 128];
+
+/// The number of blocks of each slab size which have been carved out of the
+/// heap and the number of those which are currently on the free list
+struct SlabCounters {
+    blocks: [AtomicUsize; SLAB_SIZES.len()],
+    free_blocks: [AtomicUsize; SLAB_SIZES.len()],
+}
+
+impl SlabCounters {
+    const fn new() -> Self {
+        Self {
+            blocks: [const { AtomicUsize::new(0) }; SLAB_SIZES.len()],
+            free_blocks: [const { AtomicUsize::new(0) }; SLAB_SIZES.len()],
+        }
+    }
+}
+
+static SLAB_COUNTERS: SlabCounters = SlabCounters::new();
+
+/// A free block, which stores the link to the next free block of its size in
+/// the block itself
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+}
+
+/// Serves allocations of up to [`SLAB_SIZES`] bytes from per-size free lists
+/// and everything else from a linked list heap. The blocks are taken from the
+/// same heap when a free list runs empty, but are never given back to it.
+struct SlabHeap {
+    inner: Mutex<Slabs>,
+}
+
+struct Slabs {
+    free_lists: [Option<NonNull<FreeBlock>>; SLAB_SIZES.len()],
+    fallback: Heap,
+    counters: &'static SlabCounters,
+}
+
+// SAFETY: the free blocks are only reachable through the mutex
+unsafe impl Send for Slabs {}
+
+/// Returns the index of the smallest slab which fits the layout. Blocks are
+/// aligned to their size, so the alignment has to fit as well.
+fn slab_index(layout: &Layout) -> Option<usize> {
+    let required = layout.size().max(layout.align());
+
+    SLAB_SIZES.iter().position(|&size| size >= required)
+}
+
+impl Slabs {
+    const fn empty(counters: &'static SlabCounters) -> Self {
+        Self {
+            free_lists: [None; SLAB_SIZES.len()],
+            fallback: Heap::empty(),
+            counters,
+        }
+    }
+
+    /// Returns a block which fits the layout, or null if the heap is full
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let Some(index) = slab_index(&layout) else {
+            return self
+                .fallback
+                .allocate_first_fit(layout)
+                .map_or(ptr::null_mut(), |block| block.as_ptr());
+        };
+
+        if let Some(block) = self.free_lists[index] {
+            // SAFETY: blocks on a free list are unused and hold a FreeBlock
+            self.free_lists[index] = unsafe { block.as_ref().next };
+            self.counters.free_blocks[index].fetch_sub(1, Ordering::Relaxed);
+
+            return block.as_ptr().cast();
+        }
+
+        let size = SLAB_SIZES[index];
+        // SAFETY: every slab size is a non-zero power of two
+        let block_layout = unsafe { Layout::from_size_align_unchecked(size, size) };
+
+        match self.fallback.allocate_first_fit(block_layout) {
+            Ok(block) => {
+                self.counters.blocks[index].fetch_add(1, Ordering::Relaxed);
+                block.as_ptr()
+            }
+            Err(()) => ptr::null_mut(),
+        }
+    }
+
+    /// Puts a slab block on its free list or gives anything larger back to
+    /// the heap
+    ///
+    /// # Safety
+    ///
+    /// The block must have been returned by [`Slabs::allocate`] with the same
+    /// layout and must no longer be used
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let Some(index) = slab_index(&layout) else {
+            // SAFETY: the caller upholds the contract, and layouts which don't
+            // fit a slab always come from the fallback
+            unsafe {
+                self.fallback
+                    .deallocate(NonNull::new_unchecked(ptr), layout)
+            };
+            return;
+        };
+
+        let block = ptr.cast::<FreeBlock>();
+        // SAFETY: the block is at least 16 bytes, aligned to its size and no
+        // longer used by the caller
+        unsafe {
+            block.write(FreeBlock {
+                next: self.free_lists[index],
+            });
+            self.free_lists[index] = Some(NonNull::new_unchecked(block));
+        }
+
+        self.counters.free_blocks[index].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl SlabHeap {
+    const fn empty() -> Self {
+        Self {
+            inner: Mutex::new(Slabs::empty(&SLAB_COUNTERS)),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The memory must be valid, unused and only initialized once
+    unsafe fn init(&self, start: *mut u8, size: usize) {
+        // SAFETY: the caller upholds the contract
+        unsafe { self.inner.lock().fallback.init(start, size) };
+    }
+}
+
+unsafe impl GlobalAlloc for SlabHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.inner.lock().allocate(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: the caller upholds the contract of GlobalAlloc::dealloc
+        unsafe { self.inner.lock().deallocate(ptr, layout) };
+    }
+}
 
 pub const HEAP_START: usize = 0x_4444_4444_0000;
-pub const HEAP_SIZE: usize = 128 * 1024; // 128 KiB
+pub const HEAP_SIZE: usize = 1024 * 1024; // 1 MiB
+
+/// The part of the heap which is mapped during initialization. The rest is
+/// mapped a page at a time by the page fault handler once it is first used
+/// (see [`memory::reserve_on_demand`]), so frames are only spent on the part of
+/// the heap which is actually needed.
+const HEAP_INITIAL_SIZE: usize = 128 * 1024; // 128 KiB
 
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
@@ -18,7 +224,7 @@ pub fn init_heap(
 ) -> Result<(), MapToError<Size4KiB>> {
     let page_range = {
         let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + HEAP_SIZE as _ - 1u64;
+        let heap_end = heap_start + HEAP_INITIAL_SIZE as _ - 1u64;
         let heap_start_page = Page::containing_address(heap_start);
         let heap_end_page = Page::containing_address(heap_end);
         Page::range_inclusive(heap_start_page, heap_end_page)
@@ -32,9 +238,205 @@ pub fn init_heap(
         unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
     }
 
+    memory::reserve_on_demand(
+        VirtAddr::new((HEAP_START + HEAP_INITIAL_SIZE) as u64),
+        (HEAP_SIZE - HEAP_INITIAL_SIZE) as u64,
+    );
+
     unsafe {
-        ALLOCATOR.lock().init(HEAP_START as _, HEAP_SIZE);
+        ALLOCATOR.heap.init(HEAP_START as _, HEAP_SIZE);
     }
 
     Ok(())
 }
+
+/// A snapshot of the kernel heap usage, in bytes
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// The size of the whole heap
+    pub size: usize,
+    /// The total of every allocation made since boot
+    pub allocated: usize,
+    /// The total of every allocation freed since boot
+    pub freed: usize,
+    /// The blocks of each of the [`SLAB_SIZES`]
+    pub slabs: [SlabStats; SLAB_SIZES.len()],
+}
+
+/// The blocks of one slab size
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+    /// The size of every block, in bytes
+    pub block_size: usize,
+    /// The blocks which have been taken from the heap
+    pub blocks: usize,
+    /// The blocks which are on the free list, ready to be reused
+    pub free_blocks: usize,
+}
+
+impl HeapStats {
+    /// The bytes which are currently allocated
+    pub fn used(&self) -> usize {
+        self.allocated - self.freed
+    }
+
+    /// The bytes in the free lists of the slabs. They can only be reused by
+    /// allocations of the same size.
+    pub fn cached(&self) -> usize {
+        self.slabs
+            .iter()
+            .map(|slab| slab.free_blocks * slab.block_size)
+            .sum()
+    }
+
+    /// The bytes which are not allocated or cached. The allocator rounds
+    /// allocations up, so slightly less than this can actually be allocated.
+    pub fn available(&self) -> usize {
+        self.size.saturating_sub(self.used() + self.cached())
+    }
+}
+
+/// Returns how much of the kernel heap is currently in use
+pub fn stats() -> HeapStats {
+    // NOTE: memory is always allocated before it is freed, so reading the
+    // freed bytes first keeps them from exceeding the allocated bytes
+    let freed = FREED_BYTES.load(Ordering::Relaxed);
+    let allocated = ALLOCATED_BYTES.load(Ordering::Relaxed);
+
+    // The same goes for the free blocks of a slab, which never exceed the
+    // blocks taken from the heap
+    let slabs = core::array::from_fn(|i| {
+        let free_blocks = SLAB_COUNTERS.free_blocks[i].load(Ordering::Relaxed);
+
+        SlabStats {
+            block_size: SLAB_SIZES[i],
+            blocks: SLAB_COUNTERS.blocks[i].load(Ordering::Relaxed),
+            free_blocks,
+        }
+    });
+
+    HeapStats {
+        size: HEAP_SIZE,
+        allocated,
+        freed,
+        slabs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec};
+
+    use super::*;
+
+    /// Creates slabs on top of a small heap of their own, so that the tests
+    /// neither depend on nor disturb the kernel heap and its counters
+    fn test_slabs() -> Slabs {
+        let counters = Box::leak(Box::new(SlabCounters::new()));
+        let memory = Box::leak(vec![0u8; 4096].into_boxed_slice());
+
+        let mut slabs = Slabs::empty(counters);
+        // SAFETY: the memory is leaked, so it is never used for anything else
+        unsafe { slabs.fallback.init(memory.as_mut_ptr(), memory.len()) };
+
+        slabs
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test_case]
+    fn counters_track_allocations() {
+        let before = stats();
+        let block = Box::new([0u8; 1000]);
+        let allocated = stats();
+        drop(block);
+        let freed = stats();
+
+        // Nothing else should allocate in between, but interrupt handlers
+        // could, so only the difference made by the block itself is checked
+        assert!(allocated.allocated >= before.allocated + 1000);
+        assert!(freed.freed >= allocated.freed + 1000);
+        assert_eq!(freed.size, HEAP_SIZE);
+    }
+
+    #[test_case]
+    fn slab_index_fits_size_and_alignment() {
+        assert_eq!(slab_index(&layout(1, 1)), Some(0));
+        assert_eq!(slab_index(&layout(16, 8)), Some(0));
+        assert_eq!(slab_index(&layout(17, 1)), Some(1));
+        assert_eq!(slab_index(&layout(8, 64)), Some(2));
+        assert_eq!(slab_index(&layout(128, 16)), Some(3));
+        assert_eq!(slab_index(&layout(129, 1)), None);
+        assert_eq!(slab_index(&layout(8, 256)), None);
+    }
+
+    #[test_case]
+    fn allocates_aligned_slab_blocks() {
+        let mut slabs = test_slabs();
+
+        let a = slabs.allocate(layout(24, 8));
+        let b = slabs.allocate(layout(24, 8));
+
+        assert!(!a.is_null() && !b.is_null());
+        assert_ne!(a, b);
+        assert_eq!(a as usize % 32, 0);
+        assert_eq!(b as usize % 32, 0);
+        assert_eq!(slabs.counters.blocks[1].load(Ordering::Relaxed), 2);
+        assert_eq!(slabs.counters.free_blocks[1].load(Ordering::Relaxed), 0);
+    }
+
+    #[test_case]
+    fn reuses_freed_blocks() {
+        let mut slabs = test_slabs();
+
+        let a = slabs.allocate(layout(64, 8));
+        // SAFETY: the block was just allocated with this layout
+        unsafe { slabs.deallocate(a, layout(64, 8)) };
+        assert_eq!(slabs.counters.free_blocks[2].load(Ordering::Relaxed), 1);
+
+        // Any layout of the same slab gets the freed block back
+        let b = slabs.allocate(layout(40, 4));
+        assert_eq!(a, b);
+        assert_eq!(slabs.counters.blocks[2].load(Ordering::Relaxed), 1);
+        assert_eq!(slabs.counters.free_blocks[2].load(Ordering::Relaxed), 0);
+    }
+
+    #[test_case]
+    fn large_allocations_use_fallback() {
+        let mut slabs = test_slabs();
+
+        let a = slabs.allocate(layout(1024, 8));
+        assert!(!a.is_null());
+        assert!(
+            slabs
+                .counters
+                .blocks
+                .iter()
+                .all(|b| b.load(Ordering::Relaxed) == 0)
+        );
+
+        // The whole heap doesn't fit while the first allocation is there
+        assert!(slabs.allocate(layout(3584, 8)).is_null());
+
+        // SAFETY: the block was allocated with this layout
+        unsafe { slabs.deallocate(a, layout(1024, 8)) };
+        assert!(!slabs.allocate(layout(3584, 8)).is_null());
+    }
+
+    #[test_case]
+    fn freed_slab_blocks_stay_in_slab() {
+        let mut slabs = test_slabs();
+
+        let blocks = [(); 8].map(|_| slabs.allocate(layout(128, 8)));
+        for block in blocks {
+            // SAFETY: each block was allocated with this layout
+            unsafe { slabs.deallocate(block, layout(128, 8)) };
+        }
+
+        // The free blocks aren't given back, so the fallback has less room
+        assert_eq!(slabs.counters.free_blocks[3].load(Ordering::Relaxed), 8);
+        assert!(slabs.allocate(layout(4096 - 512, 8)).is_null());
+    }
+}
@@ -1,22 +1,52 @@
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+
+use spin::Mutex;
+
+use super::{char::get_char_device, next_device_id};
+use crate::fs::vfs::IoError;
+
 /// Represents an abstract device which can read and write data to/from a store
 /// in fixed size blocks
-pub trait BlockDevice {
+pub trait BlockDevice: Send + Sync {
     fn metadata(&self) -> BlockDeviceMetadata;
 
+    /// Reads whole blocks starting at the given byte offset into the buffer.
+    /// The offset and the length of the buffer must be multiples of the block
+    /// size.
     fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, BlockDeviceIoError> {
         Err(BlockDeviceIoError::OperationNotSupported)
     }
 
+    /// Writes whole blocks from the buffer starting at the given byte offset.
+    /// The offset and the length of the buffer must be multiples of the block
+    /// size.
     fn write(&self, offset: usize, buf: &[u8]) -> Result<usize, BlockDeviceIoError> {
         Err(BlockDeviceIoError::OperationNotSupported)
     }
+
+    /// Writes anything the device holds back from its storage, like modified
+    /// blocks in a cache. Devices which write every request immediately have
+    /// nothing to do.
+    fn flush(&self) -> Result<(), BlockDeviceIoError> {
+        Ok(())
+    }
 }
 
 pub struct BlockDeviceMetadata {
+    /// The name which identifies the device in the registry and in `/dev`
+    pub name: &'static str,
     pub block_size: usize,
     pub total_blocks: usize,
 }
 
+impl BlockDeviceMetadata {
+    /// The total number of bytes which can be stored on the device
+    pub fn capacity(&self) -> usize {
+        self.block_size * self.total_blocks
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BlockDeviceIoError {
     /// Returned if this operation is not supported on this device
     OperationNotSupported,
@@ -26,4 +56,84 @@ pub enum BlockDeviceIoError {
     OffsetOutOfBounds,
     /// The provided buffer was not a multiple of the block size
     MismatchedBlockSize,
+    /// The hardware reported an error or stopped responding while carrying out
+    /// the request
+    HardwareError,
+}
+
+impl From<BlockDeviceIoError> for IoError {
+    fn from(value: BlockDeviceIoError) -> Self {
+        match value {
+            BlockDeviceIoError::OperationNotSupported => IoError::OperationNotSupported,
+            BlockDeviceIoError::HardwareError => IoError::DeviceError,
+            // Callers going through the VFS only ever issue whole block
+            // requests within the device, so anything else means the device
+            // itself is misbehaving
+            BlockDeviceIoError::UnalignedOffset
+            | BlockDeviceIoError::OffsetOutOfBounds
+            | BlockDeviceIoError::MismatchedBlockSize => IoError::DeviceError,
+        }
+    }
+}
+
+/// A device in the registry along with the ID it was assigned
+struct RegisteredBlockDevice {
+    id: u64,
+    device: Arc<dyn BlockDevice>,
+}
+
+lazy_static::lazy_static! {
+    // Maps device names to implementations
+    static ref BLOCK_DEVICE_REGISTRY: Mutex<BTreeMap<&'static str, RegisteredBlockDevice>>
+        = Default::default();
+}
+
+#[derive(Debug)]
+pub enum BlockDeviceRegistrationError {
+    NameConflict,
+}
+
+pub fn register_block_device(
+    b_dev: Arc<dyn BlockDevice>,
+) -> Result<(), BlockDeviceRegistrationError> {
+    let name = b_dev.metadata().name;
+
+    // Char and block devices share the same namespace in /dev
+    if get_char_device(name).is_some() {
+        return Err(BlockDeviceRegistrationError::NameConflict);
+    }
+
+    let mut registry = BLOCK_DEVICE_REGISTRY.lock();
+
+    // Make sure no other devices are registered under this name
+    if registry.contains_key(name) {
+        return Err(BlockDeviceRegistrationError::NameConflict);
+    }
+
+    let id = next_device_id();
+
+    registry.insert(name, RegisteredBlockDevice { id, device: b_dev });
+
+    Ok(())
+}
+
+pub fn list_block_devices() -> Vec<Arc<dyn BlockDevice>> {
+    BLOCK_DEVICE_REGISTRY
+        .lock()
+        .values()
+        .map(|r| r.device.clone())
+        .collect()
+}
+
+pub fn get_block_device(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    BLOCK_DEVICE_REGISTRY
+        .lock()
+        .get(name)
+        .map(|r| r.device.clone())
+}
+
+/// Returns the globally unique ID which was assigned to the block device with
+/// the given name when it was registered
+pub fn device_id(name: &str) -> Option<u64> {
+    BLOCK_DEVICE_REGISTRY.lock().get(name).map(|r| r.id)
 }
@@ -0,0 +1,397 @@
+//! A cache of recently used blocks which sits in front of a block device so
+//! that file system drivers don't have to go to the hardware for every access
+
+use alloc::{boxed::Box, collections::btree_map::BTreeMap, sync::Arc, vec};
+
+use spin::Mutex;
+
+use super::block::{BlockDevice, BlockDeviceIoError, BlockDeviceMetadata};
+
+/// Controls when writes to the cache reach the underlying device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Every write goes to the device immediately and the cache only speeds up
+    /// reads
+    WriteThrough,
+    /// Writes only update the cache. Modified blocks are written to the device
+    /// when they are evicted or the cache is flushed.
+    WriteBack,
+}
+
+struct CachedBlock {
+    data: Box<[u8]>,
+    /// Whether the block was modified since it was read from the device
+    dirty: bool,
+    /// The value of the access counter the last time this block was used
+    last_used: u64,
+}
+
+struct CacheState {
+    /// Maps block numbers to their cached contents
+    blocks: BTreeMap<usize, CachedBlock>,
+    /// Incremented on every access to order the blocks by how recently they
+    /// were used
+    access_counter: u64,
+}
+
+/// Caches up to a fixed number of blocks of a device in memory, evicting the
+/// least recently used block once it is full. The cache is a [`BlockDevice`]
+/// itself, so it can be used anywhere the device it wraps can.
+pub struct BufferCache {
+    device: Arc<dyn BlockDevice>,
+    block_size: usize,
+    capacity: usize,
+    policy: WritePolicy,
+    state: Mutex<CacheState>,
+}
+
+impl BufferCache {
+    /// Creates a cache which holds up to `capacity` blocks of the device
+    pub fn new(device: Arc<dyn BlockDevice>, capacity: usize, policy: WritePolicy) -> Self {
+        assert!(capacity > 0, "buffer cache capacity must not be 0");
+
+        Self {
+            block_size: device.metadata().block_size,
+            device,
+            capacity,
+            policy,
+            state: Mutex::new(CacheState {
+                blocks: BTreeMap::new(),
+                access_counter: 0,
+            }),
+        }
+    }
+
+    /// Writes every modified block back to the device. The blocks stay cached.
+    pub fn flush_all(&self) -> Result<(), BlockDeviceIoError> {
+        let mut state = self.state.lock();
+
+        for (block, cached) in state.blocks.iter_mut().filter(|(_, c)| c.dirty) {
+            self.device.write(block * self.block_size, &cached.data)?;
+            cached.dirty = false;
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure that a request covers whole blocks which are all on the
+    /// device and returns the number of the first block it refers to
+    fn check_request(&self, offset: usize, length: usize) -> Result<usize, BlockDeviceIoError> {
+        if offset % self.block_size != 0 {
+            return Err(BlockDeviceIoError::UnalignedOffset);
+        }
+
+        if length % self.block_size != 0 {
+            return Err(BlockDeviceIoError::MismatchedBlockSize);
+        }
+
+        offset
+            .checked_add(length)
+            .filter(|end| *end <= self.device.metadata().capacity())
+            .ok_or(BlockDeviceIoError::OffsetOutOfBounds)?;
+
+        Ok(offset / self.block_size)
+    }
+
+    /// Returns the cached copy of the block, reading it from the device first
+    /// if it isn't cached yet
+    fn get_block<'a>(
+        &self,
+        state: &'a mut CacheState,
+        block: usize,
+    ) -> Result<&'a mut CachedBlock, BlockDeviceIoError> {
+        state.access_counter += 1;
+        let now = state.access_counter;
+
+        if !state.blocks.contains_key(&block) {
+            let mut data = vec![0; self.block_size].into_boxed_slice();
+            self.device.read(block * self.block_size, &mut data)?;
+
+            self.insert_block(state, block, data, false)?;
+        }
+
+        let cached = state.blocks.get_mut(&block).unwrap();
+        cached.last_used = now;
+
+        Ok(cached)
+    }
+
+    /// Adds a block to the cache, evicting the least recently used block if
+    /// the cache is full
+    fn insert_block(
+        &self,
+        state: &mut CacheState,
+        block: usize,
+        data: Box<[u8]>,
+        dirty: bool,
+    ) -> Result<(), BlockDeviceIoError> {
+        if state.blocks.len() >= self.capacity && !state.blocks.contains_key(&block) {
+            self.evict_least_recently_used(state)?;
+        }
+
+        state.blocks.insert(
+            block,
+            CachedBlock {
+                data,
+                dirty,
+                last_used: state.access_counter,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn evict_least_recently_used(&self, state: &mut CacheState) -> Result<(), BlockDeviceIoError> {
+        let Some((&block, cached)) = state.blocks.iter().min_by_key(|(_, c)| c.last_used) else {
+            return Ok(());
+        };
+
+        // Make sure modifications aren't lost. The block stays cached if this
+        // fails so that it can be retried later.
+        if cached.dirty {
+            self.device.write(block * self.block_size, &cached.data)?;
+        }
+
+        state.blocks.remove(&block);
+
+        Ok(())
+    }
+}
+
+impl BlockDevice for BufferCache {
+    fn metadata(&self) -> BlockDeviceMetadata {
+        self.device.metadata()
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, BlockDeviceIoError> {
+        let first_block = self.check_request(offset, buf.len())?;
+
+        let mut state = self.state.lock();
+
+        for (block, chunk) in (first_block..).zip(buf.chunks_exact_mut(self.block_size)) {
+            chunk.copy_from_slice(&self.get_block(&mut state, block)?.data);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn write(&self, offset: usize, buf: &[u8]) -> Result<usize, BlockDeviceIoError> {
+        let first_block = self.check_request(offset, buf.len())?;
+
+        if self.policy == WritePolicy::WriteThrough {
+            self.device.write(offset, buf)?;
+        }
+
+        let dirty = self.policy == WritePolicy::WriteBack;
+
+        let mut state = self.state.lock();
+
+        for (block, chunk) in (first_block..).zip(buf.chunks_exact(self.block_size)) {
+            state.access_counter += 1;
+            let now = state.access_counter;
+
+            if let Some(cached) = state.blocks.get_mut(&block) {
+                cached.data.copy_from_slice(chunk);
+                cached.dirty |= dirty;
+                cached.last_used = now;
+            } else {
+                // Whole blocks are always written, so there's no need to read
+                // the old contents from the device first
+                self.insert_block(&mut state, block, chunk.into(), dirty)?;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&self) -> Result<(), BlockDeviceIoError> {
+        self.flush_all()?;
+
+        self.device.flush()
+    }
+}
+
+impl Drop for BufferCache {
+    fn drop(&mut self) {
+        // Errors can't be reported from here, so owners which care about them
+        // should flush the cache themselves before dropping it
+        let _ = self.flush_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    const BLOCK_SIZE: usize = 512;
+    const BLOCKS: usize = 8;
+
+    /// Keeps its blocks in memory and counts the requests which reach it
+    struct CountingDevice {
+        data: Mutex<Vec<u8>>,
+        reads: AtomicUsize,
+        writes: AtomicUsize,
+    }
+
+    impl CountingDevice {
+        fn new() -> Arc<Self> {
+            Arc::new(Self {
+                data: Mutex::new(vec![0; BLOCK_SIZE * BLOCKS]),
+                reads: AtomicUsize::new(0),
+                writes: AtomicUsize::new(0),
+            })
+        }
+
+        fn reads(&self) -> usize {
+            self.reads.load(Ordering::Relaxed)
+        }
+
+        fn writes(&self) -> usize {
+            self.writes.load(Ordering::Relaxed)
+        }
+
+        fn block(&self, block: usize) -> Vec<u8> {
+            self.data.lock()[block * BLOCK_SIZE..][..BLOCK_SIZE].to_vec()
+        }
+    }
+
+    impl BlockDevice for CountingDevice {
+        fn metadata(&self) -> BlockDeviceMetadata {
+            BlockDeviceMetadata {
+                name: "counting",
+                block_size: BLOCK_SIZE,
+                total_blocks: BLOCKS,
+            }
+        }
+
+        fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, BlockDeviceIoError> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            buf.copy_from_slice(&self.data.lock()[offset..][..buf.len()]);
+
+            Ok(buf.len())
+        }
+
+        fn write(&self, offset: usize, buf: &[u8]) -> Result<usize, BlockDeviceIoError> {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            self.data.lock()[offset..][..buf.len()].copy_from_slice(buf);
+
+            Ok(buf.len())
+        }
+    }
+
+    #[test_case]
+    fn second_read_hits_the_cache() {
+        let device = CountingDevice::new();
+        device.data.lock()[BLOCK_SIZE] = 0xAB;
+
+        let cache = BufferCache::new(device.clone(), 4, WritePolicy::WriteThrough);
+        let mut buf = [0; BLOCK_SIZE];
+
+        cache.read(BLOCK_SIZE, &mut buf).unwrap();
+        assert_eq!(device.reads(), 1);
+        assert_eq!(buf[0], 0xAB);
+
+        buf.fill(0);
+        cache.read(BLOCK_SIZE, &mut buf).unwrap();
+        assert_eq!(device.reads(), 1);
+        assert_eq!(buf[0], 0xAB);
+
+        // A block which isn't cached yet is a miss
+        cache.read(2 * BLOCK_SIZE, &mut buf).unwrap();
+        assert_eq!(device.reads(), 2);
+    }
+
+    #[test_case]
+    fn evicts_least_recently_used_block() {
+        let device = CountingDevice::new();
+        let cache = BufferCache::new(device.clone(), 2, WritePolicy::WriteThrough);
+        let mut buf = [0; BLOCK_SIZE];
+
+        cache.read(0, &mut buf).unwrap();
+        cache.read(BLOCK_SIZE, &mut buf).unwrap();
+        // Makes block 1 the least recently used one
+        cache.read(0, &mut buf).unwrap();
+        cache.read(2 * BLOCK_SIZE, &mut buf).unwrap();
+        assert_eq!(device.reads(), 3);
+
+        cache.read(0, &mut buf).unwrap();
+        assert_eq!(device.reads(), 3);
+
+        cache.read(BLOCK_SIZE, &mut buf).unwrap();
+        assert_eq!(device.reads(), 4);
+    }
+
+    #[test_case]
+    fn write_through_reaches_device() {
+        let device = CountingDevice::new();
+        let cache = BufferCache::new(device.clone(), 4, WritePolicy::WriteThrough);
+
+        cache.write(0, &[7; BLOCK_SIZE]).unwrap();
+        assert_eq!(device.writes(), 1);
+        assert_eq!(device.block(0), [7; BLOCK_SIZE]);
+
+        // The written block is cached as well
+        let mut buf = [0; BLOCK_SIZE];
+        cache.read(0, &mut buf).unwrap();
+        assert_eq!(device.reads(), 0);
+        assert_eq!(buf, [7; BLOCK_SIZE]);
+    }
+
+    #[test_case]
+    fn write_back_waits_for_flush() {
+        let device = CountingDevice::new();
+        let cache = BufferCache::new(device.clone(), 4, WritePolicy::WriteBack);
+
+        cache.write(0, &[1; 2 * BLOCK_SIZE]).unwrap();
+        assert_eq!(device.writes(), 0);
+        assert_eq!(device.block(0), [0; BLOCK_SIZE]);
+
+        cache.flush().unwrap();
+        assert_eq!(device.writes(), 2);
+        assert_eq!(device.block(0), [1; BLOCK_SIZE]);
+        assert_eq!(device.block(1), [1; BLOCK_SIZE]);
+
+        // Clean blocks aren't written again
+        cache.flush().unwrap();
+        assert_eq!(device.writes(), 2);
+    }
+
+    #[test_case]
+    fn write_back_writes_evicted_blocks() {
+        let device = CountingDevice::new();
+        let cache = BufferCache::new(device.clone(), 1, WritePolicy::WriteBack);
+
+        cache.write(0, &[3; BLOCK_SIZE]).unwrap();
+        cache.write(BLOCK_SIZE, &[4; BLOCK_SIZE]).unwrap();
+
+        assert_eq!(device.writes(), 1);
+        assert_eq!(device.block(0), [3; BLOCK_SIZE]);
+        assert_eq!(device.block(1), [0; BLOCK_SIZE]);
+
+        // Dropping the cache flushes what is left
+        drop(cache);
+        assert_eq!(device.block(1), [4; BLOCK_SIZE]);
+    }
+
+    #[test_case]
+    fn rejects_partial_blocks() {
+        let cache = BufferCache::new(CountingDevice::new(), 4, WritePolicy::WriteThrough);
+        let mut buf = [0; BLOCK_SIZE];
+
+        assert_eq!(
+            cache.read(1, &mut buf),
+            Err(BlockDeviceIoError::UnalignedOffset)
+        );
+        assert_eq!(
+            cache.read(0, &mut buf[1..]),
+            Err(BlockDeviceIoError::MismatchedBlockSize)
+        );
+        assert_eq!(
+            cache.read(BLOCK_SIZE * BLOCKS, &mut buf),
+            Err(BlockDeviceIoError::OffsetOutOfBounds)
+        );
+    }
+}
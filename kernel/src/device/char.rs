@@ -2,6 +2,7 @@ use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
 
 use spin::Mutex;
 
+use super::{block::get_block_device, next_device_id};
 use crate::fs::FileOperations;
 
 pub trait CharDevice: Send + Sync {
@@ -13,9 +14,15 @@ pub struct CharacterDeviceMetadata {
     pub name: &'static str,
 }
 
+/// A device in the registry along with the ID it was assigned
+struct RegisteredCharDevice {
+    id: u64,
+    device: Arc<dyn CharDevice>,
+}
+
 lazy_static::lazy_static! {
-    // Maps file systems from names to implementations
-    static ref CHAR_DEVICE_REGISTRY: Mutex<BTreeMap<&'static str, Arc<dyn CharDevice>>>
+    // Maps device names to implementations
+    static ref CHAR_DEVICE_REGISTRY: Mutex<BTreeMap<&'static str, RegisteredCharDevice>>
         = Default::default();
 }
 
@@ -25,24 +32,45 @@ pub enum CharDeviceRegistrationError {
 }
 
 pub fn register_char_device(c_dev: Arc<dyn CharDevice>) -> Result<(), CharDeviceRegistrationError> {
-    let mut registry = CHAR_DEVICE_REGISTRY.lock();
-
     let name = c_dev.metadata().name;
 
+    // Char and block devices share the same namespace in /dev
+    if get_block_device(name).is_some() {
+        return Err(CharDeviceRegistrationError::NameConflict);
+    }
+
+    let mut registry = CHAR_DEVICE_REGISTRY.lock();
+
     // Make sure no other devices are registered under this name
     if registry.contains_key(name) {
         return Err(CharDeviceRegistrationError::NameConflict);
     }
 
-    registry.insert(name, c_dev);
+    let id = next_device_id();
+
+    registry.insert(name, RegisteredCharDevice { id, device: c_dev });
 
     Ok(())
 }
 
 pub fn list_char_devices() -> Vec<Arc<dyn CharDevice>> {
-    CHAR_DEVICE_REGISTRY.lock().values().cloned().collect()
+    CHAR_DEVICE_REGISTRY
+        .lock()
+        .values()
+        .map(|r| r.device.clone())
+        .collect()
 }
 
 pub fn get_char_device(name: &str) -> Option<Arc<dyn CharDevice>> {
-    CHAR_DEVICE_REGISTRY.lock().get(name).cloned()
+    CHAR_DEVICE_REGISTRY
+        .lock()
+        .get(name)
+        .map(|r| r.device.clone())
+}
+
+/// Returns the globally unique ID which was assigned to the device with the
+/// given name when it was registered. The ID stays the same for as long as the
+/// device is registered.
+pub fn device_id(name: &str) -> Option<u64> {
+    CHAR_DEVICE_REGISTRY.lock().get(name).map(|r| r.id)
 }
@@ -1,2 +1,14 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
 pub mod block;
+pub mod block_cache;
 pub mod char;
+
+/// Hands out the ID for a newly registered device. IDs are unique across all
+/// kinds of devices and are never reused so that they stay unique even if
+/// devices are removed in the future. 0 is left unused.
+fn next_device_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
@@ -1,42 +1,605 @@
 //! Floppy Disk Driver
+//!
+//! Drives 3.5" 1.44 MB disks through the 82077AA compatible floppy disk
+//! controller (FDC) found in PCs and emulated by QEMU and Bochs. Data is moved
+//! between the controller and memory by channel 2 of the ISA DMA controller,
+//! and the FDC raises IRQ 6 whenever a command finishes.
+//!
+//! Each sector is transferred with the following sequence:
+//!
+//! 1. Select the drive and turn its motor on through the Digital Output
+//!    Register, then wait for the motor to spin up.
+//! 2. Send SEEK with the cylinder of the sector, wait for IRQ 6 and send SENSE
+//!    INTERRUPT to confirm the head reached the cylinder.
+//! 3. Program DMA channel 2 with the physical address and length of the
+//!    transfer buffer and the direction of the transfer.
+//! 4. Send READ DATA or WRITE DATA with the cylinder, head and sector, wait
+//!    for IRQ 6 and read the 7 result bytes to check for errors.
+//!
+//! Failed transfers are retried after recalibrating the drive. The caller is
+//! blocked until the controller raises its interrupt, so all operations must
+//! be started with interrupts enabled.
 
-use crate::device::block::{BlockDevice, BlockDeviceIoError, BlockDeviceMetadata};
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use spin::Mutex;
+use x86_64::{VirtAddr, instructions::port::Port, structures::paging::Translate};
+
+use crate::{
+    device::block::{BlockDevice, BlockDeviceIoError, BlockDeviceMetadata},
+    memory,
+    util::time,
+};
+
+/// The floppy controller supports up to 4 drives
+const DRIVE_NAMES: [&str; 4] = ["fd0", "fd1", "fd2", "fd3"];
+
+/* Geometry of a 1.44 MB disk */
+
+const SECTOR_SIZE: usize = 512;
+const SECTORS_PER_TRACK: usize = 18;
+const HEADS: usize = 2;
+const CYLINDERS: usize = 80;
+const TOTAL_SECTORS: usize = SECTORS_PER_TRACK * HEADS * CYLINDERS;
+
+/// Sector sizes are encoded as `128 << n`, so 2 selects 512 byte sectors
+const SECTOR_SIZE_CODE: u8 = 2;
+/// The gap between sectors used by read and write commands on 3.5" disks
+const GAP_3_LENGTH: u8 = 0x1B;
+
+/* Floppy disk controller registers */
+
+const DIGITAL_OUTPUT_REGISTER: u16 = 0x3F2;
+const MAIN_STATUS_REGISTER: u16 = 0x3F4;
+const DATA_FIFO: u16 = 0x3F5;
+const CONFIGURATION_CONTROL_REGISTER: u16 = 0x3F7;
+
+/// Takes the controller out of reset when set and resets it when cleared
+const DOR_NOT_RESET: u8 = 1 << 2;
+/// Enables IRQ 6 and DMA requests from the controller
+const DOR_IRQ_DMA: u8 = 1 << 3;
+
+/// Set when the controller is ready to exchange a byte through the FIFO
+const MSR_REQUEST_FOR_MASTER: u8 = 1 << 7;
+/// Set when the next FIFO byte flows from the controller to the CPU
+const MSR_DATA_INPUT_OUTPUT: u8 = 1 << 6;
+
+/// Selects a data rate of 500 kbps, which is what 1.44 MB disks use
+const DATA_RATE_500_KBPS: u8 = 0;
+
+const COMMAND_SPECIFY: u8 = 0x03;
+const COMMAND_WRITE_DATA: u8 = 0x05;
+const COMMAND_READ_DATA: u8 = 0x06;
+const COMMAND_RECALIBRATE: u8 = 0x07;
+const COMMAND_SENSE_INTERRUPT: u8 = 0x08;
+const COMMAND_SEEK: u8 = 0x0F;
+
+/// Continue onto the other head once the end of a track is reached
+const COMMAND_FLAG_MULTI_TRACK: u8 = 0x80;
+/// Use MFM encoding, which every disk since the 5.25" double density uses
+const COMMAND_FLAG_MFM: u8 = 0x40;
+
+/// The first SPECIFY parameter: a step rate time of 3ms (upper nibble) and a
+/// head unload time of 240ms (lower nibble)
+const SPECIFY_STEP_RATE_HEAD_UNLOAD: u8 = 0xDF;
+/// The second SPECIFY parameter: a head load time of 16ms (upper 7 bits) and
+/// DMA mode (bit 0 clear)
+const SPECIFY_HEAD_LOAD_DMA: u8 = 0x02;
+
+/// The interrupt code in the upper two bits of ST0 is zero when a command
+/// completed normally
+const ST0_INTERRUPT_CODE: u8 = 0b1100_0000;
+/// Set in ST0 once a SEEK or RECALIBRATE command has finished
+const ST0_SEEK_END: u8 = 1 << 5;
+
+/* ISA DMA controller registers for channel 2 */
+
+const DMA_CHANNEL: u8 = 2;
+const DMA_CHANNEL_2_ADDRESS: u16 = 0x04;
+const DMA_CHANNEL_2_COUNT: u16 = 0x05;
+const DMA_CHANNEL_2_PAGE: u16 = 0x81;
+const DMA_SINGLE_CHANNEL_MASK: u16 = 0x0A;
+const DMA_MODE: u16 = 0x0B;
+const DMA_FLIP_FLOP_RESET: u16 = 0x0C;
+
+/// Masks the channel written alongside it in the mask register
+const DMA_MASK_ON: u8 = 1 << 2;
+/// Single transfer mode with an incrementing address and no auto-init
+const DMA_MODE_SINGLE: u8 = 0x40;
+/// The device writes to memory, used when reading from the disk
+const DMA_MODE_WRITE_TO_MEMORY: u8 = 0x04;
+/// The device reads from memory, used when writing to the disk
+const DMA_MODE_READ_FROM_MEMORY: u8 = 0x08;
+
+/// ISA DMA can only address the first 16 MiB of physical memory
+const DMA_ADDRESS_LIMIT: u64 = 0x100_0000;
+
+/* Timing */
+
+/// How long a drive takes to spin up to a stable speed after its motor is
+/// turned on
+const MOTOR_SPIN_UP_DELAY: Duration = Duration::from_millis(300);
+/// How long to wait for a command to raise IRQ 6 before giving up
+const INTERRUPT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How many times to poll the main status register before giving up on a FIFO
+/// transfer
+const FIFO_POLL_ATTEMPTS: usize = 10_000;
+/// How many times a transfer is attempted before reporting an error
+const TRANSFER_ATTEMPTS: usize = 3;
+
+/// Set by the interrupt handler once the controller raises IRQ 6
+static INTERRUPT_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+/// Called by the IRQ 6 interrupt handler. Must not block or allocate.
+pub(crate) fn handle_interrupt() {
+    INTERRUPT_RECEIVED.store(true, Ordering::Release);
+}
+
+/// The buffer the DMA controller transfers sectors to and from. Aligning it to
+/// its size guarantees that it never crosses a 64 KiB boundary, which ISA DMA
+/// can't do.
+#[repr(C, align(512))]
+struct DmaBuffer([u8; SECTOR_SIZE]);
+
+/// All drives share a single controller and DMA channel, so only one transfer
+/// can happen at a time. Holding this lock grants exclusive access to both.
+static CONTROLLER: Mutex<DmaBuffer> = Mutex::new(DmaBuffer([0; SECTOR_SIZE]));
+
+/// The direction of a transfer between the disk and memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Read,
+    Write,
+}
+
+/// Reads the drive types the BIOS stored in CMOS register 0x10 and returns the
+/// IDs of the drives which hold 1.44 MB disks. Only the first two drives are
+/// described there.
+pub fn detect_drives() -> impl Iterator<Item = u8> {
+    /// The CMOS drive type of a 3.5" 1.44 MB drive
+    const DRIVE_TYPE_1_44_MB: u8 = 4;
+
+    let mut select_port = Port::<u8>::new(0x70);
+    let mut data_port = Port::<u8>::new(0x71);
+
+    let drive_types = x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        select_port.write(0x10);
+        data_port.read()
+    });
+
+    // Drive 0 is described by the upper nibble and drive 1 by the lower one
+    [drive_types >> 4, drive_types & 0x0F]
+        .into_iter()
+        .zip(0..)
+        .filter(|(drive_type, _)| *drive_type == DRIVE_TYPE_1_44_MB)
+        .map(|(_, drive_id)| drive_id)
+}
+
+/// Keeps the motor of a drive running until it is dropped
+struct MotorGuard {
+    drive_id: u8,
+}
+
+impl Drop for MotorGuard {
+    fn drop(&mut self) {
+        write_digital_output(self.drive_id | DOR_NOT_RESET | DOR_IRQ_DMA);
+    }
+}
 
 pub struct FloppyDisk {
     drive_id: u8,
-    direction: bool,
-    step_index: u8,
 }
 
 impl FloppyDisk {
     /// Callers must ensure that only one instance of this driver exists for
     /// each drive ID
     pub unsafe fn new(drive_id: u8) -> Self {
-        Self {
-            drive_id,
-            direction: false,
-            step_index: 0,
-        }
+        assert!(
+            (drive_id as usize) < DRIVE_NAMES.len(),
+            "invalid floppy drive ID"
+        );
+
+        Self { drive_id }
     }
 
     /// Resets the floppy disk to a known state. Should be called after
     /// instantiation.
-    pub fn reset(&mut self) {}
+    ///
+    /// This resets the whole controller, so transfers on other drives must not
+    /// be in progress.
+    pub fn reset(&mut self) -> Result<(), BlockDeviceIoError> {
+        let _controller = CONTROLLER.lock();
+
+        // Pulse the reset bit. The controller raises an interrupt once it
+        // comes out of reset.
+        INTERRUPT_RECEIVED.store(false, Ordering::Release);
+        write_digital_output(0);
+        write_digital_output(DOR_NOT_RESET | DOR_IRQ_DMA);
+        wait_for_interrupt()?;
+
+        // The controller expects a SENSE INTERRUPT for each of the 4 drives it
+        // polled after the reset
+        for _ in 0..DRIVE_NAMES.len() {
+            sense_interrupt()?;
+        }
+
+        unsafe {
+            Port::<u8>::new(CONFIGURATION_CONTROL_REGISTER).write(DATA_RATE_500_KBPS);
+        }
+
+        send_command(&[
+            COMMAND_SPECIFY,
+            SPECIFY_STEP_RATE_HEAD_UNLOAD,
+            SPECIFY_HEAD_LOAD_DMA,
+        ])?;
+
+        let _motor = self.motor_on();
+
+        self.recalibrate()
+    }
+
+    /// Turns the motor of the drive on and waits for it to spin up. The motor
+    /// is turned off again once the returned guard is dropped.
+    fn motor_on(&self) -> MotorGuard {
+        // The lower two bits select the drive and each drive's motor is enabled
+        // by one of the upper four bits
+        write_digital_output(
+            self.drive_id | DOR_NOT_RESET | DOR_IRQ_DMA | (1 << (4 + self.drive_id)),
+        );
+        delay(MOTOR_SPIN_UP_DELAY);
+
+        MotorGuard {
+            drive_id: self.drive_id,
+        }
+    }
+
+    /// Moves the head of the drive back to cylinder 0. This also recovers the
+    /// drive after it lost track of which cylinder its head is on.
+    fn recalibrate(&self) -> Result<(), BlockDeviceIoError> {
+        INTERRUPT_RECEIVED.store(false, Ordering::Release);
+        send_command(&[COMMAND_RECALIBRATE, self.drive_id])?;
+        wait_for_interrupt()?;
+
+        let (st0, cylinder) = sense_interrupt()?;
+
+        if st0 & ST0_SEEK_END == 0 || cylinder != 0 {
+            return Err(BlockDeviceIoError::HardwareError);
+        }
+
+        Ok(())
+    }
+
+    /// Moves the head of the drive to the given cylinder
+    fn seek(&self, cylinder: u8, head: u8) -> Result<(), BlockDeviceIoError> {
+        INTERRUPT_RECEIVED.store(false, Ordering::Release);
+        send_command(&[COMMAND_SEEK, (head << 2) | self.drive_id, cylinder])?;
+        wait_for_interrupt()?;
+
+        let (st0, current_cylinder) = sense_interrupt()?;
+
+        if st0 & ST0_SEEK_END == 0 || current_cylinder != cylinder {
+            return Err(BlockDeviceIoError::HardwareError);
+        }
+
+        Ok(())
+    }
+
+    /// Transfers a single sector between the disk and the DMA buffer
+    fn transfer_sector(
+        &self,
+        buffer: &mut DmaBuffer,
+        lba: usize,
+        direction: Direction,
+    ) -> Result<(), BlockDeviceIoError> {
+        let (cylinder, head, sector) = lba_to_chs(lba);
+
+        self.seek(cylinder, head)?;
+
+        let (mode, command) = match direction {
+            Direction::Read => (DMA_MODE_WRITE_TO_MEMORY, COMMAND_READ_DATA),
+            Direction::Write => (DMA_MODE_READ_FROM_MEMORY, COMMAND_WRITE_DATA),
+        };
+
+        setup_dma(buffer, mode)?;
+
+        INTERRUPT_RECEIVED.store(false, Ordering::Release);
+        send_command(&[
+            command | COMMAND_FLAG_MULTI_TRACK | COMMAND_FLAG_MFM,
+            (head << 2) | self.drive_id,
+            cylinder,
+            head,
+            sector,
+            SECTOR_SIZE_CODE,
+            // The last sector number on the track
+            SECTORS_PER_TRACK as u8,
+            GAP_3_LENGTH,
+            // The data length is unused since the sector size is not 0
+            0xFF,
+        ])?;
+        wait_for_interrupt()?;
+
+        // ST0, ST1, ST2, then the cylinder, head, sector and size the
+        // controller stopped at
+        let mut result = [0; 7];
+        for byte in &mut result {
+            *byte = read_fifo()?;
+        }
+
+        let [st0, st1, st2, ..] = result;
+
+        if st0 & ST0_INTERRUPT_CODE != 0 || st1 != 0 || st2 != 0 {
+            return Err(BlockDeviceIoError::HardwareError);
+        }
+
+        Ok(())
+    }
+
+    /// Transfers a sector, recalibrating the drive and trying again if it
+    /// fails
+    fn transfer_sector_with_retries(
+        &self,
+        buffer: &mut DmaBuffer,
+        lba: usize,
+        direction: Direction,
+    ) -> Result<(), BlockDeviceIoError> {
+        let mut result = Ok(());
+
+        for _ in 0..TRANSFER_ATTEMPTS {
+            result = self.transfer_sector(buffer, lba, direction);
+
+            if result.is_ok() {
+                break;
+            }
+
+            // A failed recalibration will show up in the next attempt
+            let _ = self.recalibrate();
+        }
+
+        result
+    }
 }
 
 impl BlockDevice for FloppyDisk {
     fn metadata(&self) -> BlockDeviceMetadata {
         BlockDeviceMetadata {
-            block_size: 512,
-            total_blocks: 2880,
+            name: DRIVE_NAMES[self.drive_id as usize],
+            block_size: SECTOR_SIZE,
+            total_blocks: TOTAL_SECTORS,
         }
     }
 
     fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, BlockDeviceIoError> {
-        todo!()
+        let sectors = check_request(offset, buf.len())?;
+
+        let mut controller = CONTROLLER.lock();
+        let _motor = self.motor_on();
+
+        for (lba, chunk) in sectors.zip(buf.chunks_exact_mut(SECTOR_SIZE)) {
+            self.transfer_sector_with_retries(&mut controller, lba, Direction::Read)?;
+
+            chunk.copy_from_slice(&controller.0);
+        }
+
+        Ok(buf.len())
     }
 
     fn write(&self, offset: usize, buf: &[u8]) -> Result<usize, BlockDeviceIoError> {
-        todo!()
+        let sectors = check_request(offset, buf.len())?;
+
+        let mut controller = CONTROLLER.lock();
+        let _motor = self.motor_on();
+
+        for (lba, chunk) in sectors.zip(buf.chunks_exact(SECTOR_SIZE)) {
+            controller.0.copy_from_slice(chunk);
+
+            self.transfer_sector_with_retries(&mut controller, lba, Direction::Write)?;
+        }
+
+        Ok(buf.len())
+    }
+}
+
+/// Makes sure that a request covers whole sectors which are all on the disk and
+/// returns the range of sectors it refers to
+fn check_request(offset: usize, length: usize) -> Result<Range<usize>, BlockDeviceIoError> {
+    if offset % SECTOR_SIZE != 0 {
+        return Err(BlockDeviceIoError::UnalignedOffset);
+    }
+
+    if length % SECTOR_SIZE != 0 {
+        return Err(BlockDeviceIoError::MismatchedBlockSize);
+    }
+
+    let start = offset / SECTOR_SIZE;
+    let end = start + length / SECTOR_SIZE;
+
+    if end > TOTAL_SECTORS {
+        return Err(BlockDeviceIoError::OffsetOutOfBounds);
+    }
+
+    Ok(start..end)
+}
+
+/// Converts a logical sector number into the cylinder, head and sector which
+/// the controller expects. Sectors are numbered from 1 within a track and the
+/// tracks alternate between both sides of the disk.
+fn lba_to_chs(lba: usize) -> (u8, u8, u8) {
+    let cylinder = (lba / (SECTORS_PER_TRACK * HEADS)) as u8;
+    let head = ((lba / SECTORS_PER_TRACK) % HEADS) as u8;
+    let sector = (lba % SECTORS_PER_TRACK + 1) as u8;
+
+    (cylinder, head, sector)
+}
+
+fn write_digital_output(value: u8) {
+    unsafe {
+        Port::<u8>::new(DIGITAL_OUTPUT_REGISTER).write(value);
+    }
+}
+
+/// Waits for the controller to accept a byte and writes it to the FIFO
+fn write_fifo(byte: u8) -> Result<(), BlockDeviceIoError> {
+    let mut status_port = Port::<u8>::new(MAIN_STATUS_REGISTER);
+    let mut fifo_port = Port::<u8>::new(DATA_FIFO);
+
+    for _ in 0..FIFO_POLL_ATTEMPTS {
+        let status = unsafe { status_port.read() };
+
+        if status & (MSR_REQUEST_FOR_MASTER | MSR_DATA_INPUT_OUTPUT) == MSR_REQUEST_FOR_MASTER {
+            unsafe { fifo_port.write(byte) };
+
+            return Ok(());
+        }
+    }
+
+    Err(BlockDeviceIoError::HardwareError)
+}
+
+/// Waits for the controller to provide a byte and reads it from the FIFO
+fn read_fifo() -> Result<u8, BlockDeviceIoError> {
+    let mut status_port = Port::<u8>::new(MAIN_STATUS_REGISTER);
+    let mut fifo_port = Port::<u8>::new(DATA_FIFO);
+
+    for _ in 0..FIFO_POLL_ATTEMPTS {
+        let status = unsafe { status_port.read() };
+
+        if status & (MSR_REQUEST_FOR_MASTER | MSR_DATA_INPUT_OUTPUT)
+            == MSR_REQUEST_FOR_MASTER | MSR_DATA_INPUT_OUTPUT
+        {
+            return Ok(unsafe { fifo_port.read() });
+        }
+    }
+
+    Err(BlockDeviceIoError::HardwareError)
+}
+
+/// Sends a command byte followed by its parameters
+fn send_command(bytes: &[u8]) -> Result<(), BlockDeviceIoError> {
+    bytes.iter().try_for_each(|b| write_fifo(*b))
+}
+
+/// Acknowledges the interrupt raised by a reset, SEEK or RECALIBRATE command
+/// and returns ST0 and the cylinder the head is on
+fn sense_interrupt() -> Result<(u8, u8), BlockDeviceIoError> {
+    send_command(&[COMMAND_SENSE_INTERRUPT])?;
+
+    Ok((read_fifo()?, read_fifo()?))
+}
+
+/// Blocks until the controller raises IRQ 6. The flag has to be cleared before
+/// the command which raises the interrupt is sent, otherwise the interrupt
+/// could be missed.
+fn wait_for_interrupt() -> Result<(), BlockDeviceIoError> {
+    let deadline = time::ticks().saturating_add(time::ticks_for(INTERRUPT_TIMEOUT));
+
+    while !INTERRUPT_RECEIVED.swap(false, Ordering::Acquire) {
+        if time::ticks() >= deadline {
+            return Err(BlockDeviceIoError::HardwareError);
+        }
+
+        // The timer interrupt wakes us up at least once per tick, so an IRQ 6
+        // arriving right before halting only delays us until then
+        x86_64::instructions::hlt();
+    }
+
+    Ok(())
+}
+
+/// Blocks for at least the given duration
+fn delay(duration: Duration) {
+    let deadline = time::ticks().saturating_add(time::ticks_for(duration));
+
+    while time::ticks() < deadline {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Programs DMA channel 2 to transfer a sector between the floppy controller
+/// and the buffer in the given direction
+fn setup_dma(buffer: &mut DmaBuffer, mode: u8) -> Result<(), BlockDeviceIoError> {
+    let address = memory::with_mapper(|mapper, _| {
+        mapper.translate_addr(VirtAddr::from_ptr(buffer.0.as_mut_ptr()))
+    })
+    .map(|a| a.as_u64())
+    .filter(|a| a + SECTOR_SIZE as u64 <= DMA_ADDRESS_LIMIT)
+    .ok_or(BlockDeviceIoError::HardwareError)?;
+
+    // The controller transfers one more byte than the count it is given
+    let count = (SECTOR_SIZE - 1) as u16;
+
+    let mut mask_port = Port::<u8>::new(DMA_SINGLE_CHANNEL_MASK);
+    let mut mode_port = Port::<u8>::new(DMA_MODE);
+    let mut flip_flop_port = Port::<u8>::new(DMA_FLIP_FLOP_RESET);
+    let mut address_port = Port::<u8>::new(DMA_CHANNEL_2_ADDRESS);
+    let mut count_port = Port::<u8>::new(DMA_CHANNEL_2_COUNT);
+    let mut page_port = Port::<u8>::new(DMA_CHANNEL_2_PAGE);
+
+    unsafe {
+        // Mask the channel while it is being programmed
+        mask_port.write(DMA_MASK_ON | DMA_CHANNEL);
+
+        // The 16 bit address and count registers are written one byte at a
+        // time, low byte first, starting from a reset flip-flop
+        flip_flop_port.write(0xFF);
+        address_port.write(address as u8);
+        address_port.write((address >> 8) as u8);
+        page_port.write((address >> 16) as u8);
+
+        flip_flop_port.write(0xFF);
+        count_port.write(count as u8);
+        count_port.write((count >> 8) as u8);
+
+        mode_port.write(DMA_MODE_SINGLE | mode | DMA_CHANNEL);
+
+        // Unmask the channel again so that the controller can use it
+        mask_port.write(DMA_CHANNEL);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn maps_sectors_to_chs() {
+        assert_eq!(lba_to_chs(0), (0, 0, 1));
+        assert_eq!(lba_to_chs(17), (0, 0, 18));
+        // The next track is on the other side of the disk
+        assert_eq!(lba_to_chs(18), (0, 1, 1));
+        assert_eq!(lba_to_chs(36), (1, 0, 1));
+        assert_eq!(lba_to_chs(TOTAL_SECTORS - 1), (79, 1, 18));
+    }
+
+    #[test_case]
+    fn checks_requests() {
+        assert_eq!(check_request(0, 512), Ok(0..1));
+        assert_eq!(check_request(1024, 1536), Ok(2..5));
+        assert_eq!(
+            check_request((TOTAL_SECTORS - 1) * 512, 512),
+            Ok(2879..2880)
+        );
+
+        assert_eq!(
+            check_request(100, 512),
+            Err(BlockDeviceIoError::UnalignedOffset)
+        );
+        assert_eq!(
+            check_request(0, 100),
+            Err(BlockDeviceIoError::MismatchedBlockSize)
+        );
+        assert_eq!(
+            check_request(TOTAL_SECTORS * 512, 512),
+            Err(BlockDeviceIoError::OffsetOutOfBounds)
+        );
+        assert_eq!(
+            check_request((TOTAL_SECTORS - 1) * 512, 1024),
+            Err(BlockDeviceIoError::OffsetOutOfBounds)
+        );
     }
 }
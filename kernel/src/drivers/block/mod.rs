@@ -1,2 +1,70 @@
+use alloc::sync::Arc;
+
+use floppy::FloppyDisk;
+use ram::RamDisk;
+
+use crate::{
+    device::{
+        block::{BlockDeviceRegistrationError, list_block_devices, register_block_device},
+        block_cache::{BufferCache, WritePolicy},
+    },
+    vga::println,
+};
+
 pub mod floppy;
+pub mod partition;
 pub mod ram;
+
+/// The size of the ram disk created at boot. It is kept small since it lives
+/// on the kernel heap.
+const RAM_DISK_BLOCKS: usize = 64;
+const RAM_DISK_BLOCK_SIZE: usize = 512;
+
+/// The number of sectors cached for each floppy drive, which is one cylinder
+/// (18 KiB). Every access to the drive takes at least a seek, so even a small
+/// cache saves a lot of time.
+const FLOPPY_CACHE_BLOCKS: usize = 36;
+
+pub fn init() -> Result<(), BlockDeviceRegistrationError> {
+    register_block_device(Arc::new(RamDisk::new(
+        "ram0",
+        RAM_DISK_BLOCK_SIZE,
+        RAM_DISK_BLOCKS,
+    )))?;
+
+    for drive_id in floppy::detect_drives() {
+        // SAFETY: each drive ID is only reported once
+        let mut disk = unsafe { FloppyDisk::new(drive_id) };
+
+        // A drive which can't be reset is left out rather than failing boot
+        if let Err(e) = disk.reset() {
+            println!("Failed to reset floppy drive {drive_id}: {e:?}");
+            continue;
+        }
+
+        // Writes are kept in the cache until the device is flushed, which
+        // devfs does when a file of the device is closed and on sync
+        let cache = BufferCache::new(Arc::new(disk), FLOPPY_CACHE_BLOCKS, WritePolicy::WriteBack);
+
+        register_block_device(Arc::new(cache))?;
+    }
+
+    // Only the disks registered above are scanned, since partitions can't be
+    // partitioned themselves
+    for device in list_block_devices() {
+        let partitions = match partition::read_partitions(&device) {
+            Ok(p) => p,
+            Err(e) => {
+                let name = device.metadata().name;
+                println!("Failed to read the partition table of {name}: {e:?}");
+                continue;
+            }
+        };
+
+        for p in partitions {
+            register_block_device(Arc::new(p))?;
+        }
+    }
+
+    Ok(())
+}
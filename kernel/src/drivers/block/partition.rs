@@ -0,0 +1,231 @@
+//! MBR partition table support. Each primary partition of a disk is exposed as
+//! its own block device which maps onto a range of blocks of the disk.
+
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+
+use crate::device::block::{BlockDevice, BlockDeviceIoError, BlockDeviceMetadata};
+
+/// The MBR fits in the first 512 bytes of the disk
+const MBR_SIZE: usize = 512;
+/// The last two bytes of a valid MBR
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const PARTITION_TABLE_OFFSET: usize = 0x1BE;
+const PARTITION_ENTRY_SIZE: usize = 16;
+const PARTITION_ENTRIES: usize = 4;
+
+/// Partition types which hold an extended partition table instead of data
+const EXTENDED_PARTITION_TYPES: [u8; 3] = [0x05, 0x0F, 0x85];
+
+/// A primary partition of a disk
+pub struct PartitionBlockDevice {
+    name: &'static str,
+    parent: Arc<dyn BlockDevice>,
+    /// The first block of the parent device which belongs to the partition
+    start_block: usize,
+    total_blocks: usize,
+}
+
+impl PartitionBlockDevice {
+    /// Translates a request to the partition into a byte offset on the parent
+    /// device, making sure that it stays within the partition
+    fn parent_offset(&self, offset: usize, length: usize) -> Result<usize, BlockDeviceIoError> {
+        let block_size = self.parent.metadata().block_size;
+
+        offset
+            .checked_add(length)
+            .filter(|end| *end <= self.total_blocks * block_size)
+            .ok_or(BlockDeviceIoError::OffsetOutOfBounds)?;
+
+        Ok(self.start_block * block_size + offset)
+    }
+}
+
+impl BlockDevice for PartitionBlockDevice {
+    fn metadata(&self) -> BlockDeviceMetadata {
+        BlockDeviceMetadata {
+            name: self.name,
+            block_size: self.parent.metadata().block_size,
+            total_blocks: self.total_blocks,
+        }
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, BlockDeviceIoError> {
+        let offset = self.parent_offset(offset, buf.len())?;
+
+        self.parent.read(offset, buf)
+    }
+
+    fn write(&self, offset: usize, buf: &[u8]) -> Result<usize, BlockDeviceIoError> {
+        let offset = self.parent_offset(offset, buf.len())?;
+
+        self.parent.write(offset, buf)
+    }
+
+    fn flush(&self) -> Result<(), BlockDeviceIoError> {
+        self.parent.flush()
+    }
+}
+
+/// Reads the MBR from the first block of the device and returns a device for
+/// each valid primary partition in it. Partitions are named after the device
+/// and their slot in the table, so the first partition of `fd0` is `fd0p1`.
+///
+/// Returns no partitions if the device has no MBR. Extended partitions are
+/// skipped.
+pub fn read_partitions(
+    device: &Arc<dyn BlockDevice>,
+) -> Result<Vec<PartitionBlockDevice>, BlockDeviceIoError> {
+    let metadata = device.metadata();
+
+    // The partition table addresses the disk in 512 byte sectors, which only
+    // line up with the blocks of the device if they are the same size
+    if metadata.block_size != MBR_SIZE {
+        return Ok(Vec::new());
+    }
+
+    let mut mbr = vec![0; MBR_SIZE];
+    device.read(0, &mut mbr)?;
+
+    if mbr[MBR_SIZE - 2..] != MBR_SIGNATURE {
+        return Ok(Vec::new());
+    }
+
+    let entries = mbr[PARTITION_TABLE_OFFSET..]
+        .chunks_exact(PARTITION_ENTRY_SIZE)
+        .take(PARTITION_ENTRIES);
+
+    let mut partitions = Vec::new();
+
+    for (number, entry) in (1..).zip(entries) {
+        // Byte 0 is the boot flag and bytes 1-3 and 5-7 are the CHS addresses
+        // of the first and last sectors, which are superseded by the LBA
+        // fields
+        let partition_type = entry[4];
+        let start_block = u32::from_le_bytes(entry[8..12].try_into().unwrap()) as usize;
+        let total_blocks = u32::from_le_bytes(entry[12..16].try_into().unwrap()) as usize;
+
+        // A type of 0 marks an unused entry
+        if partition_type == 0
+            || total_blocks == 0
+            || EXTENDED_PARTITION_TYPES.contains(&partition_type)
+        {
+            continue;
+        }
+
+        // Ignore partitions which don't fit on the device rather than letting
+        // every access to them fail
+        if start_block + total_blocks > metadata.total_blocks {
+            continue;
+        }
+
+        // Devices live for the lifetime of the kernel, so the name is never
+        // freed
+        let name = String::leak(format!("{}p{number}", metadata.name));
+
+        partitions.push(PartitionBlockDevice {
+            name,
+            parent: device.clone(),
+            start_block,
+            total_blocks,
+        });
+    }
+
+    Ok(partitions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::block::ram::RamDisk;
+
+    /// Creates a disk of 16 blocks with the given partition table entries as
+    /// (slot, type, start block, total blocks)
+    fn disk_with_partitions(entries: &[(usize, u8, u32, u32)]) -> Arc<dyn BlockDevice> {
+        let disk: Arc<dyn BlockDevice> = Arc::new(RamDisk::new("disk", MBR_SIZE, 16));
+
+        let mut mbr = [0u8; MBR_SIZE];
+        for &(slot, partition_type, start, length) in entries {
+            let entry = PARTITION_TABLE_OFFSET + slot * PARTITION_ENTRY_SIZE;
+
+            mbr[entry + 4] = partition_type;
+            mbr[entry + 8..entry + 12].copy_from_slice(&start.to_le_bytes());
+            mbr[entry + 12..entry + 16].copy_from_slice(&length.to_le_bytes());
+        }
+        mbr[MBR_SIZE - 2..].copy_from_slice(&MBR_SIGNATURE);
+
+        disk.write(0, &mbr).unwrap();
+        disk
+    }
+
+    #[test_case]
+    fn finds_primary_partitions() {
+        let disk = disk_with_partitions(&[
+            (0, 0x83, 1, 4),
+            // Extended partitions are skipped
+            (2, 0x05, 5, 2),
+            (3, 0x0C, 8, 8),
+        ]);
+
+        let partitions = read_partitions(&disk).unwrap();
+        let found: Vec<_> = partitions
+            .iter()
+            .map(|p| (p.name, p.start_block, p.total_blocks))
+            .collect();
+
+        assert_eq!(found, [("diskp1", 1, 4), ("diskp4", 8, 8)]);
+    }
+
+    #[test_case]
+    fn skips_partitions_past_the_end() {
+        let disk = disk_with_partitions(&[(0, 0x83, 8, 9), (1, 0x83, 15, 1)]);
+
+        let partitions = read_partitions(&disk).unwrap();
+
+        assert_eq!(partitions.len(), 1);
+        assert_eq!(partitions[0].name, "diskp2");
+    }
+
+    #[test_case]
+    fn ignores_disks_without_signature() {
+        let disk: Arc<dyn BlockDevice> = Arc::new(RamDisk::new("disk", MBR_SIZE, 16));
+
+        assert!(read_partitions(&disk).unwrap().is_empty());
+    }
+
+    #[test_case]
+    fn translates_offsets() {
+        let disk = disk_with_partitions(&[(0, 0x83, 2, 4)]);
+        let partitions = read_partitions(&disk).unwrap();
+        let partition = &partitions[0];
+
+        assert_eq!(partition.metadata().total_blocks, 4);
+
+        // The second block of the partition is the fourth block of the disk
+        partition.write(512, &[0xab; 512]).unwrap();
+        let mut buf = [0u8; 512];
+        disk.read(3 * 512, &mut buf).unwrap();
+        assert_eq!(buf, [0xab; 512]);
+
+        disk.write(5 * 512, &[0xcd; 512]).unwrap();
+        partition.read(3 * 512, &mut buf).unwrap();
+        assert_eq!(buf, [0xcd; 512]);
+    }
+
+    #[test_case]
+    fn rejects_accesses_past_the_partition() {
+        let disk = disk_with_partitions(&[(0, 0x83, 2, 4)]);
+        let partitions = read_partitions(&disk).unwrap();
+        let partition = &partitions[0];
+
+        let mut buf = [0u8; 1024];
+        assert_eq!(
+            partition.read(4 * 512, &mut buf[..512]),
+            Err(BlockDeviceIoError::OffsetOutOfBounds)
+        );
+        // Runs off the end even though the disk continues
+        assert_eq!(
+            partition.write(3 * 512, &buf),
+            Err(BlockDeviceIoError::OffsetOutOfBounds)
+        );
+    }
+}
@@ -1,5 +1,153 @@
 //! Ram disk block device driver. This is the simplest functional block device
-//! implementation as it just reads and writes directory from memory instead of
-//! any physical hardware,
+//! implementation as it just reads and writes directly from memory instead of
+//! any physical hardware.
 
-pub struct RamDisk;
+use alloc::{boxed::Box, vec};
+use core::ops::Range;
+
+use spin::RwLock;
+
+use crate::device::block::{BlockDevice, BlockDeviceIoError, BlockDeviceMetadata};
+
+pub struct RamDisk {
+    name: &'static str,
+    block_size: usize,
+    total_blocks: usize,
+    data: RwLock<Box<[u8]>>,
+}
+
+impl RamDisk {
+    /// Allocates a zeroed disk of `total_blocks * block_size` bytes on the heap
+    pub fn new(name: &'static str, block_size: usize, total_blocks: usize) -> Self {
+        assert!(block_size > 0, "block size must not be 0");
+
+        Self {
+            name,
+            block_size,
+            total_blocks,
+            data: RwLock::new(vec![0; block_size * total_blocks].into_boxed_slice()),
+        }
+    }
+
+    /// Makes sure that a request covers whole blocks which are all on the disk
+    /// and returns the range of bytes it refers to
+    fn check_request(
+        &self,
+        offset: usize,
+        length: usize,
+    ) -> Result<Range<usize>, BlockDeviceIoError> {
+        if offset % self.block_size != 0 {
+            return Err(BlockDeviceIoError::UnalignedOffset);
+        }
+
+        if length % self.block_size != 0 {
+            return Err(BlockDeviceIoError::MismatchedBlockSize);
+        }
+
+        let end = offset
+            .checked_add(length)
+            .filter(|end| *end <= self.block_size * self.total_blocks)
+            .ok_or(BlockDeviceIoError::OffsetOutOfBounds)?;
+
+        Ok(offset..end)
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn metadata(&self) -> BlockDeviceMetadata {
+        BlockDeviceMetadata {
+            name: self.name,
+            block_size: self.block_size,
+            total_blocks: self.total_blocks,
+        }
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, BlockDeviceIoError> {
+        let range = self.check_request(offset, buf.len())?;
+
+        buf.copy_from_slice(&self.data.read()[range]);
+
+        Ok(buf.len())
+    }
+
+    fn write(&self, offset: usize, buf: &[u8]) -> Result<usize, BlockDeviceIoError> {
+        let range = self.check_request(offset, buf.len())?;
+
+        self.data.write()[range].copy_from_slice(buf);
+
+        Ok(buf.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn reads_back_aligned_writes() {
+        let disk = RamDisk::new("test", 512, 4);
+
+        let data = [0xabu8; 1024];
+        assert_eq!(disk.write(512, &data), Ok(1024));
+
+        let mut buf = [0u8; 1536];
+        assert_eq!(disk.read(0, &mut buf), Ok(1536));
+        assert_eq!(buf[..512], [0u8; 512]);
+        assert_eq!(buf[512..], data);
+
+        // The last block is still within the disk
+        assert_eq!(disk.write(1536, &[1; 512]), Ok(512));
+        assert_eq!(disk.read(1536, &mut buf[..512]), Ok(512));
+        assert_eq!(buf[..512], [1u8; 512]);
+    }
+
+    #[test_case]
+    fn rejects_unaligned_offsets() {
+        let disk = RamDisk::new("test", 512, 4);
+        let mut buf = [0u8; 512];
+
+        assert_eq!(
+            disk.read(1, &mut buf),
+            Err(BlockDeviceIoError::UnalignedOffset)
+        );
+        assert_eq!(
+            disk.write(511, &buf),
+            Err(BlockDeviceIoError::UnalignedOffset)
+        );
+    }
+
+    #[test_case]
+    fn rejects_partial_blocks() {
+        let disk = RamDisk::new("test", 512, 4);
+        let mut buf = [0u8; 513];
+
+        assert_eq!(
+            disk.read(0, &mut buf),
+            Err(BlockDeviceIoError::MismatchedBlockSize)
+        );
+        assert_eq!(
+            disk.write(0, &buf[..100]),
+            Err(BlockDeviceIoError::MismatchedBlockSize)
+        );
+    }
+
+    #[test_case]
+    fn rejects_requests_past_the_end() {
+        let disk = RamDisk::new("test", 512, 4);
+        let mut buf = [0u8; 1024];
+
+        assert_eq!(
+            disk.read(2048, &mut buf[..512]),
+            Err(BlockDeviceIoError::OffsetOutOfBounds)
+        );
+        // Starts on the disk but runs off the end
+        assert_eq!(
+            disk.write(1536, &buf),
+            Err(BlockDeviceIoError::OffsetOutOfBounds)
+        );
+        assert_eq!(
+            disk.read(usize::MAX - 511, &mut buf[..512]),
+            Err(BlockDeviceIoError::OffsetOutOfBounds)
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! The console, which reads from the keyboard and writes to the screen
+//!
+//! Reads turn the queued scancodes into the bytes a terminal would send:
+//! characters are encoded as UTF-8 and keys without a character (i.e. the arrow
+//! keys) as ANSI escape sequences. Editing the line is left to whoever reads
+//! the console, so nothing is echoed.
+
+use alloc::string::String;
+
+use pc_keyboard::{DecodedKey, HandleControl, KeyCode, Keyboard, ScancodeSet1, layouts::Us104Key};
+use spin::Mutex;
+
+use crate::{
+    device::char::{CharDevice, CharacterDeviceMetadata},
+    fs::{File, FileOperations, vfs::IoError},
+    shell::keyboard,
+    vga::print,
+};
+
+/// Long enough for the longest encoding of a single key
+type KeyBytes = heapless::Vec<u8, 4>;
+
+struct ConsoleInput {
+    keyboard: Keyboard<Us104Key, ScancodeSet1>,
+    /// Bytes of the last key which didn't fit into the buffer of a read
+    pending: heapless::Deque<u8, 4>,
+}
+
+lazy_static::lazy_static! {
+    static ref INPUT: Mutex<ConsoleInput> = Mutex::new(ConsoleInput {
+        // Control combinations are read as the control characters a terminal
+        // sends for them (i.e. Ctrl-W is 0x17)
+        keyboard: Keyboard::new(
+            ScancodeSet1::new(),
+            Us104Key,
+            HandleControl::MapLettersToUnicode,
+        ),
+        pending: heapless::Deque::new(),
+    });
+}
+
+impl ConsoleInput {
+    /// Decodes the next queued scancode. Returns None once the queue is empty
+    /// and an empty encoding for scancodes which don't complete a key press.
+    fn next_key(&mut self) -> Option<KeyBytes> {
+        let scancode = keyboard::pop_scancode()?;
+
+        let key = self
+            .keyboard
+            .add_byte(scancode)
+            .ok()
+            .flatten()
+            .and_then(|event| self.keyboard.process_keyevent(event));
+
+        let ctrl = self.keyboard.get_modifiers().is_ctrl();
+
+        Some(key.map(|key| encode_key(key, ctrl)).unwrap_or_default())
+    }
+}
+
+/// Encodes a key the same way a terminal would
+fn encode_key(key: DecodedKey, ctrl: bool) -> KeyBytes {
+    let bytes: &[u8] = match key {
+        // Backspace sends DEL so that Ctrl-Backspace can send ^H instead
+        DecodedKey::Unicode('\x08') if !ctrl => b"\x7f",
+        // The keyboard decodes the delete key as DEL, which is already taken
+        DecodedKey::Unicode('\x7f') => b"\x1b[3~",
+        DecodedKey::Unicode(character) => {
+            let mut encoded = [0; 4];
+            let encoded = character.encode_utf8(&mut encoded);
+
+            return KeyBytes::from_slice(encoded.as_bytes()).unwrap();
+        }
+        DecodedKey::RawKey(KeyCode::ArrowUp) => b"\x1b[A",
+        DecodedKey::RawKey(KeyCode::ArrowDown) => b"\x1b[B",
+        DecodedKey::RawKey(KeyCode::ArrowRight) => b"\x1b[C",
+        DecodedKey::RawKey(KeyCode::ArrowLeft) => b"\x1b[D",
+        DecodedKey::RawKey(KeyCode::Home) => b"\x1b[H",
+        DecodedKey::RawKey(KeyCode::End) => b"\x1b[F",
+        // Modifiers and the like have nothing to send
+        DecodedKey::RawKey(_) => b"",
+    };
+
+    KeyBytes::from_slice(bytes).unwrap()
+}
+
+/// Sets up the keyboard input which the console reads from
+pub fn init() {
+    keyboard::init();
+}
+
+/// Throws away input which hasn't been read yet, including the rest of a key
+/// which was partially read
+pub fn discard_input() {
+    keyboard::drain_scancodes();
+    INPUT.lock().pending.clear();
+}
+
+pub struct ConsoleDevice;
+
+impl CharDevice for ConsoleDevice {
+    fn metadata(&self) -> &CharacterDeviceMetadata {
+        &CharacterDeviceMetadata { name: "console" }
+    }
+
+    fn file_operations(&self) -> &dyn FileOperations {
+        self
+    }
+}
+
+impl FileOperations for ConsoleDevice {
+    /// Returns the bytes of the keys pressed so far without waiting for more,
+    /// so a read may return 0 bytes
+    fn read(&self, _file: &File, _offset: usize, buffer: &mut [u8]) -> Result<usize, IoError> {
+        let mut input = INPUT.lock();
+        let mut n = 0;
+
+        while n < buffer.len() {
+            if let Some(byte) = input.pending.pop_front() {
+                buffer[n] = byte;
+                n += 1;
+                continue;
+            }
+
+            let Some(bytes) = input.next_key() else {
+                break;
+            };
+
+            for byte in bytes {
+                // NOTE: pending is empty here and fits a whole key
+                if n < buffer.len() {
+                    buffer[n] = byte;
+                    n += 1;
+                } else {
+                    let _ = input.pending.push_back(byte);
+                }
+            }
+        }
+
+        Ok(n)
+    }
+
+    fn write(&self, _file: &File, _offset: usize, buffer: &[u8]) -> Result<usize, IoError> {
+        print!("{}", String::from_utf8_lossy(buffer));
+
+        Ok(buffer.len())
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{
+    device::char::{CharDevice, CharacterDeviceMetadata},
+    fs::{File, FileOperations, vfs::IoError},
+};
+
+/// A device which is always full. Reads return zeros like `/dev/zero` while
+/// writes always fail, which is useful for testing write error handling.
+pub struct FullDevice;
+
+impl CharDevice for FullDevice {
+    fn metadata(&self) -> &CharacterDeviceMetadata {
+        &CharacterDeviceMetadata { name: "full" }
+    }
+
+    fn file_operations(&self) -> &dyn FileOperations {
+        self
+    }
+}
+
+impl FileOperations for FullDevice {
+    fn read(&self, _file: &File, _offset: usize, buffer: &mut [u8]) -> Result<usize, IoError> {
+        buffer.fill(0);
+        Ok(buffer.len())
+    }
+
+    fn write(&self, _file: &File, _offset: usize, _buffer: &[u8]) -> Result<usize, IoError> {
+        Err(IoError::NoSpaceLeft)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::{
+        FileMode,
+        vfs::{self, IoError},
+    };
+
+    #[test_case]
+    fn writes_fail_with_no_space_left() {
+        let vfs = vfs::get();
+
+        let fd = vfs.open("/dev/full", FileMode::Write).unwrap();
+        assert_eq!(vfs.write(fd, b"data"), Err(IoError::NoSpaceLeft));
+        vfs.close(fd).unwrap();
+    }
+
+    #[test_case]
+    fn reads_return_zeros() {
+        let vfs = vfs::get();
+
+        let fd = vfs.open("/dev/full", FileMode::Read).unwrap();
+        let mut buffer = [0xffu8; 16];
+        assert_eq!(vfs.read(fd, &mut buffer), Ok(16));
+        assert_eq!(buffer, [0u8; 16]);
+        vfs.close(fd).unwrap();
+    }
+}
@@ -1,16 +1,32 @@
 use alloc::sync::Arc;
 
+use console::ConsoleDevice;
+use full::FullDevice;
 use null::NullDevice;
+use random::RandomDevice;
+use serial::SerialDevice;
 use zero::ZeroDevice;
 
 use crate::device::char::{CharDeviceRegistrationError, register_char_device};
 
+pub mod console;
+mod full;
 mod null;
+mod random;
+pub mod serial;
 mod zero;
 
 pub fn init() -> Result<(), CharDeviceRegistrationError> {
+    serial::init();
+    console::init();
+
     register_char_device(Arc::new(NullDevice))?;
     register_char_device(Arc::new(ZeroDevice))?;
+    register_char_device(Arc::new(FullDevice))?;
+    register_char_device(Arc::new(RandomDevice::new("random")))?;
+    register_char_device(Arc::new(RandomDevice::new("urandom")))?;
+    register_char_device(Arc::new(SerialDevice))?;
+    register_char_device(Arc::new(ConsoleDevice))?;
 
     Ok(())
 }
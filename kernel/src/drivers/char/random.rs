@@ -0,0 +1,90 @@
+//! Pseudo-random number devices (`/dev/random` and `/dev/urandom`)
+//!
+//! Both devices draw from a single shared xorshift64* generator which is mixed
+//! with the timer tick count on every read. This is fine for testing and
+//! shuffling things around but is NOT cryptographically secure.
+
+use spin::Mutex;
+
+use crate::{
+    device::char::{CharDevice, CharacterDeviceMetadata},
+    fs::{File, FileOperations, vfs::IoError},
+    util::time,
+};
+
+/// An arbitrary non-zero starting state (the 64-bit golden ratio)
+const INITIAL_STATE: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// The state of the generator shared by all random devices. Must never be 0
+/// since xorshift would only ever produce zeros from then on.
+static STATE: Mutex<u64> = Mutex::new(INITIAL_STATE);
+
+pub struct RandomDevice {
+    metadata: CharacterDeviceMetadata,
+}
+
+impl RandomDevice {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            metadata: CharacterDeviceMetadata { name },
+        }
+    }
+}
+
+impl CharDevice for RandomDevice {
+    fn metadata(&self) -> &CharacterDeviceMetadata {
+        &self.metadata
+    }
+
+    fn file_operations(&self) -> &dyn FileOperations {
+        self
+    }
+}
+
+impl FileOperations for RandomDevice {
+    fn read(&self, _file: &File, _offset: usize, buffer: &mut [u8]) -> Result<usize, IoError> {
+        let mut state = STATE.lock();
+
+        mix(&mut state, time::ticks());
+
+        for chunk in buffer.chunks_mut(8) {
+            let bytes = next(&mut state).to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+
+        Ok(buffer.len())
+    }
+
+    /// Written data is mixed into the state of the generator
+    fn write(&self, _file: &File, _offset: usize, buffer: &[u8]) -> Result<usize, IoError> {
+        let mut state = STATE.lock();
+
+        for chunk in buffer.chunks(8) {
+            let mut bytes = [0u8; 8];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+
+            mix(&mut state, u64::from_le_bytes(bytes));
+        }
+
+        Ok(buffer.len())
+    }
+}
+
+/// Advances the generator and returns the next value (xorshift64*)
+fn next(state: &mut u64) -> u64 {
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+
+    state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+}
+
+/// Folds a value into the state of the generator
+fn mix(state: &mut u64, value: u64) {
+    *state ^= value.wrapping_mul(INITIAL_STATE);
+    next(state);
+
+    if *state == 0 {
+        *state = INITIAL_STATE;
+    }
+}
@@ -0,0 +1,209 @@
+//! Driver for the 16550 UART behind the first serial port (COM1)
+//!
+//! The port is used in polling mode. Output waits until the transmitter can
+//! take another byte and reads only return the bytes which have already
+//! arrived. Under QEMU, `-serial stdio` connects the port to the terminal,
+//! which makes it useful for capturing kernel output.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+use crate::{
+    device::char::{CharDevice, CharacterDeviceMetadata},
+    fs::{File, FileOperations, vfs::IoError},
+};
+
+/// The I/O port base of COM1
+const COM1_BASE: u16 = 0x3F8;
+
+/// Registers relative to the base port. The first two registers hold the baud
+/// rate divisor instead while the divisor latch is enabled.
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+/// Divides the 115200 Hz base clock of the UART down to the baud rate (38400)
+const BAUD_DIVISOR: u16 = 3;
+
+/// Line control bit which maps the divisor onto the first two registers
+const LINE_CONTROL_DIVISOR_LATCH: u8 = 0x80;
+/// 8 data bits, no parity and a single stop bit
+const LINE_CONTROL_8N1: u8 = 0x03;
+/// Enables and clears both FIFOs with a 14 byte receive threshold
+const FIFO_CONTROL_ENABLE: u8 = 0xC7;
+/// Sets DTR, RTS and OUT2
+const MODEM_CONTROL_READY: u8 = 0x0B;
+
+const LINE_STATUS_DATA_READY: u8 = 0x01;
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 0x20;
+
+static COM1: Mutex<SerialPort> = Mutex::new(SerialPort::new(COM1_BASE));
+
+/// Set once the UART has been programmed
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+pub struct SerialPort {
+    base: u16,
+}
+
+impl SerialPort {
+    const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    fn register(&self, offset: u16) -> Port<u8> {
+        Port::new(self.base + offset)
+    }
+
+    /// Programs the baud rate and line settings and enables the FIFOs
+    fn init(&mut self) {
+        // SAFETY: the registers all belong to this UART, which is only
+        // accessed through this struct
+        unsafe {
+            // Everything is polled, so the UART must not raise interrupts
+            self.register(INTERRUPT_ENABLE).write(0);
+
+            self.register(LINE_CONTROL)
+                .write(LINE_CONTROL_DIVISOR_LATCH);
+            self.register(DATA).write(BAUD_DIVISOR as u8);
+            self.register(INTERRUPT_ENABLE)
+                .write((BAUD_DIVISOR >> 8) as u8);
+
+            // Clearing the divisor latch flag here as well switches the first
+            // two registers back
+            self.register(LINE_CONTROL).write(LINE_CONTROL_8N1);
+            self.register(FIFO_CONTROL).write(FIFO_CONTROL_ENABLE);
+            self.register(MODEM_CONTROL).write(MODEM_CONTROL_READY);
+        }
+    }
+
+    fn line_status(&self) -> u8 {
+        // SAFETY: reading the line status has no side effects
+        unsafe { self.register(LINE_STATUS).read() }
+    }
+
+    /// Waits until the transmitter is ready and then sends a byte
+    fn send(&mut self, byte: u8) {
+        while self.line_status() & LINE_STATUS_TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+
+        // SAFETY: the transmitter is empty, so the byte won't overwrite one
+        // which is still being sent
+        unsafe { self.register(DATA).write(byte) }
+    }
+
+    /// Returns the next received byte, if there is one
+    fn try_receive(&mut self) -> Option<u8> {
+        if self.line_status() & LINE_STATUS_DATA_READY == 0 {
+            return None;
+        }
+
+        // SAFETY: a byte is waiting, so reading it only removes it from the
+        // receive FIFO
+        Some(unsafe { self.register(DATA).read() })
+    }
+}
+
+impl core::fmt::Write for SerialPort {
+    /// Terminals expect a carriage return before each line feed
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+
+            self.send(byte);
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets up COM1. Output written before this still goes out, but with whatever
+/// settings the firmware left behind.
+pub fn init() {
+    x86_64::instructions::interrupts::without_interrupts(|| COM1.lock().init());
+    INITIALIZED.store(true, Ordering::Release);
+}
+
+/// Returns true once [`init`] has set up the port
+pub fn is_initialized() -> bool {
+    INITIALIZED.load(Ordering::Acquire)
+}
+
+/// Exposes COM1 as a character device. Data is passed through unchanged in
+/// both directions.
+pub struct SerialDevice;
+
+impl CharDevice for SerialDevice {
+    fn metadata(&self) -> &CharacterDeviceMetadata {
+        &CharacterDeviceMetadata { name: "ttyS0" }
+    }
+
+    fn file_operations(&self) -> &dyn FileOperations {
+        self
+    }
+}
+
+impl FileOperations for SerialDevice {
+    /// Returns the bytes which have arrived so far without waiting for more,
+    /// so a read may return 0 bytes
+    fn read(&self, _file: &File, _offset: usize, buffer: &mut [u8]) -> Result<usize, IoError> {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut port = COM1.lock();
+            let mut n = 0;
+
+            while n < buffer.len() {
+                let Some(byte) = port.try_receive() else {
+                    break;
+                };
+
+                buffer[n] = byte;
+                n += 1;
+            }
+
+            Ok(n)
+        })
+    }
+
+    fn write(&self, _file: &File, _offset: usize, buffer: &[u8]) -> Result<usize, IoError> {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut port = COM1.lock();
+
+            for byte in buffer {
+                port.send(*byte);
+            }
+
+            Ok(buffer.len())
+        })
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: core::fmt::Arguments) {
+    use core::fmt::Write;
+
+    // Same as the VGA writer, interrupt handlers must be able to print without
+    // deadlocking on the port
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        // NOTE: writing to the port is infallible
+        COM1.lock().write_fmt(args).unwrap();
+    });
+}
+
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::drivers::char::serial::_print(format_args!($($arg)*)));
+}
+pub(crate) use serial_print;
+
+macro_rules! serial_println {
+    () => ($crate::drivers::char::serial::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::drivers::char::serial::serial_print!("{}\n", format_args!($($arg)*)));
+}
+pub(crate) use serial_println;
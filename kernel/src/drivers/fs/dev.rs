@@ -1,16 +1,17 @@
-use alloc::{boxed::Box, sync::Arc};
-
-use spin::Mutex;
+use alloc::{boxed::Box, sync::Arc, vec};
 
 use crate::{
-    device::char::{CharDevice, get_char_device, list_char_devices},
+    device::{
+        block::{self, BlockDevice, get_block_device, list_block_devices},
+        char::{self, CharDevice, get_char_device, list_char_devices},
+    },
     fs::{
-        DirectoryOperations, File, FileOperations, FileSystem, FileSystemMetadata, FileSystemType,
-        FileSystemTypeMetadata, FsNode, FsNodeId, FsNodeKind, FsNodeLock, FsNodeMetadata,
-        FsNodeOperations, MountFlags, impl_fs_ops_for_self,
+        DirectoryOperations, File, FileOperations, FileSystem, FileSystemMetadata, FileSystemStats,
+        FileSystemType, FileSystemTypeMetadata, FsNode, FsNodeId, FsNodeKind, FsNodeOperations,
+        MountFlags, impl_fs_ops_for_self,
+        path::MAX_NAME_LENGTH,
         vfs::{DirectoryEntry, DirectoryIterationContext, IoError, MountId},
     },
-    util::sync_cell::SynCell,
 };
 
 pub struct DevFileSystemType;
@@ -29,7 +30,10 @@ impl FileSystemType for DevFileSystemType {
         source: &str,
         flags: MountFlags,
     ) -> Result<Arc<dyn FileSystem>, IoError> {
-        assert_eq!(source, "", "dev does not take a source argument");
+        // There is no backing device to read from
+        if !source.is_empty() {
+            return Err(IoError::InvalidPath);
+        }
 
         Ok(Arc::new(DevFileSystem {
             metadata: FileSystemMetadata {
@@ -37,43 +41,102 @@ impl FileSystemType for DevFileSystemType {
                 mount_flags: flags,
                 block_size: 512,
                 max_file_size: usize::MAX,
+                max_name_len: MAX_NAME_LENGTH,
                 file_system_type: self.clone(),
             },
-            root: Arc::new(FsNode {
+            root: Arc::new(FsNode::new(
+                FsNodeId::ZERO,
                 mount_id,
-                id: FsNodeId::ZERO,
-                kind: FsNodeKind::Directory,
-                metadata: Mutex::new(FsNodeMetadata {
-                    dirty: false,
-                    link_count: 1,
-                    size: 0,
-                    accessed_at: 0,
-                    created_at: 0,
-                    modified_at: 0,
-                }),
-                structure_lock: Mutex::new(FsNodeLock),
-                private_data: None,
-            }),
-            next_node_id: SynCell::new(FsNodeId::new(1)),
+                FsNodeKind::Directory,
+                None,
+            )),
         }))
     }
 
     fn unmount(self: Arc<Self>, _instance: Arc<dyn FileSystem>) {
-        todo!("unmount dev file system")
+        // Devfs only reflects the device registry, which stays intact, so
+        // there is nothing to tear down
     }
 }
 
 pub struct DevFileSystem {
     metadata: FileSystemMetadata,
-    next_node_id: SynCell<FsNodeId>,
     root: Arc<FsNode>,
 }
 
 impl DevFileSystem {
-    fn next_node_id(&self) -> FsNodeId {
-        self.next_node_id
-            .replace(|id| FsNodeId::new(id.as_u64() + 1))
+    /// Device nodes use the ID the device was registered with so that every
+    /// lookup of a device produces the same node ID. Device IDs start at 1,
+    /// so they never collide with the root directory.
+    fn node_id(&self, name: &str) -> Option<FsNodeId> {
+        char::device_id(name)
+            .or_else(|| block::device_id(name))
+            .map(FsNodeId::new)
+    }
+}
+
+/// Reads from a block device at any byte offset by reading all of the blocks
+/// which cover the requested range
+fn read_block_device(
+    b_dev: &dyn BlockDevice,
+    offset: usize,
+    buffer: &mut [u8],
+) -> Result<usize, IoError> {
+    let metadata = b_dev.metadata();
+    let capacity = metadata.capacity();
+
+    // Reading past the end of the device is the same as the end of a file
+    if offset >= capacity || buffer.is_empty() {
+        return Ok(0);
+    }
+
+    let length = buffer.len().min(capacity - offset);
+
+    let start = offset - offset % metadata.block_size;
+    let end = (offset + length).next_multiple_of(metadata.block_size);
+
+    let mut blocks = vec![0; end - start];
+    b_dev.read(start, &mut blocks)?;
+
+    buffer[..length].copy_from_slice(&blocks[offset - start..][..length]);
+
+    Ok(length)
+}
+
+/// Writes to a block device at any byte offset. Blocks which are only partially
+/// covered by the buffer are read first so that the rest of their contents are
+/// preserved.
+fn write_block_device(
+    b_dev: &dyn BlockDevice,
+    offset: usize,
+    buffer: &[u8],
+) -> Result<usize, IoError> {
+    let metadata = b_dev.metadata();
+    let capacity = metadata.capacity();
+
+    if buffer.is_empty() {
+        return Ok(0);
+    }
+
+    if offset >= capacity {
+        return Err(IoError::NoSpaceLeft);
     }
+
+    let length = buffer.len().min(capacity - offset);
+
+    let start = offset - offset % metadata.block_size;
+    let end = (offset + length).next_multiple_of(metadata.block_size);
+
+    let mut blocks = vec![0; end - start];
+
+    if start != offset || end != offset + length {
+        b_dev.read(start, &mut blocks)?;
+    }
+
+    blocks[offset - start..][..length].copy_from_slice(&buffer[..length]);
+    b_dev.write(start, &blocks)?;
+
+    Ok(length)
 }
 
 impl FileSystem for DevFileSystem {
@@ -85,6 +148,27 @@ impl FileSystem for DevFileSystem {
         self.root.clone()
     }
 
+    fn sync(&self) -> Result<(), IoError> {
+        let mut result = Ok(());
+
+        for b_dev in list_block_devices() {
+            if let Err(e) = b_dev.flush() {
+                result = result.and(Err(e.into()));
+            }
+        }
+
+        result
+    }
+
+    fn statfs(&self) -> Result<FileSystemStats, IoError> {
+        // Device nodes don't take up any space
+        Ok(FileSystemStats {
+            block_size: self.metadata.block_size,
+            used_blocks: 0,
+            free_blocks: Some(0),
+        })
+    }
+
     impl_fs_ops_for_self!();
 }
 
@@ -108,7 +192,11 @@ impl FileOperations for DevFileSystem {
 
                 c_dev.file_operations().read(file, offset, buffer)
             }
-            FsNodeKind::BlockDevice => todo!(),
+            FsNodeKind::BlockDevice => {
+                let b_dev = file.node.data_as::<Arc<dyn BlockDevice>>();
+
+                read_block_device(b_dev.as_ref(), offset, buffer)
+            }
             _ => unreachable!(),
         }
     }
@@ -120,7 +208,27 @@ impl FileOperations for DevFileSystem {
 
                 c_dev.file_operations().write(file, offset, buffer)
             }
-            FsNodeKind::BlockDevice => todo!(),
+            FsNodeKind::BlockDevice => {
+                let b_dev = file.node.data_as::<Arc<dyn BlockDevice>>();
+
+                write_block_device(b_dev.as_ref(), offset, buffer)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn flush(&self, file: &File) -> Result<(), IoError> {
+        match file.node.kind {
+            FsNodeKind::CharDevice => {
+                let c_dev = file.node.data_as::<Arc<dyn CharDevice>>();
+
+                c_dev.file_operations().flush(file)
+            }
+            FsNodeKind::BlockDevice => {
+                let b_dev = file.node.data_as::<Arc<dyn BlockDevice>>();
+
+                Ok(b_dev.flush()?)
+            }
             _ => unreachable!(),
         }
     }
@@ -135,25 +243,38 @@ impl DirectoryOperations for DevFileSystem {
         // We only support a single directory right now, so just lookup the name
         // in the device table
 
-        Ok(get_char_device(name).map(|d| {
-            Arc::new(FsNode {
-                // FIXME: see below comment about consistent node ids
-                id: self.next_node_id(),
-                mount_id: self.root.mount_id,
-                kind: FsNodeKind::CharDevice,
-                metadata: Mutex::new(FsNodeMetadata {
-                    dirty: false,
-                    link_count: 1,
-                    // FIXME: what should these be?
-                    size: 0,
-                    accessed_at: 0,
-                    created_at: 0,
-                    modified_at: 0,
-                }),
-                structure_lock: Mutex::new(FsNodeLock),
-                private_data: Some(Box::new(d)),
-            })
-        }))
+        let Some(id) = self.node_id(name) else {
+            return Ok(None);
+        };
+
+        // FIXME: the timestamps should be the time the device was registered
+        // rather than the time it was looked up
+        if let Some(d) = get_char_device(name) {
+            return Ok(Some(Arc::new(FsNode::new(
+                id,
+                self.root.mount_id,
+                FsNodeKind::CharDevice,
+                Some(Box::new(d)),
+            ))));
+        }
+
+        if let Some(d) = get_block_device(name) {
+            let capacity = d.metadata().capacity();
+
+            let node = FsNode::new(
+                id,
+                self.root.mount_id,
+                FsNodeKind::BlockDevice,
+                Some(Box::new(d)),
+            );
+
+            // Report the capacity of the device as its size
+            node.metadata.lock().size = capacity;
+
+            return Ok(Some(Arc::new(node)));
+        }
+
+        Ok(None)
     }
 
     fn read_directory(
@@ -165,15 +286,23 @@ impl DirectoryOperations for DevFileSystem {
         // devices currently registered in the device table
 
         for dev in list_char_devices() {
-            context.insert(
-                dev.metadata().name,
-                // FIXME: we should always be returning the same fsnode ids for
-                // any given device but for now this is ok. can we assign global
-                // ids to each device and then store a mapping from device ids
-                // to node ids? should the device ids just be the node ids?
-                self.next_node_id(),
-                FsNodeKind::CharDevice,
-            );
+            let name = dev.metadata().name;
+
+            let Some(id) = self.node_id(name) else {
+                continue;
+            };
+
+            context.insert(name, id, FsNodeKind::CharDevice);
+        }
+
+        for dev in list_block_devices() {
+            let name = dev.metadata().name;
+
+            let Some(id) = self.node_id(name) else {
+                continue;
+            };
+
+            context.insert(name, id, FsNodeKind::BlockDevice);
         }
         Ok(())
     }
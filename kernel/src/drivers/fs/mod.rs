@@ -1,16 +1,22 @@
 use alloc::sync::Arc;
 
 use dev::DevFileSystemType;
+use proc::ProcFileSystemType;
 use ram::RamFileSystemType;
+use tmp::TmpFileSystemType;
 
 use crate::fs::registry::{FileSystemRegistrationError, register_file_system};
 
 mod dev;
+mod proc;
 mod ram;
+mod tmp;
 
 pub fn init() -> Result<(), FileSystemRegistrationError> {
     register_file_system(Arc::new(RamFileSystemType))?;
     register_file_system(Arc::new(DevFileSystemType))?;
+    register_file_system(Arc::new(ProcFileSystemType))?;
+    register_file_system(Arc::new(TmpFileSystemType))?;
 
     Ok(())
 }
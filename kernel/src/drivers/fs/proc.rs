@@ -0,0 +1,193 @@
+use alloc::{boxed::Box, format, string::String, sync::Arc};
+
+use crate::{
+    allocator,
+    fs::{
+        DirectoryOperations, File, FileOperations, FileSystem, FileSystemMetadata, FileSystemStats,
+        FileSystemType, FileSystemTypeMetadata, FsNode, FsNodeId, FsNodeKind, FsNodeOperations,
+        MountFlags, impl_fs_ops_for_self,
+        path::MAX_NAME_LENGTH,
+        vfs::{DirectoryEntry, DirectoryIterationContext, IoError, MountId},
+    },
+    util::{slice_io, time},
+};
+
+pub struct ProcFileSystemType;
+
+impl FileSystemType for ProcFileSystemType {
+    fn metadata(&self) -> &FileSystemTypeMetadata {
+        &FileSystemTypeMetadata {
+            name: "procfs",
+            magic: &[],
+        }
+    }
+
+    fn mount(
+        self: Arc<Self>,
+        mount_id: MountId,
+        source: &str,
+        flags: MountFlags,
+    ) -> Result<Arc<dyn FileSystem>, IoError> {
+        // There is no backing device to read from
+        if !source.is_empty() {
+            return Err(IoError::InvalidPath);
+        }
+
+        Ok(Arc::new(ProcFileSystem {
+            metadata: FileSystemMetadata {
+                device: None,
+                mount_flags: flags,
+                block_size: 512,
+                max_file_size: usize::MAX,
+                max_name_len: MAX_NAME_LENGTH,
+                file_system_type: self.clone(),
+            },
+            root: Arc::new(FsNode::new(
+                FsNodeId::ZERO,
+                mount_id,
+                FsNodeKind::Directory,
+                None,
+            )),
+        }))
+    }
+
+    fn unmount(self: Arc<Self>, _instance: Arc<dyn FileSystem>) {
+        // The files are generated on demand, so there is nothing to tear down
+    }
+}
+
+/// A file whose contents are generated each time it is read
+struct ProcEntry {
+    name: &'static str,
+    generate: fn() -> String,
+}
+
+/// Every file in the root of procfs. Each file uses its index plus one as its
+/// node ID so that the IDs never collide with the root directory.
+const ENTRIES: &[ProcEntry] = &[
+    ProcEntry {
+        name: "meminfo",
+        generate: generate_meminfo,
+    },
+    ProcEntry {
+        name: "uptime",
+        generate: generate_uptime,
+    },
+    ProcEntry {
+        name: "version",
+        generate: generate_version,
+    },
+];
+
+fn generate_meminfo() -> String {
+    let stats = allocator::stats();
+
+    format!(
+        "HeapTotal: {:>8} kB\nHeapUsed:  {:>8} kB\nHeapFree:  {:>8} kB\nSlabCache: {:>8} kB\n",
+        stats.size / 1024,
+        stats.used() / 1024,
+        stats.available() / 1024,
+        stats.cached() / 1024
+    )
+}
+
+/// The time since boot in seconds, with two decimal places like Linux
+fn generate_uptime() -> String {
+    let ms = time::uptime_ms();
+
+    format!("{}.{:02}\n", ms / 1000, ms % 1000 / 10)
+}
+
+fn generate_version() -> String {
+    format!("Riptide version {} (x86_64)\n", env!("CARGO_PKG_VERSION"))
+}
+
+pub struct ProcFileSystem {
+    metadata: FileSystemMetadata,
+    root: Arc<FsNode>,
+}
+
+impl FileSystem for ProcFileSystem {
+    fn metadata(&self) -> &FileSystemMetadata {
+        &self.metadata
+    }
+
+    fn root_directory(&self) -> Arc<FsNode> {
+        self.root.clone()
+    }
+
+    fn statfs(&self) -> Result<FileSystemStats, IoError> {
+        // Nothing is stored, so the files don't take up any space
+        Ok(FileSystemStats {
+            block_size: self.metadata.block_size,
+            used_blocks: 0,
+            free_blocks: Some(0),
+        })
+    }
+
+    impl_fs_ops_for_self!();
+}
+
+impl FsNodeOperations for ProcFileSystem {
+    fn write_node(&self, _node: &FsNode) -> Result<(), ()> {
+        // no-op because we dont persist files
+        Ok(())
+    }
+
+    fn evict_node(&self, _node: &FsNode) -> Result<(), ()> {
+        // no-op because we dont persist files
+        Ok(())
+    }
+}
+
+impl FileOperations for ProcFileSystem {
+    fn read(&self, file: &File, offset: usize, buffer: &mut [u8]) -> Result<usize, IoError> {
+        let entry = file.node.data_as::<&'static ProcEntry>();
+
+        // NOTE: the contents are generated again for every read, so a file
+        // which changes between two reads (i.e. uptime) may come out torn if
+        // the buffer is too small to read it all at once
+        let contents = (entry.generate)();
+
+        Ok(slice_io::read_at(contents.as_bytes(), offset, buffer))
+    }
+}
+
+impl DirectoryOperations for ProcFileSystem {
+    fn lookup(
+        &self,
+        _parent: &Arc<DirectoryEntry>,
+        name: &str,
+    ) -> Result<Option<Arc<FsNode>>, IoError> {
+        // There are no subdirectories, so every lookup is in the root
+        let Some((i, entry)) = ENTRIES.iter().enumerate().find(|(_, e)| e.name == name) else {
+            return Ok(None);
+        };
+
+        // NOTE: like on Linux, the files report a size of 0 since their
+        // contents aren't known until they are read
+        let node = FsNode::new(
+            FsNodeId::new(i as u64 + 1),
+            self.root.mount_id,
+            FsNodeKind::File,
+            Some(Box::new(entry)),
+        );
+
+        // The files are generated, so they can't be written to
+        node.metadata.lock().mode = 0o444;
+
+        Ok(Some(Arc::new(node)))
+    }
+
+    fn read_directory(
+        &self,
+        context: &mut DirectoryIterationContext,
+        _entry: &Arc<DirectoryEntry>,
+    ) -> Result<(), IoError> {
+        for (i, entry) in ENTRIES.iter().enumerate() {
+            context.insert(entry.name, FsNodeId::new(i as u64 + 1), FsNodeKind::File);
+        }
+
+        Ok(())
+    }
+}
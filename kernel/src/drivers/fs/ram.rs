@@ -1,15 +1,23 @@
-use alloc::{boxed::Box, collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use alloc::{
+    boxed::Box,
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use spin::{Mutex, RwLock};
+use spin::RwLock;
 
 use crate::{
     fs::{
-        DirectoryOperations, File, FileOperations, FileSystem, FileSystemMetadata, FileSystemType,
-        FileSystemTypeMetadata, FsNode, FsNodeId, FsNodeKind, FsNodeLock, FsNodeMetadata,
-        FsNodeOperations, MountFlags, impl_fs_ops_for_self,
+        DirectoryOperations, File, FileOperations, FileSystem, FileSystemMetadata, FileSystemStats,
+        FileSystemType, FileSystemTypeMetadata, FsNode, FsNodeId, FsNodeKind, FsNodeOperations,
+        MountFlags, SeekFrom, impl_fs_ops_for_self,
+        path::MAX_NAME_LENGTH,
         vfs::{DirectoryEntry, DirectoryIterationContext, IoError, MountId},
     },
-    util::sync_cell::SynCell,
+    util::{slice_io, sync_cell::SynCell},
 };
 
 pub struct RamFileSystemType;
@@ -28,37 +36,17 @@ impl FileSystemType for RamFileSystemType {
         source: &str,
         flags: MountFlags,
     ) -> Result<Arc<dyn FileSystem>, IoError> {
-        assert_eq!(source, "", "ramfs does not take a source argument");
+        // There is no backing device to read from
+        if !source.is_empty() {
+            return Err(IoError::InvalidPath);
+        }
 
-        Ok(Arc::new(RamFileSystem {
-            metadata: FileSystemMetadata {
-                device: None,
-                mount_flags: flags,
-                block_size: 512,
-                max_file_size: usize::MAX,
-                file_system_type: self.clone(),
-            },
-            root: Arc::new(FsNode {
-                mount_id,
-                id: FsNodeId::ZERO,
-                kind: FsNodeKind::Directory,
-                metadata: Mutex::new(FsNodeMetadata {
-                    dirty: false,
-                    link_count: 1,
-                    size: 0,
-                    accessed_at: 0,
-                    created_at: 0,
-                    modified_at: 0,
-                }),
-                structure_lock: Mutex::new(FsNodeLock),
-                private_data: Some(Box::new(RamDirectoryNode::default())),
-            }),
-            next_node_id: SynCell::new(FsNodeId::new(1)),
-        }))
+        Ok(Arc::new(RamFileSystem::new(self, mount_id, flags, None)))
     }
 
     fn unmount(self: Arc<Self>, _instance: Arc<dyn FileSystem>) {
-        todo!("unmount ram file system")
+        // Nothing needs to be written back since everything lives in memory.
+        // The nodes are freed once the last reference to the instance drops.
     }
 }
 
@@ -66,13 +54,91 @@ pub struct RamFileSystem {
     metadata: FileSystemMetadata,
     next_node_id: SynCell<FsNodeId>,
     root: Arc<FsNode>,
+    /// The most bytes which the contents of all files may add up to, if there
+    /// is a limit
+    size_limit: Option<usize>,
+    /// The bytes taken up by the contents of all files
+    used_bytes: AtomicUsize,
 }
 
 impl RamFileSystem {
+    /// Creates an empty file system. Writes which would take the contents of
+    /// all files past `size_limit` fail with [`IoError::NoSpaceLeft`].
+    pub(super) fn new(
+        file_system_type: Arc<dyn FileSystemType>,
+        mount_id: MountId,
+        flags: MountFlags,
+        size_limit: Option<usize>,
+    ) -> Self {
+        Self {
+            metadata: FileSystemMetadata {
+                device: None,
+                mount_flags: flags,
+                block_size: 512,
+                // The size limit applies to all files together and is enforced
+                // by `resize_contents`, so a single file past it runs out of
+                // space rather than being too large
+                max_file_size: usize::MAX,
+                max_name_len: MAX_NAME_LENGTH,
+                file_system_type,
+            },
+            root: Arc::new(FsNode::new(
+                FsNodeId::ZERO,
+                mount_id,
+                FsNodeKind::Directory,
+                Some(Box::new(RamDirectoryNode::default())),
+            )),
+            next_node_id: SynCell::new(FsNodeId::new(1)),
+            size_limit,
+            used_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Accounts for a file changing its length. Fails without changing
+    /// anything if growing the file would go past the size limit.
+    fn resize_contents(&self, old_length: usize, new_length: usize) -> Result<(), IoError> {
+        if new_length <= old_length {
+            self.used_bytes
+                .fetch_sub(old_length - new_length, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        let growth = new_length - old_length;
+        let limit = self.size_limit.unwrap_or(usize::MAX);
+
+        self.used_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(growth).filter(|&used| used <= limit)
+            })
+            .map(|_| ())
+            .map_err(|_| IoError::NoSpaceLeft)
+    }
+
     fn next_node_id(&self) -> FsNodeId {
         self.next_node_id
             .replace(|id| FsNodeId::new(id.as_u64() + 1))
     }
+
+    /// Counts the blocks used by the contents of every node below the
+    /// directory. Nodes with several names are only counted once.
+    fn used_blocks(&self, directory: &RamDirectoryNode, counted: &mut BTreeSet<FsNodeId>) -> usize {
+        let mut blocks = 0;
+
+        for node in directory.children.read().values() {
+            if !counted.insert(node.id) {
+                continue;
+            }
+
+            if node.is_directory() {
+                blocks += self.used_blocks(node.data_as::<RamDirectoryNode>(), counted);
+            } else {
+                let size = node.metadata.lock().size;
+                blocks += size.div_ceil(self.metadata.block_size);
+            }
+        }
+
+        blocks
+    }
 }
 
 impl FileSystem for RamFileSystem {
@@ -84,6 +150,24 @@ impl FileSystem for RamFileSystem {
         self.root.clone()
     }
 
+    fn statfs(&self) -> Result<FileSystemStats, IoError> {
+        let root = self.root.data_as::<RamDirectoryNode>();
+        let used_blocks = self.used_blocks(root, &mut BTreeSet::new());
+
+        // Without a limit, files can keep growing for as long as the heap has
+        // room
+        let free_blocks = self.size_limit.map(|limit| {
+            let used = self.used_bytes.load(Ordering::Relaxed);
+            limit.saturating_sub(used) / self.metadata.block_size
+        });
+
+        Ok(FileSystemStats {
+            block_size: self.metadata.block_size,
+            used_blocks,
+            free_blocks,
+        })
+    }
+
     impl_fs_ops_for_self!();
 }
 
@@ -93,8 +177,14 @@ impl FsNodeOperations for RamFileSystem {
         Ok(())
     }
 
-    fn evict_node(&self, _node: &FsNode) -> Result<(), ()> {
-        // no-op because we dont persist files
+    fn evict_node(&self, node: &FsNode) -> Result<(), ()> {
+        // Nothing is persisted, but the contents no longer count towards the
+        // size limit once the last reference is gone
+        if node.kind == FsNodeKind::File {
+            let length = node.data_as::<RamFileNode>().data.read().len();
+            let _ = self.resize_contents(length, 0);
+        }
+
         Ok(())
     }
 }
@@ -106,41 +196,54 @@ pub struct RamFileNode {
 
 impl FileOperations for RamFileSystem {
     fn read(&self, file: &File, offset: usize, buffer: &mut [u8]) -> Result<usize, IoError> {
-        let f_node = file.node.data_as::<RamFileNode>();
-        let data = f_node.data.read();
+        let node = file.node.data_as::<RamFileNode>();
 
-        // If the offset is past the end of the file, there is nothing to read
-        if offset > data.len() {
-            return Ok(0);
-        }
+        Ok(slice_io::read_at(&node.data.read(), offset, buffer))
+    }
+
+    fn write(&self, file: &File, offset: usize, buffer: &[u8]) -> Result<usize, IoError> {
+        let node = file.node.data_as::<RamFileNode>();
+        let mut data = node.data.write();
 
-        // The number of bytes we can read is determined by the number of bytes
-        // left past the offset and the length of the buffer
-        let bytes_remaining = data.len() - offset;
-        let read_size = buffer.len().min(bytes_remaining);
+        // Make sure that any growth fits before the contents are touched
+        let new_length = slice_io::length_after_write(&data, offset, buffer.len());
+        self.resize_contents(data.len(), new_length)?;
 
-        buffer[..read_size].copy_from_slice(&data[offset..offset + read_size]);
+        slice_io::write_at(&mut data, offset, buffer);
+        file.node.metadata.lock().size = data.len();
 
-        Ok(read_size)
+        Ok(buffer.len())
     }
 
-    fn write(&self, file: &File, offset: usize, buffer: &[u8]) -> Result<usize, IoError> {
+    fn seek(&self, file: &File, position: usize, from: SeekFrom) -> Result<usize, IoError> {
+        let node = file.node.data_as::<RamFileNode>();
+
+        // Seeking past the end is fine since writes fill the gap with 0s
+        from.resolve(position, node.data.read().len())
+    }
+
+    fn truncate(&self, file: &File, length: usize) -> Result<(), IoError> {
         let node = file.node.data_as::<RamFileNode>();
         let mut data = node.data.write();
 
-        // If the length of the file would be increased by this operation, we
-        // need to first resize the backing buffer up to the new length which
-        // fills the new space (and any created holes) with 0s.
-        let min_new_len = offset + buffer.len();
-        if min_new_len > data.len() {
-            data.resize(min_new_len, 0);
-        }
+        self.resize_contents(data.len(), length)?;
+        data.resize(length, 0);
+        file.node.metadata.lock().size = data.len();
 
-        data[offset..offset + buffer.len()].copy_from_slice(buffer);
+        Ok(())
+    }
 
-        Ok(buffer.len())
+    fn readlink(&self, node: &FsNode) -> Result<String, IoError> {
+        Ok(node.data_as::<RamSymlinkNode>().target.clone())
     }
 }
+
+pub struct RamSymlinkNode {
+    /// The path the link points to, exactly as it was given when the link was
+    /// created
+    target: String,
+}
+
 #[derive(Default)]
 pub struct RamDirectoryNode {
     // NOTE: we use a map from names to FsNodes instead of directory entries
@@ -156,21 +259,12 @@ impl DirectoryOperations for RamFileSystem {
         parent: &Arc<DirectoryEntry>,
         name: &str,
     ) -> Result<Arc<FsNode>, IoError> {
-        let node = Arc::new(FsNode {
-            id: self.next_node_id(),
-            mount_id: self.root.mount_id,
-            kind: FsNodeKind::File,
-            metadata: Mutex::new(FsNodeMetadata {
-                dirty: false,
-                link_count: 1,
-                size: 0,
-                accessed_at: 0,
-                created_at: 0,
-                modified_at: 0,
-            }),
-            structure_lock: Mutex::new(FsNodeLock),
-            private_data: Some(Box::new(RamFileNode::default())),
-        });
+        let node = Arc::new(FsNode::new(
+            self.next_node_id(),
+            self.root.mount_id,
+            FsNodeKind::File,
+            Some(Box::new(RamFileNode::default())),
+        ));
 
         let parent = parent.node.data_as::<RamDirectoryNode>();
         parent.children.write().insert(name.into(), node.clone());
@@ -183,21 +277,12 @@ impl DirectoryOperations for RamFileSystem {
         parent: &Arc<DirectoryEntry>,
         name: &str,
     ) -> Result<Arc<FsNode>, IoError> {
-        let node = Arc::new(FsNode {
-            id: self.next_node_id(),
-            mount_id: self.root.mount_id,
-            kind: FsNodeKind::Directory,
-            metadata: Mutex::new(FsNodeMetadata {
-                dirty: false,
-                link_count: 1,
-                size: 0,
-                accessed_at: 0,
-                created_at: 0,
-                modified_at: 0,
-            }),
-            structure_lock: Mutex::new(FsNodeLock),
-            private_data: Some(Box::new(RamDirectoryNode::default())),
-        });
+        let node = Arc::new(FsNode::new(
+            self.next_node_id(),
+            self.root.mount_id,
+            FsNodeKind::Directory,
+            Some(Box::new(RamDirectoryNode::default())),
+        ));
 
         // FIXME: check if already exists
 
@@ -207,6 +292,44 @@ impl DirectoryOperations for RamFileSystem {
         Ok(node)
     }
 
+    fn create_symlink(
+        &self,
+        parent: &Arc<DirectoryEntry>,
+        name: &str,
+        target: &str,
+    ) -> Result<Arc<FsNode>, IoError> {
+        let node = Arc::new(FsNode::new(
+            self.next_node_id(),
+            self.root.mount_id,
+            FsNodeKind::Symlink,
+            Some(Box::new(RamSymlinkNode {
+                target: target.to_string(),
+            })),
+        ));
+
+        // Like other file systems, the size of a link is the length of its
+        // target
+        node.metadata.lock().size = target.len();
+
+        let parent = parent.node.data_as::<RamDirectoryNode>();
+        parent.children.write().insert(name.into(), node.clone());
+
+        Ok(node)
+    }
+
+    fn link(
+        &self,
+        parent: &Arc<DirectoryEntry>,
+        name: &str,
+        node: &Arc<FsNode>,
+    ) -> Result<(), IoError> {
+        // Both names share the node, so the contents are shared as well
+        let parent = parent.node.data_as::<RamDirectoryNode>();
+        parent.children.write().insert(name.into(), node.clone());
+
+        Ok(())
+    }
+
     fn remove_file(&self, parent: &Arc<DirectoryEntry>, name: &str) -> Result<(), IoError> {
         let parent = parent.node.data_as::<RamDirectoryNode>();
 
@@ -223,16 +346,44 @@ impl DirectoryOperations for RamFileSystem {
         // trying to remove them
         let parent = parent.node.data_as::<RamDirectoryNode>();
 
-        let children = parent.children.write();
+        let mut children = parent.children.write();
 
         let dir = children.get(name).ok_or(IoError::EntryNotFound)?;
 
-        assert!(dir.is_directory());
-        let dir_node = dir.data_as::<RamDirectoryNode>();
-        assert!(dir_node.children.read().is_empty());
+        if !dir.is_directory() {
+            return Err(IoError::NotADirectory);
+        }
 
-        // fixme: check child is empty
-        todo!()
+        if !dir.data_as::<RamDirectoryNode>().children.read().is_empty() {
+            return Err(IoError::NotEmpty);
+        }
+
+        children.remove(name);
+
+        Ok(())
+    }
+
+    fn rename(
+        &self,
+        old_parent: &Arc<DirectoryEntry>,
+        old_name: &str,
+        new_parent: &Arc<DirectoryEntry>,
+        new_name: &str,
+    ) -> Result<(), IoError> {
+        // NOTE: the VFS holds the structure locks of both parents, so nothing
+        // else can modify them while the node is moved
+        let old_parent = old_parent.node.data_as::<RamDirectoryNode>();
+        let new_parent = new_parent.node.data_as::<RamDirectoryNode>();
+
+        let node = old_parent
+            .children
+            .write()
+            .remove(old_name)
+            .ok_or(IoError::EntryNotFound)?;
+
+        new_parent.children.write().insert(new_name.into(), node);
+
+        Ok(())
     }
 
     fn lookup(
@@ -259,3 +410,49 @@ impl DirectoryOperations for RamFileSystem {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use crate::{
+        fs::{FileMode, MountFlags, vfs},
+        testing::scratch_directory,
+    };
+
+    #[test_case]
+    fn statfs_counts_used_blocks() {
+        let mnt = format!("{}/mnt", scratch_directory("ramfs_statfs"));
+        let vfs = vfs::get();
+        vfs.mount(
+            "",
+            &mnt,
+            Some("ramfs"),
+            MountFlags::READ | MountFlags::WRITE,
+        )
+        .unwrap();
+
+        let stats = vfs.statfs(&mnt).unwrap();
+        assert_eq!(stats.block_size, 512);
+        assert_eq!(stats.used_blocks, 0);
+        // ramfs can grow as long as the heap has room
+        assert_eq!(stats.free_blocks, None);
+        assert_eq!(stats.total_blocks(), None);
+
+        // Partial blocks count as whole ones, including in subdirectories
+        let file = format!("{}/file", mnt);
+        vfs.create_directory(&format!("{}/directory", mnt)).unwrap();
+        for (path, size) in [(file.clone(), 513), (format!("{}/directory/a", mnt), 1)] {
+            let fd = vfs.open(&path, FileMode::Write).unwrap();
+            vfs.write(fd, &[0; 1024][..size]).unwrap();
+            vfs.close(fd).unwrap();
+        }
+        assert_eq!(vfs.statfs(&mnt).unwrap().used_blocks, 3);
+
+        // A node with several names only takes up space once
+        vfs.link(&file, &format!("{}/link", mnt)).unwrap();
+        assert_eq!(vfs.statfs(&mnt).unwrap().used_blocks, 3);
+
+        vfs.unmount(&mnt).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+use alloc::sync::Arc;
+
+use super::ram::RamFileSystem;
+use crate::{
+    allocator,
+    fs::{
+        FileSystem, FileSystemType, FileSystemTypeMetadata, MountFlags,
+        vfs::{IoError, MountId},
+    },
+};
+
+/// Same as ramfs, except that the contents of all files together can't grow
+/// past a size limit. This keeps a runaway write from using up the whole heap.
+///
+/// The limit is given as the source of the mount, i.e. `size=64K`. Sizes may
+/// have a `K`, `M` or `G` suffix. Without one, the limit is half of the heap.
+pub struct TmpFileSystemType;
+
+impl FileSystemType for TmpFileSystemType {
+    fn metadata(&self) -> &FileSystemTypeMetadata {
+        &FileSystemTypeMetadata {
+            name: "tmpfs",
+            magic: &[],
+        }
+    }
+
+    fn mount(
+        self: Arc<Self>,
+        mount_id: MountId,
+        source: &str,
+        flags: MountFlags,
+    ) -> Result<Arc<dyn FileSystem>, IoError> {
+        let size_limit = match source {
+            "" => allocator::HEAP_SIZE / 2,
+            source => parse_size_option(source).ok_or(IoError::InvalidPath)?,
+        };
+
+        Ok(Arc::new(RamFileSystem::new(
+            self,
+            mount_id,
+            flags,
+            Some(size_limit),
+        )))
+    }
+
+    fn unmount(self: Arc<Self>, _instance: Arc<dyn FileSystem>) {
+        // Same as ramfs, the nodes are freed once the last reference to the
+        // instance drops
+    }
+}
+
+/// Parses a `size=N` option where N may end with a `K`, `M` or `G` suffix
+fn parse_size_option(option: &str) -> Option<usize> {
+    let size = option.strip_prefix("size=")?;
+
+    let (digits, multiplier) = match size.as_bytes().last()? {
+        b'K' | b'k' => (&size[..size.len() - 1], 1 << 10),
+        b'M' | b'm' => (&size[..size.len() - 1], 1 << 20),
+        b'G' | b'g' => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, string::String};
+
+    use super::*;
+    use crate::{
+        fs::{FileMode, vfs},
+        testing::scratch_directory,
+    };
+
+    /// Mounts a tmpfs with the given limit in a new scratch directory and
+    /// returns the path of the mount
+    fn mount_tmpfs(name: &str, source: &str) -> String {
+        let target = format!("{}/mnt", scratch_directory(name));
+
+        vfs::get()
+            .mount(
+                source,
+                &target,
+                Some("tmpfs"),
+                MountFlags::READ | MountFlags::WRITE,
+            )
+            .unwrap();
+
+        target
+    }
+
+    fn write_file(path: &str, data: &[u8]) -> Result<usize, IoError> {
+        let vfs = vfs::get();
+        let fd = vfs.open(path, FileMode::Write)?;
+        let result = vfs.write(fd, data);
+        vfs.close(fd)?;
+
+        result
+    }
+
+    #[test_case]
+    fn parses_size_options() {
+        assert_eq!(parse_size_option("size=100"), Some(100));
+        assert_eq!(parse_size_option("size=64K"), Some(64 << 10));
+        assert_eq!(parse_size_option("size=2m"), Some(2 << 20));
+        assert_eq!(parse_size_option("size=1G"), Some(1 << 30));
+        assert_eq!(parse_size_option("size="), None);
+        assert_eq!(parse_size_option("size=K"), None);
+        assert_eq!(parse_size_option("64K"), None);
+        assert_eq!(parse_size_option("size=99999999999999999999G"), None);
+    }
+
+    #[test_case]
+    fn statfs_reports_space_left() {
+        let mnt = mount_tmpfs("tmpfs_statfs", "size=4K");
+
+        let stats = vfs::get().statfs(&mnt).unwrap();
+        assert_eq!(stats.used_blocks, 0);
+        assert_eq!(stats.free_blocks, Some(8));
+        assert_eq!(stats.total_blocks(), Some(8));
+
+        assert_eq!(write_file(&format!("{}/a", mnt), &[1; 1000]), Ok(1000));
+
+        // Only whole blocks of the space which is left count as free
+        let stats = vfs::get().statfs(&mnt).unwrap();
+        assert_eq!(stats.used_blocks, 2);
+        assert_eq!(stats.free_blocks, Some(6));
+
+        vfs::get().unmount(&mnt).unwrap();
+    }
+
+    #[test_case]
+    fn writes_up_to_the_limit() {
+        let mnt = mount_tmpfs("tmpfs_limit_exact", "size=1K");
+
+        assert_eq!(write_file(&format!("{}/a", mnt), &[1; 1000]), Ok(1000));
+        assert_eq!(write_file(&format!("{}/b", mnt), &[2; 24]), Ok(24));
+
+        let stats = vfs::get().statfs(&mnt).unwrap();
+        assert_eq!(stats.free_blocks, Some(0));
+
+        vfs::get().unmount(&mnt).unwrap();
+    }
+
+    #[test_case]
+    fn rejects_writes_past_the_limit() {
+        let mnt = mount_tmpfs("tmpfs_limit_past", "size=1K");
+        let path = format!("{}/a", mnt);
+
+        assert_eq!(write_file(&path, &[1; 1025]), Err(IoError::NoSpaceLeft));
+
+        // The failed write takes no space, so the whole limit is still left
+        assert_eq!(write_file(&path, &[1; 1024]), Ok(1024));
+        assert_eq!(
+            write_file(&format!("{}/b", mnt), &[2]),
+            Err(IoError::NoSpaceLeft)
+        );
+
+        vfs::get().unmount(&mnt).unwrap();
+    }
+}
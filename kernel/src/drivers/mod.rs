@@ -1,3 +1,4 @@
 pub mod block;
 pub mod char;
 pub mod fs;
+pub mod rtc;
@@ -0,0 +1,152 @@
+//! Driver for the real-time clock of the CMOS
+//!
+//! The RTC keeps the wall-clock time while the machine is off. Its registers
+//! are read through an index port and a data port. Depending on how the
+//! firmware set it up, the values are either binary or BCD, and the hour is in
+//! either 12 or 24 hour format, which is described by status register B.
+
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+use crate::util::time::DateTime;
+
+const CMOS_INDEX: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REGISTER_SECOND: u8 = 0x00;
+const REGISTER_MINUTE: u8 = 0x02;
+const REGISTER_HOUR: u8 = 0x04;
+const REGISTER_DAY: u8 = 0x07;
+const REGISTER_MONTH: u8 = 0x08;
+const REGISTER_YEAR: u8 = 0x09;
+const REGISTER_STATUS_A: u8 = 0x0A;
+const REGISTER_STATUS_B: u8 = 0x0B;
+
+/// Set in status register A while the RTC is updating its registers
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 0x80;
+/// Set in status register B if the hour is in 24 hour format
+const STATUS_B_24_HOUR: u8 = 0x02;
+/// Set in status register B if values are binary rather than BCD
+const STATUS_B_BINARY: u8 = 0x04;
+
+/// Set in the hour register for times after noon in 12 hour format
+const HOUR_PM: u8 = 0x80;
+
+/// The RTC only stores two digits of the year
+///
+/// FIXME: read the century register from the ACPI FADT once there is ACPI
+/// support
+const CENTURY: u16 = 2000;
+
+/// Selecting a register and reading it must not be interleaved with another
+/// access
+static CMOS: Mutex<Cmos> = Mutex::new(Cmos::new());
+
+struct Cmos {
+    index: Port<u8>,
+    data: Port<u8>,
+}
+
+/// The registers as they were read, before decoding
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+}
+
+impl Cmos {
+    const fn new() -> Self {
+        Self {
+            index: Port::new(CMOS_INDEX),
+            data: Port::new(CMOS_DATA),
+        }
+    }
+
+    fn read(&mut self, register: u8) -> u8 {
+        // SAFETY: both ports belong to the CMOS, which is only accessed
+        // through this struct, and reading a clock register has no side
+        // effects
+        unsafe {
+            self.index.write(register);
+            self.data.read()
+        }
+    }
+
+    fn update_in_progress(&mut self) -> bool {
+        self.read(REGISTER_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0
+    }
+
+    /// Reads the time registers once an update isn't in progress. An update
+    /// can still start while reading them.
+    fn read_raw(&mut self) -> RawTime {
+        while self.update_in_progress() {
+            core::hint::spin_loop();
+        }
+
+        RawTime {
+            second: self.read(REGISTER_SECOND),
+            minute: self.read(REGISTER_MINUTE),
+            hour: self.read(REGISTER_HOUR),
+            day: self.read(REGISTER_DAY),
+            month: self.read(REGISTER_MONTH),
+            year: self.read(REGISTER_YEAR),
+        }
+    }
+}
+
+fn bcd_to_binary(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// Reads the current date and time from the RTC
+pub fn now() -> DateTime {
+    let (raw, status_b) = x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut cmos = CMOS.lock();
+
+        // Read until two reads in a row agree so that an update which started
+        // in the middle of a read can't produce a torn time
+        let mut raw = cmos.read_raw();
+        loop {
+            let again = cmos.read_raw();
+            if again == raw {
+                break;
+            }
+
+            raw = again;
+        }
+
+        (raw, cmos.read(REGISTER_STATUS_B))
+    });
+
+    let decode = |value: u8| {
+        if status_b & STATUS_B_BINARY != 0 {
+            value
+        } else {
+            bcd_to_binary(value)
+        }
+    };
+
+    // The PM flag is not part of the BCD digits
+    let mut hour = decode(raw.hour & !HOUR_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12 AM is midnight and 12 PM is noon
+        hour %= 12;
+
+        if raw.hour & HOUR_PM != 0 {
+            hour += 12;
+        }
+    }
+
+    DateTime {
+        year: CENTURY + decode(raw.year) as u16,
+        month: decode(raw.month),
+        day: decode(raw.day),
+        hour,
+        minute: decode(raw.minute),
+        second: decode(raw.second),
+    }
+}
@@ -0,0 +1,532 @@
+//! A minimal loader for statically linked ELF64 executables
+//!
+//! Only the parts of the format required to get a program into memory are
+//! supported: the file header and the `PT_LOAD` program headers. Section
+//! headers, relocations and dynamic linking are not supported.
+
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use x86_64::{
+    VirtAddr,
+    registers::model_specific::{Efer, EferFlags},
+    structures::paging::{FrameAllocator, Mapper, OffsetPageTable, Page, PageTableFlags, Size4KiB},
+};
+
+use crate::{
+    memory::{self, BootInfoFrameAllocator},
+    user::USER_SPACE_END,
+};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+const ELF_CLASS_64: u8 = 2;
+const ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+const ELF_TYPE_EXECUTABLE: u16 = 2;
+const ELF_MACHINE_X86_64: u16 = 0x3e;
+
+const FILE_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const PF_X: u32 = 0x1;
+const PF_W: u32 = 0x2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// The file does not start with the ELF magic bytes
+    InvalidMagic,
+    /// The file is not a 64-bit ELF file
+    UnsupportedClass,
+    /// The file is not encoded in little endian
+    UnsupportedEndianness,
+    /// The file was not compiled for x86-64
+    UnsupportedMachine,
+    /// The file is not an executable (i.e. it is a shared object or a
+    /// relocatable object file)
+    UnsupportedType,
+    /// The executable requires an interpreter or dynamic linking
+    DynamicallyLinked,
+    /// A header or segment points past the end of the file
+    Truncated,
+    /// A loadable segment has an invalid address or size
+    InvalidSegment,
+    /// The entry point does not lie within an executable segment
+    InvalidEntryPoint,
+    /// A segment overlaps memory which is already mapped
+    AddressInUse,
+    /// There were not enough physical frames to load the executable
+    OutOfMemory,
+}
+
+impl Display for ElfError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let message = match self {
+            ElfError::InvalidMagic => "not an ELF file",
+            ElfError::UnsupportedClass => "only 64-bit ELF files are supported",
+            ElfError::UnsupportedEndianness => "only little endian ELF files are supported",
+            ElfError::UnsupportedMachine => "not an x86-64 executable",
+            ElfError::UnsupportedType => "not an executable ELF file",
+            ElfError::DynamicallyLinked => "dynamically linked executables are not supported",
+            ElfError::Truncated => "ELF file is truncated",
+            ElfError::InvalidSegment => "ELF file contains an invalid segment",
+            ElfError::InvalidEntryPoint => "entry point is not in an executable segment",
+            ElfError::AddressInUse => "segment overlaps memory which is already in use",
+            ElfError::OutOfMemory => "out of memory",
+        };
+
+        write!(f, "{}", message)
+    }
+}
+
+/// Returns true if the provided image starts with the ELF magic bytes
+pub fn is_elf(image: &[u8]) -> bool {
+    image.starts_with(&ELF_MAGIC)
+}
+
+/// An executable which has been mapped into the current address space,
+/// accessible from ring 3. The mapped pages are released once this is dropped.
+pub struct ElfImage {
+    entry: VirtAddr,
+    pages: Vec<Page<Size4KiB>>,
+}
+
+impl ElfImage {
+    /// The address of the first instruction of the program
+    pub fn entry(&self) -> VirtAddr {
+        self.entry
+    }
+}
+
+impl Drop for ElfImage {
+    fn drop(&mut self) {
+        // FIXME: the frames backing these pages are leaked since the frame
+        // allocator does not support freeing yet
+        memory::with_mapper(|mapper, _| {
+            for page in self.pages.drain(..) {
+                if let Ok((_, flush)) = mapper.unmap(page) {
+                    flush.flush();
+                }
+            }
+        });
+    }
+}
+
+struct FileHeader {
+    entry: u64,
+    program_header_offset: u64,
+    program_header_size: u16,
+    program_header_count: u16,
+}
+
+struct ProgramHeader {
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    virtual_address: u64,
+    file_size: u64,
+    memory_size: u64,
+}
+
+/// Validates the provided ELF executable and maps its loadable segments at the
+/// virtual addresses they were linked at. Segments are zero filled past the end
+/// of their file contents (i.e. `.bss`).
+pub fn load(image: &[u8]) -> Result<ElfImage, ElfError> {
+    let header = parse_file_header(image)?;
+    let program_headers = parse_program_headers(image, &header)?;
+
+    if program_headers
+        .iter()
+        .any(|ph| ph.kind == PT_INTERP || ph.kind == PT_DYNAMIC)
+    {
+        return Err(ElfError::DynamicallyLinked);
+    }
+
+    let entry = VirtAddr::try_new(header.entry).map_err(|_| ElfError::InvalidEntryPoint)?;
+
+    let entry_is_executable = program_headers.iter().any(|ph| {
+        ph.kind == PT_LOAD
+            && ph.flags & PF_X != 0
+            && (ph.virtual_address..ph.virtual_address.saturating_add(ph.memory_size))
+                .contains(&header.entry)
+    });
+
+    if !entry_is_executable {
+        return Err(ElfError::InvalidEntryPoint);
+    }
+
+    // Pages are recorded as soon as they are mapped so that they are released
+    // if a later segment fails to load
+    let mut loaded = ElfImage {
+        entry,
+        pages: Vec::new(),
+    };
+
+    // The segment flags of every page, in the same order as the pages. A
+    // page which is shared by two segments gets the flags of both.
+    let mut page_flags = Vec::new();
+
+    for ph in program_headers.iter().filter(|ph| ph.kind == PT_LOAD) {
+        load_segment(&mut loaded, &mut page_flags, image, ph)?;
+    }
+
+    apply_permissions(&loaded.pages, &page_flags);
+
+    Ok(loaded)
+}
+
+fn parse_file_header(image: &[u8]) -> Result<FileHeader, ElfError> {
+    if !is_elf(image) {
+        return Err(ElfError::InvalidMagic);
+    }
+
+    if image.len() < FILE_HEADER_SIZE {
+        return Err(ElfError::Truncated);
+    }
+
+    if image[4] != ELF_CLASS_64 {
+        return Err(ElfError::UnsupportedClass);
+    }
+
+    if image[5] != ELF_DATA_LITTLE_ENDIAN {
+        return Err(ElfError::UnsupportedEndianness);
+    }
+
+    if read_u16(image, 18)? != ELF_MACHINE_X86_64 {
+        return Err(ElfError::UnsupportedMachine);
+    }
+
+    if read_u16(image, 16)? != ELF_TYPE_EXECUTABLE {
+        return Err(ElfError::UnsupportedType);
+    }
+
+    Ok(FileHeader {
+        entry: read_u64(image, 24)?,
+        program_header_offset: read_u64(image, 32)?,
+        program_header_size: read_u16(image, 54)?,
+        program_header_count: read_u16(image, 56)?,
+    })
+}
+
+fn parse_program_headers(
+    image: &[u8],
+    header: &FileHeader,
+) -> Result<Vec<ProgramHeader>, ElfError> {
+    if (header.program_header_size as usize) < PROGRAM_HEADER_SIZE {
+        return Err(ElfError::Truncated);
+    }
+
+    (0..header.program_header_count as usize)
+        .map(|i| {
+            let base = (header.program_header_offset as usize)
+                .checked_add(i * header.program_header_size as usize)
+                .ok_or(ElfError::Truncated)?;
+
+            // The offset comes from the file, so it can be anywhere
+            let field = |offset: usize| base.checked_add(offset).ok_or(ElfError::Truncated);
+
+            Ok(ProgramHeader {
+                kind: read_u32(image, base)?,
+                flags: read_u32(image, field(4)?)?,
+                offset: read_u64(image, field(8)?)?,
+                virtual_address: read_u64(image, field(16)?)?,
+                file_size: read_u64(image, field(32)?)?,
+                memory_size: read_u64(image, field(40)?)?,
+            })
+        })
+        .collect()
+}
+
+/// Maps the pages for a single `PT_LOAD` segment and copies its contents from
+/// the file. The pages stay writable until [`apply_permissions`] is called, so
+/// that a later segment can still fill a page it shares with this one.
+fn load_segment(
+    loaded: &mut ElfImage,
+    page_flags: &mut Vec<u32>,
+    image: &[u8],
+    ph: &ProgramHeader,
+) -> Result<(), ElfError> {
+    if ph.memory_size == 0 {
+        return Ok(());
+    }
+
+    if ph.file_size > ph.memory_size {
+        return Err(ElfError::InvalidSegment);
+    }
+
+    let file_end = ph
+        .offset
+        .checked_add(ph.file_size)
+        .ok_or(ElfError::Truncated)?;
+    let data = image
+        .get(ph.offset as usize..file_end as usize)
+        .ok_or(ElfError::Truncated)?;
+
+    let start = VirtAddr::try_new(ph.virtual_address).map_err(|_| ElfError::InvalidSegment)?;
+    let end = ph
+        .virtual_address
+        .checked_add(ph.memory_size - 1)
+        .and_then(|end| VirtAddr::try_new(end).ok())
+        .ok_or(ElfError::InvalidSegment)?;
+
+    // Programs run in ring 3, so they must stay out of the kernel's half
+    if end.as_u64() >= USER_SPACE_END {
+        return Err(ElfError::InvalidSegment);
+    }
+
+    // The unmapped part of the heap looks free to the page tables
+    if memory::is_reserved(start, end + 1u64) {
+        return Err(ElfError::AddressInUse);
+    }
+
+    let pages = Page::<Size4KiB>::range_inclusive(
+        Page::containing_address(start),
+        Page::containing_address(end),
+    );
+
+    /* Map the pages as writable so that we can fill them */
+
+    for page in pages {
+        // Linkers pack the end of one segment and the start of the next into
+        // the same page, which was already mapped (and zeroed) for the first
+        if let Some(i) = loaded.pages.iter().position(|p| *p == page) {
+            page_flags[i] |= ph.flags;
+            continue;
+        }
+
+        // The page tables are only locked while mapping a single page. The
+        // list of pages is kept outside of the lock since growing it could
+        // fault on a heap page which is mapped on demand, which can't be
+        // mapped while the page tables are locked.
+        memory::with_mapper(|mapper, frame_allocator| map_page(page, mapper, frame_allocator))?;
+
+        loaded.pages.push(page);
+        page_flags.push(ph.flags);
+
+        // Fresh frames contain whatever was left in physical memory, so zero
+        // them to initialize .bss
+        unsafe {
+            core::ptr::write_bytes(page.start_address().as_mut_ptr::<u8>(), 0, 4096);
+        }
+    }
+
+    unsafe {
+        core::ptr::copy_nonoverlapping(data.as_ptr(), start.as_mut_ptr::<u8>(), data.len());
+    }
+
+    Ok(())
+}
+
+/// Applies the permissions requested by the segments to their pages, given
+/// the combined segment flags of each page
+fn apply_permissions(pages: &[Page<Size4KiB>], page_flags: &[u32]) {
+    // The no-execute bit is reserved unless it has been enabled in EFER
+    let no_execute = Efer::read().contains(EferFlags::NO_EXECUTE_ENABLE);
+
+    memory::with_mapper(|mapper, _| {
+        for (page, segment_flags) in pages.iter().zip(page_flags) {
+            let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+
+            if segment_flags & PF_W != 0 {
+                flags |= PageTableFlags::WRITABLE;
+            }
+
+            if segment_flags & PF_X == 0 && no_execute {
+                flags |= PageTableFlags::NO_EXECUTE;
+            }
+
+            unsafe {
+                mapper
+                    .update_flags(*page, flags)
+                    .expect("segment pages were just mapped")
+                    .flush();
+            }
+        }
+    });
+}
+
+/// Maps a fresh frame at the given page as writable and accessible from ring 3.
+/// Fails if something else already uses the page.
+fn map_page(
+    page: Page<Size4KiB>,
+    mapper: &mut OffsetPageTable<'static>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+) -> Result<(), ElfError> {
+    if mapper.translate_page(page).is_ok() {
+        return Err(ElfError::AddressInUse);
+    }
+
+    let frame = frame_allocator
+        .allocate_frame()
+        .ok_or(ElfError::OutOfMemory)?;
+
+    let flags =
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    unsafe {
+        mapper
+            .map_to(page, frame, flags, frame_allocator)
+            .map_err(|_| ElfError::OutOfMemory)?
+            .flush();
+    }
+
+    Ok(())
+}
+
+/// Reads `N` bytes at `offset`, failing if any of them lie past the end of the
+/// data (or past the end of the address space)
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N], ElfError> {
+    offset
+        .checked_add(N)
+        .and_then(|end| data.get(offset..end))
+        .map(|b| b.try_into().unwrap())
+        .ok_or(ElfError::Truncated)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ElfError> {
+    read_bytes(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ElfError> {
+    read_bytes(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, ElfError> {
+    read_bytes(data, offset).map(u64::from_le_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    /// Where the test executables are loaded. Nothing else is mapped there.
+    const LOAD_ADDRESS: u64 = 0x6000_0000;
+
+    /// Builds an ELF file header for an x86-64 executable
+    fn file_header(entry: u64, program_header_offset: u64, program_header_count: u16) -> Vec<u8> {
+        let mut image = vec![0; FILE_HEADER_SIZE];
+
+        image[..4].copy_from_slice(&ELF_MAGIC);
+        image[4] = ELF_CLASS_64;
+        image[5] = ELF_DATA_LITTLE_ENDIAN;
+        image[6] = 1;
+        image[16..18].copy_from_slice(&ELF_TYPE_EXECUTABLE.to_le_bytes());
+        image[18..20].copy_from_slice(&ELF_MACHINE_X86_64.to_le_bytes());
+        image[24..32].copy_from_slice(&entry.to_le_bytes());
+        image[32..40].copy_from_slice(&program_header_offset.to_le_bytes());
+        image[54..56].copy_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        image[56..58].copy_from_slice(&program_header_count.to_le_bytes());
+
+        image
+    }
+
+    /// Appends a `PT_LOAD` program header
+    fn push_load_header(
+        image: &mut Vec<u8>,
+        flags: u32,
+        offset: u64,
+        address: u64,
+        file_size: u64,
+        memory_size: u64,
+    ) {
+        image.extend_from_slice(&PT_LOAD.to_le_bytes());
+        image.extend_from_slice(&flags.to_le_bytes());
+        image.extend_from_slice(&offset.to_le_bytes());
+        image.extend_from_slice(&address.to_le_bytes());
+        // Physical address
+        image.extend_from_slice(&address.to_le_bytes());
+        image.extend_from_slice(&file_size.to_le_bytes());
+        image.extend_from_slice(&memory_size.to_le_bytes());
+        // Alignment
+        image.extend_from_slice(&4096u64.to_le_bytes());
+    }
+
+    #[test_case]
+    fn rejects_other_files() {
+        assert_eq!(load(b"#!/bin/sh").err(), Some(ElfError::InvalidMagic));
+
+        let mut image = file_header(0, 0, 0);
+        image[4] = 1;
+        assert_eq!(load(&image).err(), Some(ElfError::UnsupportedClass));
+
+        let mut image = file_header(0, 0, 0);
+        image[18] = 0x03;
+        assert_eq!(load(&image).err(), Some(ElfError::UnsupportedMachine));
+    }
+
+    #[test_case]
+    fn rejects_truncated_header() {
+        let image = file_header(0, 0, 0);
+
+        assert_eq!(load(&image[..20]).err(), Some(ElfError::Truncated));
+    }
+
+    #[test_case]
+    fn rejects_program_headers_past_end() {
+        // Past the end of the file
+        let image = file_header(LOAD_ADDRESS, FILE_HEADER_SIZE as u64, 1);
+        assert_eq!(load(&image).err(), Some(ElfError::Truncated));
+
+        // So close to the end of the address space that the offsets of the
+        // fields overflow
+        let image = file_header(LOAD_ADDRESS, usize::MAX as u64 - 2, 1);
+        assert_eq!(load(&image).err(), Some(ElfError::Truncated));
+    }
+
+    #[test_case]
+    fn loads_static_executable() {
+        let code = [0xEB, 0xFE]; // jmp $
+        let code_offset = FILE_HEADER_SIZE + PROGRAM_HEADER_SIZE;
+        let entry = LOAD_ADDRESS + code_offset as u64;
+
+        // The whole file is loaded, followed by a page of .bss
+        let file_size = (code_offset + code.len()) as u64;
+        let mut image = file_header(entry, FILE_HEADER_SIZE as u64, 1);
+        push_load_header(
+            &mut image,
+            PF_X,
+            0,
+            LOAD_ADDRESS,
+            file_size,
+            file_size + 4096,
+        );
+        image.extend_from_slice(&code);
+
+        let loaded = load(&image).expect("failed to load the executable");
+        assert_eq!(loaded.entry().as_u64(), entry);
+
+        // SAFETY: the segment is mapped until `loaded` is dropped
+        let memory = unsafe {
+            core::slice::from_raw_parts(LOAD_ADDRESS as *const u8, file_size as usize + 4096)
+        };
+        assert_eq!(&memory[..image.len()], &image[..]);
+        assert!(memory[image.len()..].iter().all(|&b| b == 0));
+    }
+
+    #[test_case]
+    fn loads_segments_sharing_a_page() {
+        let code_offset = FILE_HEADER_SIZE + 2 * PROGRAM_HEADER_SIZE;
+        let entry = LOAD_ADDRESS + code_offset as u64;
+        let text_size = (code_offset + 2) as u64;
+
+        // .data starts right after .text, in the same page
+        let mut image = file_header(entry, FILE_HEADER_SIZE as u64, 2);
+        push_load_header(&mut image, PF_X, 0, LOAD_ADDRESS, text_size, text_size);
+        push_load_header(&mut image, PF_W, text_size, LOAD_ADDRESS + text_size, 4, 4);
+        image.extend_from_slice(&[0xEB, 0xFE]);
+        image.extend_from_slice(&[1, 2, 3, 4]);
+
+        let loaded = load(&image).expect("failed to load the executable");
+
+        // SAFETY: both segments are mapped until `loaded` is dropped
+        let data =
+            unsafe { core::slice::from_raw_parts((LOAD_ADDRESS + text_size) as *const u8, 4) };
+        assert_eq!(data, [1, 2, 3, 4]);
+
+        drop(loaded);
+    }
+}
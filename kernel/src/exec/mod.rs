@@ -0,0 +1,211 @@
+//! This module contains the program loader which is used to run binaries
+//! stored in the file system.
+//!
+//! A program is either a statically linked ELF64 executable (see [`elf`]) or a
+//! flat, position independent binary whose entry point is the first byte of
+//! the file. ELF executables run in ring 3 (see [`user`]) and can make system
+//! calls. Flat binaries run in place in ring 0 on a dedicated stack, since they
+//! are loaded into kernel memory. Both share the kernel's address space. The
+//! entry point is called using the System V calling convention with the
+//! following signature:
+//!
+//! ```ignore
+//! extern "sysv64" fn(argc: usize, argv: *const *const u8, envp: *const *const u8) -> i64
+//! ```
+//!
+//! `argv` points to `argc` NUL terminated strings followed by a null pointer,
+//! where `argv[0]` is the path the program was loaded from. `envp` points to a
+//! null terminated array of `KEY=VALUE` strings, which is currently always
+//! empty. The returned value is the exit status of the program. Programs in
+//! ring 3 can also end by making the `exit` system call.
+
+use alloc::{vec, vec::Vec};
+use core::fmt::Display;
+
+use elf::ElfError;
+
+use crate::{
+    fs::{
+        FileMode, FsNodeKind,
+        vfs::{self, IoError},
+    },
+    user::{self, UserError},
+};
+
+pub mod elf;
+
+/// The largest program image which will be loaded into memory
+const MAX_IMAGE_SIZE: usize = 64 * 1024; // 64 KiB
+
+/// The size of the stack which is allocated for each program
+const STACK_SIZE: usize = 16 * 1024; // 16 KiB
+
+#[derive(Debug)]
+pub enum ExecError {
+    /// The program could not be read from the file system
+    Io(IoError),
+    /// The program file was empty and contains no entry point
+    EmptyImage,
+    /// The program file is larger than [`MAX_IMAGE_SIZE`]
+    ImageTooLarge,
+    /// The program is an ELF file which could not be loaded
+    Elf(ElfError),
+    /// The program could not be set up to run in ring 3
+    User(UserError),
+}
+
+impl From<IoError> for ExecError {
+    fn from(value: IoError) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ElfError> for ExecError {
+    fn from(value: ElfError) -> Self {
+        Self::Elf(value)
+    }
+}
+
+impl From<UserError> for ExecError {
+    fn from(value: UserError) -> Self {
+        Self::User(value)
+    }
+}
+
+impl Display for ExecError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ExecError::Io(e) => write!(f, "{}", e),
+            ExecError::EmptyImage => write!(f, "Empty executable"),
+            ExecError::ImageTooLarge => write!(f, "Executable is too large"),
+            ExecError::Elf(e) => write!(f, "{}", e),
+            ExecError::User(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Loads the program at the given path and runs it to completion with the
+/// provided arguments, returning its exit status.
+pub fn exec(path: &str, args: &[&str]) -> Result<i64, ExecError> {
+    let image = read_image(path)?;
+
+    if image.is_empty() {
+        return Err(ExecError::EmptyImage);
+    }
+
+    // ELF executables are mapped at their linked addresses and must stay
+    // mapped until the program exits
+    if elf::is_elf(&image) {
+        let elf_image = elf::load(&image)?;
+        return Ok(user::run(elf_image.entry(), path, args)?);
+    }
+
+    // Flat binaries run in place
+    let entry = image.as_ptr() as usize;
+
+    /* Build the argument and environment vectors */
+
+    // Keep the backing strings alive until the program returns since argv only
+    // holds pointers into them
+    let strings = core::iter::once(path)
+        .chain(args.iter().copied())
+        .map(|arg| {
+            let mut s = Vec::with_capacity(arg.len() + 1);
+            s.extend_from_slice(arg.as_bytes());
+            s.push(0);
+            s
+        })
+        .collect::<Vec<_>>();
+
+    let mut argv = strings
+        .iter()
+        .map(|s| s.as_ptr())
+        .collect::<Vec<*const u8>>();
+    argv.push(core::ptr::null());
+
+    let envp = [core::ptr::null::<u8>()];
+
+    /* Allocate a stack and jump into the program */
+
+    let stack = vec![0u8; STACK_SIZE];
+    // The System V ABI requires the stack to be 16 byte aligned at the call
+    let stack_top = (stack.as_ptr() as usize + STACK_SIZE) & !0xF;
+
+    // SAFETY: the image was loaded into memory that stays alive until the
+    // program returns. Beyond that we have to trust the program since it runs
+    // with full kernel privileges.
+    let status = unsafe {
+        call_on_stack(
+            entry,
+            stack_top,
+            strings.len(),
+            argv.as_ptr(),
+            envp.as_ptr(),
+        )
+    };
+
+    Ok(status)
+}
+
+/// Reads the entire contents of a regular file into memory
+fn read_image(path: &str) -> Result<Vec<u8>, ExecError> {
+    let entry = vfs::get().stat(path)?;
+
+    if entry.node.kind != FsNodeKind::File {
+        return Err(IoError::NotAFile.into());
+    }
+
+    let fd = vfs::get().open(path, FileMode::Read)?;
+
+    let mut image = Vec::new();
+    let mut buffer = [0u8; 512];
+
+    let result = loop {
+        match vfs::get().read(fd, &mut buffer) {
+            Ok(0) => break Ok(()),
+            Ok(n) if image.len() + n > MAX_IMAGE_SIZE => break Err(ExecError::ImageTooLarge),
+            Ok(n) => image.extend_from_slice(&buffer[..n]),
+            Err(e) => break Err(e.into()),
+        }
+    };
+
+    vfs::get().close(fd)?;
+    result.map(|_| image)
+}
+
+/// Switches to the provided stack and calls `entry` with the program arguments,
+/// restoring the original stack once it returns.
+///
+/// SAFETY: `entry` must point to executable code which follows the System V
+/// calling convention and `stack_top` must be the 16 byte aligned top of a
+/// stack which is large enough for the program.
+unsafe fn call_on_stack(
+    entry: usize,
+    stack_top: usize,
+    argc: usize,
+    argv: *const *const u8,
+    envp: *const *const u8,
+) -> i64 {
+    let status: i64;
+
+    unsafe {
+        core::arch::asm!(
+            // r12 is callee saved, so the kernel stack pointer stashed in it
+            // survives the call into the program
+            "mov r12, rsp",
+            "mov rsp, {stack_top}",
+            "call {entry}",
+            "mov rsp, r12",
+            stack_top = in(reg) stack_top,
+            entry = in(reg) entry,
+            in("rdi") argc,
+            in("rsi") argv,
+            in("rdx") envp,
+            out("r12") _,
+            lateout("rax") status,
+            clobber_abi("sysv64"),
+        );
+    }
+
+    status
+}
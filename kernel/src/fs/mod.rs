@@ -1,4 +1,4 @@
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{boxed::Box, string::String, sync::Arc};
 use core::{
     any::Any,
     fmt::Display,
@@ -8,7 +8,7 @@ use core::{
 use spin::Mutex;
 use vfs::{DirectoryEntry, DirectoryIterationContext, IoError, MountId};
 
-use crate::{drivers, vga};
+use crate::{drivers, util::time, vga};
 
 pub mod path;
 pub mod registry;
@@ -66,6 +66,35 @@ pub trait FileSystem: Send + Sync {
     /// Returns a pointer to a trait object which handles operations on
     /// Directory objects (usually self)
     fn directory_operations(&self) -> &dyn DirectoryOperations;
+
+    /// Writes any cached data and dirty nodes back to the backing device. File
+    /// systems which live entirely in memory have nothing to write.
+    fn sync(&self) -> Result<(), IoError> {
+        Ok(())
+    }
+
+    /// Reports how much space the file system uses and how much is left
+    fn statfs(&self) -> Result<FileSystemStats, IoError>;
+}
+
+/// Space usage of a mounted file system, counted in blocks
+#[derive(Debug, Clone, Copy)]
+pub struct FileSystemStats {
+    /// The size in bytes of the blocks which the other fields count
+    pub block_size: usize,
+    /// The number of blocks which are in use
+    pub used_blocks: usize,
+    /// The number of blocks which can still be allocated. None if the file
+    /// system has no fixed size (i.e. it lives on the heap).
+    pub free_blocks: Option<usize>,
+}
+
+impl FileSystemStats {
+    /// The total number of blocks, or None if the file system has no fixed
+    /// size
+    pub fn total_blocks(&self) -> Option<usize> {
+        self.free_blocks.map(|free| self.used_blocks + free)
+    }
 }
 
 pub struct FileSystemMetadata {
@@ -79,17 +108,31 @@ pub struct FileSystemMetadata {
     pub block_size: usize,
     /// The maximum file size which this file system supports
     pub max_file_size: usize,
+    /// The maximum length in bytes of a single entry name
+    pub max_name_len: usize,
     /// A pointer to the file system type driver
     pub file_system_type: Arc<dyn FileSystemType>,
 }
 
 bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct MountFlags: u32 {
         const READ = 0b00000001;
         const WRITE = 0b00000010;
     }
 }
 
+bitflags::bitflags! {
+    /// Changes how [`vfs::VirtualFileSystem::open_with_flags`] treats the path
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OpenFlags: u32 {
+        /// Fail with [`vfs::IoError::AlreadyExists`] instead of opening an
+        /// existing entry (including a symbolic link). Only the mutating modes
+        /// create files, so this has no effect on reads.
+        const EXCL = 0b00000001;
+    }
+}
+
 pub trait FsNodeOperations {
     /// Write a file system node back to the disk (after an operation has been
     /// performed on it)
@@ -116,8 +159,9 @@ pub trait FileOperations: Send + Sync {
         Ok(())
     }
 
-    /// Called when a a file cursor wants to be repositioned
-    fn seek(&self, file: &File, offset: usize) -> Result<usize, IoError> {
+    /// Called when a a file cursor wants to be repositioned. Receives the
+    /// current position of the cursor and returns the new one.
+    fn seek(&self, file: &File, position: usize, from: SeekFrom) -> Result<usize, IoError> {
         Err(IoError::OperationNotSupported)
     }
 
@@ -132,6 +176,18 @@ pub trait FileOperations: Send + Sync {
     fn write(&self, file: &File, offset: usize, buffer: &[u8]) -> Result<usize, IoError> {
         Err(IoError::OperationNotSupported)
     }
+
+    /// Called when the contents of a file should be cut off (or zero extended)
+    /// to the given length
+    fn truncate(&self, file: &File, length: usize) -> Result<(), IoError> {
+        Err(IoError::OperationNotSupported)
+    }
+
+    /// Returns the path which a symbolic link points to. Symbolic links are
+    /// never opened, so this receives the node rather than a file.
+    fn readlink(&self, node: &FsNode) -> Result<String, IoError> {
+        Err(IoError::OperationNotSupported)
+    }
 }
 
 /// A trait representing all operations which the VFS performs on directories
@@ -155,6 +211,29 @@ pub trait DirectoryOperations: Send + Sync {
         Err(IoError::OperationNotSupported)
     }
 
+    /// Creates a new symbolic link on disk which points to the target path and
+    /// allocates a new FsNodeId. The target is stored as is and doesn't need
+    /// to exist.
+    fn create_symlink(
+        &self,
+        _directory: &Arc<DirectoryEntry>,
+        _name: &str,
+        _target: &str,
+    ) -> Result<Arc<FsNode>, IoError> {
+        Err(IoError::OperationNotSupported)
+    }
+
+    /// Adds a new name for an existing node of the same file system to this
+    /// directory. The VFS takes care of the node's link count.
+    fn link(
+        &self,
+        _directory: &Arc<DirectoryEntry>,
+        _name: &str,
+        _node: &Arc<FsNode>,
+    ) -> Result<(), IoError> {
+        Err(IoError::OperationNotSupported)
+    }
+
     /// Removes a file in this directory from disk
     fn remove_file(&self, _parent: &Arc<DirectoryEntry>, _name: &str) -> Result<(), IoError> {
         Err(IoError::OperationNotSupported)
@@ -165,6 +244,18 @@ pub trait DirectoryOperations: Send + Sync {
         Err(IoError::OperationNotSupported)
     }
 
+    /// Moves an entry to a new name, possibly in a different directory of the
+    /// same file system. An existing entry with the new name is replaced.
+    fn rename(
+        &self,
+        _old_parent: &Arc<DirectoryEntry>,
+        _old_name: &str,
+        _new_parent: &Arc<DirectoryEntry>,
+        _new_name: &str,
+    ) -> Result<(), IoError> {
+        Err(IoError::OperationNotSupported)
+    }
+
     /// Looks up an FsNode by name in this directory
     fn lookup(
         &self,
@@ -203,8 +294,10 @@ pub(crate) use impl_fs_ops_for_self;
 /// A generic, type erased VFS node. The combination of the id and mount_id
 /// uniquely identify this node within the VFS.
 ///
-/// FIXME: centralize the creation of these objects so that we can keep track of
-/// them without creating duplicates
+/// File systems should create nodes with [`FsNode::new`].
+///
+/// FIXME: keep track of the nodes created in [`FsNode::new`] so that we don't
+/// create duplicates
 #[derive(Debug)]
 pub struct FsNode {
     /// The unique identifier which is used to index the backing file system
@@ -236,11 +329,65 @@ pub struct FsNodeMetadata {
     pub link_count: usize,
     /// The current size of the file or directory
     pub size: usize,
+    /// Unix-style permission bits (i.e. `0o644`). Everything belongs to
+    /// `root`, so only the owner bits are enforced for now.
+    pub mode: u16,
     pub accessed_at: u64,
     pub created_at: u64,
     pub modified_at: u64,
 }
 
+impl FsNodeMetadata {
+    /// Creates the metadata for a node of the given kind which is being created
+    /// right now. All file systems should use this rather than filling in the
+    /// fields by hand so that new nodes are consistent across file systems.
+    pub fn new_now(kind: FsNodeKind) -> Self {
+        let now = time::now();
+
+        Self {
+            dirty: false,
+            link_count: 1,
+            size: 0,
+            mode: kind.default_mode(),
+            accessed_at: now,
+            created_at: now,
+            modified_at: now,
+        }
+    }
+
+    /// Records that the contents of the node were just read
+    pub fn mark_accessed(&mut self) {
+        self.accessed_at = time::now();
+        self.dirty = true;
+    }
+
+    /// Records that the contents of the node were just changed. Changing the
+    /// contents also counts as accessing them.
+    pub fn mark_modified(&mut self) {
+        let now = time::now();
+
+        self.accessed_at = now;
+        self.modified_at = now;
+        self.dirty = true;
+    }
+
+    /// Moves the chosen timestamps to the current time without the contents
+    /// being accessed or changed
+    pub fn touch(&mut self, accessed: bool, modified: bool) {
+        let now = time::now();
+
+        if accessed {
+            self.accessed_at = now;
+        }
+
+        if modified {
+            self.modified_at = now;
+        }
+
+        self.dirty = true;
+    }
+}
+
 #[derive(Debug)]
 pub struct FsNodeLock;
 
@@ -253,6 +400,24 @@ impl PartialEq for FsNode {
 }
 
 impl FsNode {
+    /// Creates a node which was just created (or loaded) by a file system. The
+    /// metadata is initialized with [`FsNodeMetadata::new_now`].
+    pub fn new(
+        id: FsNodeId,
+        mount_id: MountId,
+        kind: FsNodeKind,
+        private_data: Option<Box<dyn Any + Send + Sync>>,
+    ) -> Self {
+        Self {
+            id,
+            mount_id,
+            kind,
+            metadata: Mutex::new(FsNodeMetadata::new_now(kind)),
+            structure_lock: Mutex::new(FsNodeLock),
+            private_data,
+        }
+    }
+
     #[track_caller]
     pub fn data_as<T: 'static>(&self) -> &T {
         self.private_data
@@ -279,11 +444,13 @@ impl FsNode {
         meta.link_count += 1;
     }
 
-    pub fn decrement_link_count(&self) {
+    /// Drops one reference to the node. Returns true if it was the last one,
+    /// in which case the node should be evicted from the disk.
+    pub fn decrement_link_count(&self) -> bool {
         let mut meta = self.metadata.lock();
         meta.link_count -= 1;
 
-        // FIXME: check link count and remove or add to removal list?
+        meta.link_count == 0
     }
 }
 
@@ -308,6 +475,7 @@ pub enum FsNodeKind {
     File,
     CharDevice,
     BlockDevice,
+    Symlink,
 }
 
 impl Display for FsNodeKind {
@@ -319,24 +487,81 @@ impl Display for FsNodeKind {
                 FsNodeKind::Directory => "d",
                 FsNodeKind::File => "-",
                 FsNodeKind::CharDevice => "c",
-                FsNodeKind::BlockDevice => "d",
+                FsNodeKind::BlockDevice => "b",
+                FsNodeKind::Symlink => "l",
             }
         )
     }
 }
 
 impl FsNodeKind {
+    /// A human readable name for the kind of node (i.e. for `stat`)
+    pub fn description(self) -> &'static str {
+        match self {
+            FsNodeKind::Directory => "directory",
+            FsNodeKind::File => "regular file",
+            FsNodeKind::CharDevice => "character special file",
+            FsNodeKind::BlockDevice => "block special file",
+            FsNodeKind::Symlink => "symbolic link",
+        }
+    }
+
+    /// The permission bits which new nodes of this kind are created with.
+    /// Character devices like `/dev/null` are conventionally readable and
+    /// writable by everyone.
+    pub fn default_mode(self) -> u16 {
+        match self {
+            FsNodeKind::Directory => 0o755,
+            FsNodeKind::File => 0o644,
+            FsNodeKind::CharDevice => 0o666,
+            FsNodeKind::BlockDevice => 0o660,
+            // The permissions of the target apply, so links are created with
+            // every bit set
+            FsNodeKind::Symlink => 0o777,
+        }
+    }
+
+    /// The color which entries of this kind are listed with. Character and
+    /// block devices get different colors so that they can be told apart
+    /// without `ls -l`.
     pub fn color_code(self) -> vga::ColorCode {
         match self {
             FsNodeKind::Directory => vga::Color::LightBlue,
             FsNodeKind::File => vga::Color::White,
             FsNodeKind::CharDevice => vga::Color::Yellow,
             FsNodeKind::BlockDevice => vga::Color::LightCyan,
+            FsNodeKind::Symlink => vga::Color::Cyan,
         }
         .into()
     }
 }
 
+/// The bits of [`FsNodeMetadata::mode`] which can be set
+pub const MODE_MASK: u16 = 0o777;
+
+/// Permission bits of the owner of a node
+pub const MODE_OWNER_READ: u16 = 0o400;
+pub const MODE_OWNER_WRITE: u16 = 0o200;
+
+/// Displays permission bits the way `ls -l` does (i.e. `rw-r--r--`)
+#[derive(Debug, Clone, Copy)]
+pub struct Permissions(pub u16);
+
+impl Display for Permissions {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // Owner, group and others, from the highest bits to the lowest
+        for shift in [6, 3, 0] {
+            let bits = self.0 >> shift;
+
+            for (mask, c) in [(0o4, 'r'), (0o2, 'w'), (0o1, 'x')] {
+                write!(f, "{}", if bits & mask != 0 { c } else { '-' })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 /// Represents an opened file
 pub struct File {
     /// The backing VFS node which this file is an opened instance of
@@ -372,6 +597,33 @@ pub enum FileMode {
     Append,
 }
 
+/// Describes where a file cursor should be moved to relative to some point in
+/// the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    /// An absolute offset from the start of the file
+    Start(usize),
+    /// An offset from the current position of the cursor
+    Current(isize),
+    /// An offset from the end of the file
+    End(isize),
+}
+
+impl SeekFrom {
+    /// Computes the new cursor position given the current position and the
+    /// length of the file. Seeking past the end of the file is allowed, but
+    /// seeking before the start is not.
+    pub fn resolve(self, position: usize, length: usize) -> Result<usize, IoError> {
+        let (base, offset) = match self {
+            SeekFrom::Start(offset) => return Ok(offset),
+            SeekFrom::Current(offset) => (position, offset),
+            SeekFrom::End(offset) => (length, offset),
+        };
+
+        base.checked_add_signed(offset).ok_or(IoError::InvalidSeek)
+    }
+}
+
 impl FileMode {
     pub fn is_mutating(self) -> bool {
         match self {
@@ -419,3 +671,19 @@ pub fn init() {
     drivers::fs::init().expect("Failed to initialize file system drivers");
     vfs::init();
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    #[test_case]
+    fn node_kinds_display_like_ls() {
+        assert_eq!(FsNodeKind::Directory.to_string(), "d");
+        assert_eq!(FsNodeKind::File.to_string(), "-");
+        assert_eq!(FsNodeKind::CharDevice.to_string(), "c");
+        assert_eq!(FsNodeKind::BlockDevice.to_string(), "b");
+        assert_eq!(FsNodeKind::Symlink.to_string(), "l");
+    }
+}
@@ -2,40 +2,169 @@ use alloc::{
     string::{String, ToString},
     vec::Vec,
 };
-use core::str::FromStr;
+use core::{
+    fmt::{self, Display},
+    str::FromStr,
+};
 
 const MAX_PATH_LENGTH: usize = 4096;
 
-#[derive(Debug, Default)]
+/// The longest name a single path segment can have. File systems may impose a
+/// stricter limit (see [`FileSystemMetadata::max_name_len`]).
+///
+/// [`FileSystemMetadata::max_name_len`]: super::FileSystemMetadata::max_name_len
+pub const MAX_NAME_LENGTH: usize = 255;
+
+#[derive(Debug, Default, Clone)]
 pub struct Path {
     segments: Vec<String>,
 }
 
 impl Path {
-    /// Returns true if this path starts with a "/"
+    /// Returns true if this path starts with a "/". An empty path is never
+    /// absolute.
     pub fn is_absolute(&self) -> bool {
-        self.segments.first().unwrap() == "/"
+        self.segments.first().is_some_and(|s| s == "/")
     }
 
     pub fn segments(&self) -> impl Iterator<Item = &str> {
         self.segments.iter().map(|s| s.as_str())
     }
+
+    /// Appends `other` to this path. If `other` is absolute it replaces the
+    /// path entirely. Segments like ".." are kept as is since they can only be
+    /// resolved against the file system.
+    ///
+    /// NOTE: the result is not checked against [`MAX_PATH_LENGTH`] or
+    /// [`MAX_NAME_LENGTH`]. That happens when it is parsed again by the VFS.
+    pub fn join(&self, other: &str) -> Path {
+        let rest = other.trim_start_matches('/');
+
+        let mut segments = if other.starts_with('/') {
+            Vec::from(["/".to_string()])
+        } else {
+            self.without_trailing_slash().to_vec()
+        };
+
+        if !rest.is_empty() {
+            segments.extend(rest.split('/').map(ToString::to_string));
+        }
+
+        Self { segments }
+    }
+
+    /// Returns the path without its last segment, or None if there is nothing
+    /// left (i.e. for "/" or a single relative name). This is purely lexical,
+    /// so the parent of "a/.." is "a".
+    pub fn parent(&self) -> Option<Path> {
+        let segments = match self.without_trailing_slash() {
+            [] => return None,
+            [.., last] if last == "/" => return None,
+            [rest @ .., _] => rest,
+        };
+
+        if segments.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            segments: segments.to_vec(),
+        })
+    }
+
+    /// Returns the last segment of the path, or None if the path is the root
+    /// directory or ends in ".."
+    pub fn file_name(&self) -> Option<&str> {
+        match self.without_trailing_slash().last()?.as_str() {
+            "/" | ".." => None,
+            name => Some(name),
+        }
+    }
+
+    /// Returns an equivalent path without any ".", ".." or empty segments.
+    /// ".." is resolved lexically by removing the segment before it, and the
+    /// root directory is its own parent. Leading ".." segments of a relative
+    /// path are kept since they depend on the working directory.
+    ///
+    /// A relative path which normalizes to nothing becomes ".".
+    ///
+    /// NOTE: this is only equivalent to resolving the path if none of its
+    /// segments are symbolic links, since ".." after a link goes to the parent
+    /// of the link's target.
+    pub fn normalize(&self) -> Path {
+        let absolute = self.is_absolute();
+        let mut segments: Vec<String> = Vec::new();
+
+        if absolute {
+            segments.push("/".to_string());
+        }
+
+        for segment in self.segments().skip(absolute as usize) {
+            match segment {
+                "" | "." => {}
+                ".." => match segments.last().map(String::as_str) {
+                    Some("/") => {}
+                    None | Some("..") => segments.push("..".to_string()),
+                    Some(_) => {
+                        segments.pop();
+                    }
+                },
+                name => segments.push(name.to_string()),
+            }
+        }
+
+        if segments.is_empty() {
+            segments.push(".".to_string());
+        }
+
+        Self { segments }
+    }
+
+    /// Returns the segments without the empty one left by a trailing slash
+    fn without_trailing_slash(&self) -> &[String] {
+        match self.segments.as_slice() {
+            [rest @ .., last] if !rest.is_empty() && last.is_empty() => rest,
+            segments => segments,
+        }
+    }
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut segments = self.segments();
+
+        if self.is_absolute() {
+            f.write_str("/")?;
+            segments.next();
+        }
+
+        for (i, segment) in segments.enumerate() {
+            if i > 0 {
+                f.write_str("/")?;
+            }
+
+            f.write_str(segment)?;
+        }
+
+        Ok(())
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PathParseError {
+    /// The path was empty or only contained whitespace
     Empty,
+    /// The path was longer than [`MAX_PATH_LENGTH`]
     MaxLengthExceeded,
+    /// A segment of the path was longer than [`MAX_NAME_LENGTH`]
+    NameTooLong,
 }
 
 impl FromStr for Path {
     type Err = PathParseError;
 
     fn from_str(mut s: &str) -> Result<Self, Self::Err> {
-        if !s.is_ascii() {
-            todo!("parse non-ascii paths");
-        }
-
-        if s.is_empty() {
+        if s.trim().is_empty() {
             return Err(PathParseError::Empty);
         }
 
@@ -45,13 +174,19 @@ impl FromStr for Path {
 
         let mut segments = Vec::new();
 
+        // Any number of leading slashes refer to the root directory, so a path
+        // consisting only of slashes is the same as "/"
         if s.starts_with("/") {
             segments.push("/".into());
-            s = &s[1..];
+            s = s.trim_start_matches('/');
         }
 
         if !s.is_empty() {
             for segment in s.split("/") {
+                if segment.len() > MAX_NAME_LENGTH {
+                    return Err(PathParseError::NameTooLong);
+                }
+
                 segments.push(segment.to_string());
             }
         }
@@ -59,3 +194,131 @@ impl FromStr for Path {
         Ok(Self { segments })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    fn normalize(path: &str) -> String {
+        Path::from_str(path).unwrap().normalize().to_string()
+    }
+
+    #[test_case]
+    fn rejects_empty_paths() {
+        assert_eq!(Path::from_str("").err(), Some(PathParseError::Empty));
+        assert_eq!(Path::from_str("   ").err(), Some(PathParseError::Empty));
+        assert_eq!(Path::from_str("\t\n").err(), Some(PathParseError::Empty));
+    }
+
+    #[test_case]
+    fn parses_root() {
+        for root in ["/", "///"] {
+            let path = Path::from_str(root).unwrap();
+
+            assert!(path.is_absolute());
+            assert_eq!(path.segments().collect::<Vec<_>>(), ["/"]);
+            assert_eq!(path.to_string(), "/");
+        }
+    }
+
+    #[test_case]
+    fn empty_path_is_not_absolute() {
+        assert!(!Path::default().is_absolute());
+        assert!(!Path::from_str("a/b").unwrap().is_absolute());
+    }
+
+    #[test_case]
+    fn rejects_long_paths() {
+        let name = "a".repeat(MAX_NAME_LENGTH + 1);
+        assert_eq!(
+            Path::from_str(&name).err(),
+            Some(PathParseError::NameTooLong)
+        );
+
+        let path = "a/".repeat(MAX_PATH_LENGTH / 2 + 1);
+        assert_eq!(
+            Path::from_str(&path).err(),
+            Some(PathParseError::MaxLengthExceeded)
+        );
+    }
+
+    fn path(path: &str) -> Path {
+        Path::from_str(path).unwrap()
+    }
+
+    #[test_case]
+    fn display_round_trips() {
+        for p in ["/", "/a/b", "a/b", "/a/b/", "../a", "a/../b"] {
+            assert_eq!(path(p).to_string(), p);
+        }
+    }
+
+    #[test_case]
+    fn join_appends_segments() {
+        assert_eq!(path("/").join("a").to_string(), "/a");
+        assert_eq!(path("/a").join("b/c").to_string(), "/a/b/c");
+        assert_eq!(path("/a/").join("b").to_string(), "/a/b");
+        assert_eq!(path("a").join("b/").to_string(), "a/b/");
+        assert_eq!(path("/a").join("").to_string(), "/a");
+        // ".." is only resolved by the VFS
+        assert_eq!(path("/a").join("..").to_string(), "/a/..");
+    }
+
+    #[test_case]
+    fn join_absolute_replaces_path() {
+        assert_eq!(path("/a/b").join("/c").to_string(), "/c");
+        assert_eq!(path("a").join("//").to_string(), "/");
+    }
+
+    #[test_case]
+    fn parent_removes_last_segment() {
+        assert_eq!(path("/a").parent().unwrap().to_string(), "/");
+        assert_eq!(path("/a/b").parent().unwrap().to_string(), "/a");
+        assert_eq!(path("/a/b/").parent().unwrap().to_string(), "/a");
+        assert_eq!(path("a/..").parent().unwrap().to_string(), "a");
+
+        assert!(path("/").parent().is_none());
+        assert!(path("a").parent().is_none());
+        assert!(path("..").parent().is_none());
+    }
+
+    #[test_case]
+    fn file_name_is_last_segment() {
+        assert_eq!(path("/a/b").file_name(), Some("b"));
+        assert_eq!(path("/a/b/").file_name(), Some("b"));
+        assert_eq!(path("a").file_name(), Some("a"));
+
+        assert_eq!(path("/").file_name(), None);
+        assert_eq!(path("/a/..").file_name(), None);
+        assert_eq!(path("..").file_name(), None);
+    }
+
+    #[test_case]
+    fn normalize_removes_dots() {
+        assert_eq!(normalize("/a/./b/../c"), "/a/c");
+        assert_eq!(normalize("/a//b/"), "/a/b");
+        assert_eq!(normalize("a/./b/."), "a/b");
+    }
+
+    #[test_case]
+    fn normalize_stops_at_root() {
+        assert_eq!(normalize("/.."), "/");
+        assert_eq!(normalize("/../../a/.."), "/");
+        assert_eq!(normalize("///"), "/");
+    }
+
+    #[test_case]
+    fn normalize_keeps_leading_parents_of_relative_paths() {
+        assert_eq!(normalize("../a"), "../a");
+        assert_eq!(normalize("a/../../b"), "../b");
+        assert_eq!(normalize("../../a/.."), "../..");
+    }
+
+    #[test_case]
+    fn normalize_relative_path_to_nothing() {
+        assert_eq!(normalize("a/.."), ".");
+        assert_eq!(normalize("./"), ".");
+    }
+}
@@ -1,4 +1,4 @@
-use alloc::{collections::BTreeMap, sync::Arc};
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
 
 use spin::Mutex;
 
@@ -51,3 +51,10 @@ pub fn find_file_system_type(name: &str) -> Option<Arc<dyn FileSystemType>> {
 
     registry.get(name).cloned()
 }
+
+/// Lists all registered file system types (i.e. for probing a device)
+pub fn file_system_types() -> Vec<Arc<dyn FileSystemType>> {
+    let registry = FILE_SYSTEM_REGISTRY.lock();
+
+    registry.values().cloned().collect()
+}
@@ -1,23 +1,45 @@
 use alloc::{
-    collections::{BTreeMap, VecDeque},
+    collections::BTreeMap,
+    format,
     string::{String, ToString},
     sync::{Arc, Weak},
+    vec::Vec,
 };
 use core::{
+    fmt::Display,
     str::FromStr,
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
 };
 
 use conquer_once::spin::OnceCell;
 use spin::RwLock;
 
-use super::{File, FileDescriptor, FileSystem, FsNode, FsNodeId, path::Path};
+use super::{
+    File, FileDescriptor, FileSystem, FsNode, FsNodeId, MODE_MASK, MODE_OWNER_READ,
+    MODE_OWNER_WRITE, OpenFlags,
+    path::{Path, PathParseError},
+};
 use crate::{
-    fs::{FileMode, FsNodeKind, MountFlags, registry::find_file_system_type},
+    fs::{
+        FileMode, FileSystemStats, FileSystemType, FsNodeKind, MountFlags, SeekFrom,
+        registry::{file_system_types, find_file_system_type},
+    },
     util::defer::defer_handle,
 };
 
-#[derive(Debug)]
+/// The most blocks which are passed to a driver in a single read or write.
+/// Larger requests are split into several calls.
+const MAX_TRANSFER_BLOCKS: usize = 64;
+
+/// The number of bytes read from the start of a device when detecting the type
+/// of the file system stored on it
+const PROBE_SIZE: usize = 512;
+
+/// The maximum number of symbolic links which are followed while resolving a
+/// single path. Any more than this most likely means the links form a loop.
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum IoError {
     /// The requested operation is not implemented by the target file system or
     /// device
@@ -49,6 +71,70 @@ pub enum IoError {
     /// Only ever returned if a resolution operation is attempted before the
     /// root of the file system has been mounted
     NoRootDirectory,
+    /// The provided path or one of its segments is longer than the VFS or the
+    /// target file system supports
+    NameTooLong,
+    /// There is no space left on the device to complete a write
+    NoSpaceLeft,
+    /// The device backing a file failed to carry out a request
+    DeviceError,
+    /// A file cursor was moved to a position before the start of the file
+    InvalidSeek,
+    /// The directory could not be removed because it still contains entries
+    NotEmpty,
+    /// The entry is in use by the VFS and can't be removed (i.e. it is the
+    /// mount point of another file system)
+    Busy,
+    /// Resolving the path required following more symbolic links than allowed,
+    /// which usually means that the links form a loop
+    TooManySymlinks,
+    /// A write would make the file larger than the file system supports
+    FileTooLarge,
+    /// The permission bits of the entry don't allow the requested access (i.e.
+    /// opening a read-only file for writing)
+    PermissionDenied,
+    /// The source and the destination of a copy are the same file
+    SameFile,
+}
+
+impl Display for IoError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // Worded like the matching errno messages so that the shell reads the
+        // same as a Unix one
+        let message = match self {
+            IoError::OperationNotSupported => "Operation not supported",
+            IoError::EntryNotFound => "No such file or directory",
+            IoError::AlreadyExists => "File exists",
+            IoError::NotADirectory => "Not a directory",
+            IoError::NotAFile => "Not a regular file",
+            IoError::InvalidPath => "Invalid path",
+            IoError::InvalidFile => "Bad file descriptor",
+            IoError::InvalidMode => "File not open for this operation",
+            IoError::FileSystemTypeNotFound => "Unknown file system type",
+            IoError::NoRootDirectory => "No root file system",
+            IoError::NameTooLong => "File name too long",
+            IoError::NoSpaceLeft => "No space left on device",
+            IoError::DeviceError => "Input/output error",
+            IoError::InvalidSeek => "Invalid seek",
+            IoError::NotEmpty => "Directory not empty",
+            IoError::Busy => "Device or resource busy",
+            IoError::TooManySymlinks => "Too many levels of symbolic links",
+            IoError::FileTooLarge => "File too large",
+            IoError::PermissionDenied => "Permission denied",
+            IoError::SameFile => "Source and destination are the same file",
+        };
+
+        write!(f, "{}", message)
+    }
+}
+
+impl From<PathParseError> for IoError {
+    fn from(value: PathParseError) -> Self {
+        match value {
+            PathParseError::Empty => IoError::InvalidPath,
+            PathParseError::MaxLengthExceeded | PathParseError::NameTooLong => IoError::NameTooLong,
+        }
+    }
 }
 
 #[derive(Default)]
@@ -63,6 +149,11 @@ pub struct VirtualFileSystem {
     /// query the file system implementation with lookup calls since the
     /// underlying data doesn't change for most file systems.
     directory_cache: RwLock<DirectoryCache>,
+    /// The directory which relative paths are resolved against. Defaults to the
+    /// root directory when None.
+    ///
+    /// FIXME: this should be tracked per process once there is more than one
+    working_directory: RwLock<Option<Arc<DirectoryEntry>>>,
 }
 
 impl VirtualFileSystem {
@@ -119,143 +210,281 @@ impl VirtualFileSystem {
     /// entries which identify the same entry on disk are guaranteed to have the
     /// same ID for as long as strong referernces to the entry exist in memory.
     /// When reloaded from disk, IDs are regenerated.
+    ///
+    /// Relative paths are resolved against the current working directory.
+    /// Symbolic links are followed, including one named by the last segment.
     fn resolve_path(&self, path: &str) -> Result<Option<Arc<DirectoryEntry>>, IoError> {
-        let path = Path::from_str(path).map_err(|_| IoError::InvalidPath)?;
+        let path = Path::from_str(path)?;
+        let (start, skip) = self.resolution_start(&path)?;
 
-        if !path.is_absolute() {
-            todo!("resolve relative paths ({path:?})");
+        self.walk(start, path.segments().skip(skip), true)
+    }
+
+    /// Same as [`VirtualFileSystem::resolve_path`], except that a symbolic
+    /// link named by the last segment is returned itself rather than the entry
+    /// it points to
+    fn resolve_path_no_follow(&self, path: &str) -> Result<Option<Arc<DirectoryEntry>>, IoError> {
+        let path = Path::from_str(path)?;
+        let (start, skip) = self.resolution_start(&path)?;
+
+        self.walk(start, path.segments().skip(skip), false)
+    }
+
+    /// Resolves all segments in a path to a directory entry in the VFS,
+    /// excluding the last segment. All resolved segments must be directory
+    /// nodes. The last segment must be a name, not a "." or "..", since it
+    /// identifies an entry to be created or removed within the parent.
+    ///
+    /// Trailing slashes and "." segments are ignored, so "a/b/." and "a/b/"
+    /// both name "b" within "a". The segments before the name are walked like
+    /// in [`VirtualFileSystem::resolve_path`], so links are followed and ".."
+    /// goes to the parent of the directory the walk actually reached. The path
+    /// is deliberately not [`Path::normalize`]d first, which would take ".."
+    /// after a link to the link's parent instead.
+    fn resolve_path_parent_directory(
+        &self,
+        path: &str,
+    ) -> Result<(Arc<DirectoryEntry>, String), IoError> {
+        let path = Path::from_str(path)?;
+        let (start, skip) = self.resolution_start(&path)?;
+
+        let mut segments: Vec<&str> = path.segments().skip(skip).collect();
+        while let Some(&("" | ".")) = segments.last() {
+            segments.pop();
         }
 
-        let Some(root_directory) = self.directory_cache.read().get_root() else {
-            return Err(IoError::NoRootDirectory);
+        // Must have at least one segment naming the entry itself
+        let Some(name) = segments.pop() else {
+            return Err(IoError::InvalidPath);
         };
 
-        let mut stack = VecDeque::new();
-        stack.push_back(root_directory.clone());
+        if name == ".." {
+            return Err(IoError::InvalidPath);
+        }
+
+        let parent = self
+            .walk(start, segments.into_iter(), true)?
+            .ok_or(IoError::EntryNotFound)?;
 
-        // we know the first segment is the root so we can skip it
-        'segments: for segment in path.segments().skip(1) {
-            let top = stack.back().expect("root should always exist");
+        if !parent.node.is_directory() {
+            return Err(IoError::NotADirectory);
+        }
+
+        Ok((parent, name.to_string()))
+    }
+
+    /// Returns the directory which resolution of the provided path starts at,
+    /// along with the number of leading segments which refer to that directory
+    /// and should be skipped
+    fn resolution_start(&self, path: &Path) -> Result<(Arc<DirectoryEntry>, usize), IoError> {
+        if path.is_absolute() {
+            let Some(root_directory) = self.directory_cache.read().get_root() else {
+                return Err(IoError::NoRootDirectory);
+            };
+
+            // the first segment of an absolute path is the root
+            Ok((root_directory, 1))
+        } else {
+            Ok((self.current_directory()?, 0))
+        }
+    }
+
+    /// Makes sure the given name can be created in the parent directory
+    fn check_name_length(&self, parent: &Arc<DirectoryEntry>, name: &str) -> Result<(), IoError> {
+        if name.len() > parent.node.file_system().metadata().max_name_len {
+            return Err(IoError::NameTooLong);
+        }
+
+        Ok(())
+    }
+
+    /// Walks a sequence of path segments starting at the provided entry and
+    /// returns the entry which the final segment refers to, or None if some
+    /// segment could not be found.
+    ///
+    /// A ".." segment follows the [`DirectoryEntry::parent`] link of the
+    /// current entry. For the root of a mounted file system, that link points
+    /// at the directory it is mounted in, so walking up out of a mount returns
+    /// to the parent file system rather than staying inside the mounted one.
+    ///
+    /// Symbolic links are replaced by the entry they point to. A link named by
+    /// the last segment is only followed if `follow_last` is set.
+    fn walk<'a>(
+        &self,
+        start: Arc<DirectoryEntry>,
+        segments: impl Iterator<Item = &'a str>,
+        follow_last: bool,
+    ) -> Result<Option<Arc<DirectoryEntry>>, IoError> {
+        self.walk_with_links(start, segments, follow_last, &mut 0)
+    }
+
+    /// Implementation of [`VirtualFileSystem::walk`] which counts the links
+    /// followed across nested resolutions of link targets
+    fn walk_with_links<'a>(
+        &self,
+        start: Arc<DirectoryEntry>,
+        segments: impl Iterator<Item = &'a str>,
+        follow_last: bool,
+        links_followed: &mut usize,
+    ) -> Result<Option<Arc<DirectoryEntry>>, IoError> {
+        let mut current = start;
+        let mut segments = segments.peekable();
 
+        'segments: while let Some(segment) = segments.next() {
             // Every additional segment we add requires that the previous
             // segment be a directory
-            if !top.node.is_directory() {
+            if !current.node.is_directory() {
                 return Err(IoError::NotADirectory);
             }
 
             match segment {
-                "." => {
-                    // single dots are redundant in absolute paths
+                "." | "" => {
+                    // single dots always refer to the current directory. empty
+                    // segments come from repeated or trailing slashes.
                     continue;
                 }
                 ".." => {
-                    // If only the root exists, the POSIX behavior is to ignore
-                    // any additional double dots
-                    if stack.len() == 1 {
-                        continue;
+                    // The root of the VFS has no parent. The POSIX behavior is
+                    // to ignore any additional double dots.
+                    if let Some(parent) = current.parent.clone() {
+                        current = parent;
                     }
-
-                    stack.pop_back();
                 }
                 name => {
-                    // lookup this name in the directory which is on the top of
-                    // the resolution stack
-
-                    // check if the top dir is the parent of any mounts in the
-                    // mount table. if it is, check those mounts before querying
-                    // the original fs
+                    // check if the current dir is the parent of any mounts in
+                    // the mount table. if it is, check those mounts before
+                    // querying the original fs
                     for mnt in self.mount_table.read().values() {
-                        if mnt.root.parent.as_ref().is_some_and(|p| p == top)
+                        if mnt.root.parent.as_ref().is_some_and(|p| *p == current)
                             && *mnt.root.name == *name
                         {
-                            stack.push_back(mnt.root.clone());
+                            current = mnt.root.clone();
                             continue 'segments;
                         }
                     }
 
-                    let Some(entry) = self.get_cached_or_lookup(top, name)? else {
+                    let Some(entry) = self.get_cached_or_lookup(&current, name)? else {
                         return Ok(None);
                     };
 
-                    stack.push_back(entry);
+                    // Links before the last segment always have to be followed
+                    // since the remaining segments are resolved in the target
+                    if entry.node.kind == FsNodeKind::Symlink
+                        && (follow_last || segments.peek().is_some())
+                    {
+                        let Some(target) = self.follow_symlink(&current, &entry, links_followed)?
+                        else {
+                            return Ok(None);
+                        };
+
+                        current = target;
+                    } else {
+                        current = entry;
+                    }
                 }
             }
         }
 
-        Ok(Some(stack.pop_back().unwrap()))
+        Ok(Some(current))
     }
 
-    /// Resolves all segments in a path to a directory entry in the VFS,
-    /// excluding the last segment which is not a "." or "..". All resolved
-    /// segments must be directory nodes.
-    fn resolve_path_parent_directory(
+    /// Resolves the target of a symbolic link which is located in the given
+    /// directory. Relative targets are resolved against that directory.
+    fn follow_symlink(
         &self,
-        path: &str,
-    ) -> Result<(Arc<DirectoryEntry>, String), IoError> {
-        let path = Path::from_str(path).map_err(|_| IoError::InvalidPath)?;
-        if !path.is_absolute() {
-            todo!("canonicalize relative paths");
+        directory: &Arc<DirectoryEntry>,
+        link: &Arc<DirectoryEntry>,
+        links_followed: &mut usize,
+    ) -> Result<Option<Arc<DirectoryEntry>>, IoError> {
+        *links_followed += 1;
+
+        if *links_followed > MAX_SYMLINK_DEPTH {
+            return Err(IoError::TooManySymlinks);
         }
 
-        let Some(root_directory) = self.directory_cache.read().get_root() else {
-            return Err(IoError::NoRootDirectory);
-        };
+        let fs = link.node.file_system();
+        let target = fs.file_operations().readlink(&link.node)?;
+        let target = Path::from_str(&target)?;
 
-        let mut stack = VecDeque::new();
-        stack.push_back(root_directory.clone());
+        let (start, skip) = if target.is_absolute() {
+            let root = self
+                .directory_cache
+                .read()
+                .get_root()
+                .ok_or(IoError::NoRootDirectory)?;
 
-        // number of segments after the root
-        let count = path.segments().count() - 1;
+            (root, 1)
+        } else {
+            (directory.clone(), 0)
+        };
 
-        // Must have at least one segment after the root directory
-        if count == 0 {
-            return Err(IoError::InvalidPath);
-        }
+        self.walk_with_links(start, target.segments().skip(skip), true, links_followed)
+    }
 
-        // we know the first segment is the root so we can skip it
-        for (i, segment) in path.segments().skip(1).enumerate() {
-            match segment {
-                "." => {
-                    todo!()
-                }
-                ".." => {
-                    todo!()
+    /// Returns a snapshot of every mounted file system, ordered by the time
+    /// they were mounted
+    pub fn mounts(&self) -> Vec<MountInfo> {
+        self.mount_table
+            .read()
+            .values()
+            .map(|mount| {
+                let metadata = mount.file_system.metadata();
+
+                MountInfo {
+                    id: mount.id,
+                    source: mount.source.clone(),
+                    target: mount.root.path(),
+                    file_system_type: metadata.file_system_type.metadata().name,
+                    flags: metadata.mount_flags,
                 }
-                name => {
-                    // FIXME: this doesnt handle "." or ".." at the end
-
-                    if i != count - 1 {
-                        // lookup this name in the directory which is on the top of
-                        // the resolution stack
+            })
+            .collect()
+    }
 
-                        let top = stack.back().expect("root should always exist");
+    /// Looks up a mount in the global VFS mount table
+    pub(super) fn get_mount(&self, id: MountId) -> Option<Arc<VfsMount>> {
+        self.mount_table.read().get(&id).cloned()
+    }
 
-                        let Some(entry) = self.get_cached_or_lookup(top, name)? else {
-                            return Err(IoError::EntryNotFound);
-                        };
+    /// Guesses the type of the file system stored in the given source by
+    /// comparing the start of its first block against the magic bytes of all
+    /// registered file system types. The type with the longest matching magic
+    /// wins.
+    fn detect_file_system_type(&self, source: &str) -> Result<Arc<dyn FileSystemType>, IoError> {
+        let fd = self.open(source, FileMode::Read)?;
+        let _close = defer_handle!({
+            let _ = self.close(fd);
+        });
 
-                        if !entry.node.is_directory() {
-                            return Err(IoError::NotADirectory);
-                        }
+        let mut block = [0u8; PROBE_SIZE];
+        let mut length = 0;
 
-                        stack.push_back(entry);
-                    } else {
-                        return Ok((stack.pop_back().unwrap(), name.to_string()));
-                    }
-                }
+        // Reads can be short, so keep going until the block is full or the
+        // source ends
+        while length < block.len() {
+            match self.read(fd, &mut block[length..])? {
+                0 => break,
+                n => length += n,
             }
         }
 
-        unreachable!();
-    }
+        let block = &block[..length];
 
-    /// Looks up a mount in the global VFS mount table
-    pub(super) fn get_mount(&self, id: MountId) -> Option<Arc<VfsMount>> {
-        self.mount_table.read().get(&id).cloned()
+        file_system_types()
+            .into_iter()
+            // Virtual file systems have no magic and can't be detected
+            .filter(|ty| !ty.metadata().magic.is_empty())
+            .filter(|ty| block.starts_with(ty.metadata().magic))
+            .max_by_key(|ty| ty.metadata().magic.len())
+            .ok_or(IoError::FileSystemTypeNotFound)
     }
 
     /// Mounts the given file system in the specified directory. The backing FS
     /// can be a block device or a regular file.
+    ///
+    /// The target may be a directory which doesn't exist yet or an existing
+    /// directory, whose contents are hidden for as long as the file system is
+    /// mounted over it.
     pub fn mount(
         &self,
         source: &str,
@@ -265,16 +494,12 @@ impl VirtualFileSystem {
     ) -> Result<MountId, IoError> {
         // If a desired type was specified, use that. Otherwise we will try to
         // guess based on the magic.
-        let fs_type = match kind {
-            Some(k) => Some(find_file_system_type(k).ok_or(IoError::FileSystemTypeNotFound)?),
-            None => None,
+        let ty = match kind {
+            Some(k) => find_file_system_type(k).ok_or(IoError::FileSystemTypeNotFound)?,
+            None => self.detect_file_system_type(source)?,
         };
 
-        let Some(ty) = fs_type else {
-            todo!("handle fs type detection based on longest matching sequence of magic bytes")
-        };
-
-        if ty.metadata().name != "ramfs" && ty.metadata().name != "devfs" {
+        if !matches!(ty.metadata().name, "ramfs" | "devfs" | "procfs" | "tmpfs") {
             todo!("we can only mount virtual file systems for now (no block devices)")
         }
 
@@ -294,21 +519,64 @@ impl VirtualFileSystem {
 
             VfsMount {
                 id,
+                source: source.to_string(),
                 root,
                 file_system: fs,
+                references: AtomicUsize::new(0),
             }
         }
         // Mounting over an existing directory
-        else if let Some(_target) = self.resolve_path(target)? {
-            // let id = MountId::new();
-            // let fs = ty.mount(id, source, flags)?;
+        else if let Some(target) = self.resolve_path(target)? {
+            // File systems can only be mounted on directories
+            if !target.node.is_directory() {
+                return Err(IoError::NotADirectory);
+            }
+
+            // Only the root of the VFS has no parent, and it is always mounted
+            let Some(parent) = target.parent.clone() else {
+                return Err(IoError::AlreadyExists);
+            };
+
+            let _parent_lock = parent.node.structure_lock.lock();
+            let _lock = target.node.structure_lock.lock();
+
+            // The target could have been removed, renamed or mounted over
+            // before we acquired the locks, so check again now that the parent
+            // can no longer change
+            let already_mounted = self.mount_table.read().values().any(|mnt| {
+                mnt.root.parent.as_ref() == Some(&parent) && mnt.root.name == target.name
+            });
+
+            if already_mounted {
+                return Err(IoError::Busy);
+            }
+
+            if self.get_cached_or_lookup(&parent, &target.name)?.as_ref() != Some(&target) {
+                return Err(IoError::EntryNotFound);
+            }
+
+            let id = MountId::new();
+            let fs = ty.mount(id, source, flags)?;
 
-            // todo: check if is dir
-            // todo: make sure to invalidate directory cache?
-            // todo: make sure to lock parent while we do this and then check
-            // again
+            // Evict the shadowed directory so that the root of the mount takes
+            // its place in the cache. Existing references to it (i.e. a working
+            // directory inside it) stay valid and keep referring to the
+            // original file system.
+            let mut cache = self.directory_cache.write();
+            cache.remove(&parent, &target.name);
+            let root = cache.insert(
+                Some(parent.clone()),
+                fs.root_directory(),
+                target.name.clone(),
+            );
 
-            todo!()
+            VfsMount {
+                id,
+                source: source.to_string(),
+                root,
+                file_system: fs,
+                references: AtomicUsize::new(0),
+            }
         }
         // Mounting into a non-existent directory.
         else {
@@ -316,10 +584,17 @@ impl VirtualFileSystem {
 
             let _lock = parent.node.structure_lock.lock();
 
-            // FIXME: check that this name is not already mounted in the
-            // parent directory
-            // FIXME: check that this name is not already taken in the parent
-            // dir (after acquiring the lock on the parent)
+            // Another entry or mount could have been created with this name
+            // before we acquired the lock on the parent, so check again now
+            // that the parent can no longer change
+            let already_mounted =
+                self.mount_table.read().values().any(|mnt| {
+                    mnt.root.parent.as_ref() == Some(&parent) && *mnt.root.name == *name
+                });
+
+            if already_mounted || self.get_cached_or_lookup(&parent, &name)?.is_some() {
+                return Err(IoError::AlreadyExists);
+            }
 
             let id = MountId::new();
             let fs = ty.mount(id, source, flags)?;
@@ -329,8 +604,10 @@ impl VirtualFileSystem {
 
             VfsMount {
                 id,
+                source: source.to_string(),
                 root,
                 file_system: fs,
+                references: AtomicUsize::new(0),
             }
         };
 
@@ -340,6 +617,95 @@ impl VirtualFileSystem {
         Ok(id)
     }
 
+    /// Unmounts the file system which is mounted at the given path. Fails with
+    /// [`IoError::Busy`] while files on it are open, the working directory is
+    /// inside of it or other file systems are mounted within it. The root of
+    /// the VFS can not be unmounted.
+    ///
+    /// If the file system was mounted over an existing directory, that
+    /// directory becomes visible again.
+    pub fn unmount(&self, target: &str) -> Result<(), IoError> {
+        let entry = self.resolve_path(target)?.ok_or(IoError::EntryNotFound)?;
+
+        let Some(parent) = entry.parent.clone() else {
+            return Err(IoError::Busy);
+        };
+
+        let _parent_lock = parent.node.structure_lock.lock();
+
+        let Some(mount) = self
+            .mount_table
+            .read()
+            .values()
+            .find(|mnt| mnt.root == entry)
+            .cloned()
+        else {
+            // Not a mount point
+            return Err(IoError::InvalidPath);
+        };
+
+        mount.file_system.sync()?;
+
+        {
+            let mut mount_table = self.mount_table.write();
+
+            // Check for references while holding the table so that no new ones
+            // can be acquired before the mount is removed
+            let has_submounts = mount_table.values().any(|mnt| {
+                mnt.root
+                    .parent
+                    .as_ref()
+                    .is_some_and(|p| p.node.mount_id == mount.id)
+            });
+
+            if has_submounts || mount.references.load(Ordering::Relaxed) > 0 {
+                return Err(IoError::Busy);
+            }
+
+            mount_table.remove(&mount.id);
+        }
+
+        // Drop every entry of the file system from the cache, including the
+        // root which took the place of any directory it was mounted over
+        self.directory_cache
+            .write()
+            .evict_mount(&parent, &mount.root.name, mount.id);
+
+        let fs = mount.file_system.clone();
+        let ty = fs.metadata().file_system_type.clone();
+        ty.unmount(fs);
+
+        Ok(())
+    }
+
+    /// Drops a reference to a node which was held by a name or an open file,
+    /// evicting it from its file system if that was the last one
+    fn release_node(&self, node: &Arc<FsNode>) {
+        if node.decrement_link_count() {
+            // There is nobody left to report a failure to, and a node which
+            // failed to be evicted is only wasted space
+            let _ = node.file_system().node_operations().evict_node(node);
+        }
+    }
+
+    /// Records a reference to the given mount (i.e. an open file) which
+    /// prevents it from being unmounted until it is released
+    fn acquire_mount(&self, id: MountId) -> Result<(), IoError> {
+        let mount_table = self.mount_table.read();
+        let mount = mount_table.get(&id).ok_or(IoError::EntryNotFound)?;
+
+        mount.references.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Releases a reference which was recorded with [`Self::acquire_mount`]
+    fn release_mount(&self, id: MountId) {
+        if let Some(mount) = self.mount_table.read().get(&id) {
+            mount.references.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
     fn get_file(&self, fd: FileDescriptor) -> Result<Arc<File>, IoError> {
         self.files
             .read()
@@ -351,9 +717,27 @@ impl VirtualFileSystem {
     /// Opens the given path as a file or creates one if the file does not
     /// already exist
     pub fn open(&self, path: &str, mode: FileMode) -> Result<FileDescriptor, IoError> {
+        self.open_with_flags(path, mode, OpenFlags::empty())
+    }
+
+    /// Same as [`VirtualFileSystem::open`], with flags to change how the path
+    /// is treated
+    pub fn open_with_flags(
+        &self,
+        path: &str,
+        mode: FileMode,
+        flags: OpenFlags,
+    ) -> Result<FileDescriptor, IoError> {
         // resolve the file entry or create a new one in the parent directory if
         // we are opening in a writing mode
+        let mut existed = true;
+
         let file_entry = if mode.is_mutating() {
+            // An exclusive create fails on any existing name, even a link
+            if flags.contains(OpenFlags::EXCL) && self.resolve_path_no_follow(path)?.is_some() {
+                return Err(IoError::AlreadyExists);
+            }
+
             // return the file if it exists, or try to create it as long as the
             // parent directory exists
             if let Some(entry) = self.resolve_path(path)? {
@@ -363,27 +747,66 @@ impl VirtualFileSystem {
 
                 entry
             } else {
+                // A dangling link is not replaced by a new file. Creating the
+                // target through the link is not supported either.
+                if self.resolve_path_no_follow(path)?.is_some() {
+                    return Err(IoError::EntryNotFound);
+                }
+
+                existed = false;
+
                 let (parent, file_name) = self.resolve_path_parent_directory(path)?;
+                self.check_name_length(&parent, &file_name)?;
+
+                // Lock the parent and check again so that nothing else can
+                // create the same name before we do
+                let _guard = parent.node.structure_lock.lock();
+                self.check_name_free(&parent, &file_name)?;
 
                 let fs = parent.node.file_system();
                 let node = fs.directory_operations().create_file(&parent, &file_name)?;
 
                 self.directory_cache
                     .write()
-                    .insert(Some(parent), node, file_name)
+                    .insert(Some(parent.clone()), node, file_name)
             }
         } else {
-            self.resolve_path(path)?.ok_or(IoError::EntryNotFound)?
+            let entry = self.resolve_path(path)?.ok_or(IoError::EntryNotFound)?;
+
+            if entry.node.is_directory() {
+                return Err(IoError::NotAFile);
+            }
+
+            entry
         };
 
+        // A file which was just created can always be opened by its creator
+        if existed {
+            check_access(&file_entry.node, mode)?;
+        }
+
+        self.acquire_mount(file_entry.node.mount_id)?;
         file_entry.node.increment_link_count();
         let error_cleanup = defer_handle!({
-            file_entry.node.decrement_link_count();
+            self.release_node(&file_entry.node);
+            self.release_mount(file_entry.node.mount_id);
         });
 
         let fs = file_entry.node.file_system();
         let file = Arc::new(fs.file_operations().open(file_entry.node.clone(), mode)?);
 
+        // Opening an existing regular file for writing replaces its contents.
+        // Devices have no contents to truncate.
+        if mode == FileMode::Write && existed && file.node.kind == FsNodeKind::File {
+            fs.file_operations().truncate(&file, 0)?;
+            file.node.metadata.lock().mark_modified();
+        }
+
+        // Appending files start out with the cursor at the end
+        if mode == FileMode::Append {
+            *file.position.lock() = file.node.metadata.lock().size;
+        }
+
         let fd = FileDescriptor::new();
         self.files.write().insert(fd, file.clone());
 
@@ -399,7 +822,8 @@ impl VirtualFileSystem {
         fs.file_operations().flush(&file)?;
 
         self.files.write().remove(&fd);
-        file.node.decrement_link_count();
+        self.release_node(&file.node);
+        self.release_mount(file.node.mount_id);
 
         Ok(())
     }
@@ -414,19 +838,36 @@ impl VirtualFileSystem {
             return Err(IoError::InvalidMode);
         }
 
-        // FIXME: check that buffer is smaller than max read size
-        // FIXME: update file access time
-
         let fs = file.file_system();
+        let chunk_size = max_transfer_size(&fs);
 
         /* Read and update the current offset if successful */
 
         let mut offset = file.position.lock();
+        let mut total = 0;
+
+        // Large reads are split so that drivers never see more than a bounded
+        // number of blocks at once
+        for chunk in buffer.chunks_mut(chunk_size) {
+            let n = match fs.file_operations().read(&file, *offset, chunk) {
+                Ok(n) => n,
+                // Whatever was read before the error is still returned
+                Err(_) if total > 0 => break,
+                Err(e) => return Err(e),
+            };
+
+            *offset += n;
+            total += n;
+
+            // A short read means there is nothing more to read right now
+            if n < chunk.len() {
+                break;
+            }
+        }
 
-        let n = fs.file_operations().read(&file, *offset, buffer)?;
-        *offset += n;
+        file.node.metadata.lock().mark_accessed();
 
-        Ok(n)
+        Ok(total)
     }
 
     /// Write to the file from the buffer at the current file offset. Returns
@@ -435,25 +876,71 @@ impl VirtualFileSystem {
         let file = self.get_file(fd)?;
         assert_ne!(file.node.kind, FsNodeKind::Directory);
 
-        if file.mode != FileMode::Write {
+        if !file.mode.is_mutating() {
             return Err(IoError::InvalidMode);
         }
 
-        // FIXME: check that buffer is smaller than max write size
-        // FIXME: update file modify time
-
-        
-
         let fs = file.file_system();
+        let chunk_size = max_transfer_size(&fs);
 
         /* Write and update the current offset if successful */
 
         let mut offset = file.position.lock();
 
-        let n = fs.file_operations().write(&file, *offset, buffer)?;
-        *offset += n;
+        // Appends always go to the end of the file, even if it has grown
+        // through another descriptor since the cursor was last moved
+        if file.mode == FileMode::Append {
+            *offset = file.node.metadata.lock().size;
+        }
+
+        // Devices have their own idea of how far they go, so only regular
+        // files are held to the limit of the file system
+        if file.node.kind == FsNodeKind::File {
+            let end = offset
+                .checked_add(buffer.len())
+                .ok_or(IoError::FileTooLarge)?;
+
+            if end > fs.metadata().max_file_size {
+                return Err(IoError::FileTooLarge);
+            }
+        }
+
+        let mut total = 0;
+
+        // Large writes are split the same way as reads
+        for chunk in buffer.chunks(chunk_size) {
+            let n = match fs.file_operations().write(&file, *offset, chunk) {
+                Ok(n) => n,
+                // Whatever was written before the error still counts
+                Err(_) if total > 0 => break,
+                Err(e) => return Err(e),
+            };
+
+            *offset += n;
+            total += n;
+
+            if n < chunk.len() {
+                break;
+            }
+        }
+
+        file.node.metadata.lock().mark_modified();
+
+        Ok(total)
+    }
+
+    /// Moves the cursor of an open file. Returns the new position measured from
+    /// the start of the file.
+    pub fn seek(&self, fd: FileDescriptor, from: SeekFrom) -> Result<usize, IoError> {
+        let file = self.get_file(fd)?;
+        assert_ne!(file.node.kind, FsNodeKind::Directory);
+
+        let fs = file.file_system();
+
+        let mut position = file.position.lock();
+        *position = fs.file_operations().seek(&file, *position, from)?;
 
-        Ok(n)
+        Ok(*position)
     }
 
     /// Lists the contents of a directory in the virtual file system. Uses the
@@ -492,54 +979,612 @@ impl VirtualFileSystem {
     }
 
     pub fn create_directory(&self, path: &str) -> Result<Arc<DirectoryEntry>, IoError> {
-        if self.resolve_path(path)?.is_some() {
+        // A link is not followed here since the name is taken either way
+        if self.resolve_path_no_follow(path)?.is_some() {
             return Err(IoError::AlreadyExists);
         }
 
         let (parent, dir_name) = self.resolve_path_parent_directory(path)?;
 
-        // Lock the parent to make sure that we dont try to create or delete
-        // other entries concurrently
+        // Lock the parent and check again so that nothing else can create the
+        // same name before we do
         let _guard = parent.node.structure_lock.lock();
+        self.check_name_free(&parent, &dir_name)?;
+
+        self.create_directory_locked(&parent, &dir_name)
+    }
+
+    /// Creates a directory along with any of its parents which don't exist yet,
+    /// like `mkdir -p`. Succeeds if the directory already exists.
+    ///
+    /// The segments are walked one at a time, so links are followed and ".."
+    /// goes to the parent of the directory reached so far.
+    pub fn create_directory_all(&self, path: &str) -> Result<Arc<DirectoryEntry>, IoError> {
+        let path = Path::from_str(path)?;
+        let (mut current, skip) = self.resolution_start(&path)?;
+
+        // Empty and "." segments stay in the same directory
+        let mut segments = path
+            .segments()
+            .skip(skip)
+            .filter(|segment| !matches!(*segment, "" | "."))
+            .peekable();
+
+        while let Some(segment) = segments.next() {
+            // Hold the lock while checking for the segment so that nothing can
+            // create or remove it before we do
+            let guard = current.node.structure_lock.lock();
+
+            // A link is followed, but one which dangles still takes up the
+            // name, so nothing may be created in its place
+            let entry = match self.walk(current.clone(), core::iter::once(segment), false)? {
+                Some(link) if link.node.kind == FsNodeKind::Symlink => {
+                    match self.walk(current.clone(), core::iter::once(segment), true)? {
+                        Some(target) => Some(target),
+                        None if segments.peek().is_none() => return Err(IoError::AlreadyExists),
+                        None => return Err(IoError::EntryNotFound),
+                    }
+                }
+                entry => entry,
+            };
+
+            let next = match entry {
+                Some(entry) if entry.node.is_directory() => entry,
+                // A file in place of the directory itself exists, just with
+                // the wrong kind, while one in the middle breaks the path
+                Some(_) if segments.peek().is_none() => return Err(IoError::AlreadyExists),
+                Some(_) => return Err(IoError::NotADirectory),
+                None => self.create_directory_locked(&current, segment)?,
+            };
+
+            drop(guard);
+            current = next;
+        }
+
+        Ok(current)
+    }
+
+    /// Fails with [`IoError::AlreadyExists`] if the parent has an entry with
+    /// the name. The caller must hold the structure lock of the parent so that
+    /// nothing can take the name between the check and creating the entry.
+    fn check_name_free(&self, parent: &Arc<DirectoryEntry>, name: &str) -> Result<(), IoError> {
+        // Resolve the name through the VFS so that mount points are found, and
+        // don't follow links since a dangling one still takes up the name
+        if self
+            .walk(parent.clone(), core::iter::once(name), false)?
+            .is_some()
+        {
+            return Err(IoError::AlreadyExists);
+        }
+
+        Ok(())
+    }
+
+    /// Creates a directory named `name` in the parent. The caller must hold
+    /// the structure lock of the parent.
+    fn create_directory_locked(
+        &self,
+        parent: &Arc<DirectoryEntry>,
+        name: &str,
+    ) -> Result<Arc<DirectoryEntry>, IoError> {
+        self.check_name_length(parent, name)?;
 
         let fs = parent.node.file_system();
-        let node = fs
-            .directory_operations()
-            .create_directory(&parent, &dir_name)?;
+        let node = fs.directory_operations().create_directory(parent, name)?;
 
         let entry = self
             .directory_cache
             .write()
-            .insert(Some(parent.clone()), node, dir_name);
+            .insert(Some(parent.clone()), node, name);
 
         Ok(entry)
     }
 
-    pub fn stat(&self, path: &str) -> Result<Arc<DirectoryEntry>, IoError> {
-        self.resolve_path(path)?.ok_or(IoError::EntryNotFound)
-    }
+    /// Removes a file (or device node) from its parent directory. Directories
+    /// must be removed with [`VirtualFileSystem::remove_directory`] instead.
+    pub fn remove_file(&self, path: &str) -> Result<(), IoError> {
+        let (parent, name) = self.resolve_path_parent_directory(path)?;
 
-    /// Locks the directory cache and performs a prune operation to free unused
-    /// memory. Should really only be called while the system is under high
-    /// memory pressure.
-    pub fn prune_directory_cache(&self) {
-        let mut cache = self.directory_cache.write();
-        cache.prune();
-    }
-}
+        // Lock the parent to make sure that we dont try to create or delete
+        // other entries concurrently
+        let _guard = parent.node.structure_lock.lock();
 
-pub struct VfsMount {
-    /// Uniquely identifies this mount (fs instance) within the VFS. Regenerated
-    /// on each successful mount invocation.
-    id: MountId,
-    /// A reference to the root directory which this file system is mounted on.
-    /// Keeping a strong reference here prevents the entry from ever being
+        // Resolve the name through the VFS so that mount points are also found
+        let entry = self
+            .walk(parent.clone(), core::iter::once(name.as_str()), false)?
+            .ok_or(IoError::EntryNotFound)?;
+
+        if entry.node.is_directory() {
+            return Err(IoError::NotAFile);
+        }
+
+        let fs = parent.node.file_system();
+        fs.directory_operations().remove_file(&parent, &name)?;
+
+        self.directory_cache.write().remove(&parent, &name);
+
+        // The node lives on while it has other names or is still open
+        self.release_node(&entry.node);
+
+        Ok(())
+    }
+
+    /// Creates a hard link, which is a new name at `to` for the node at
+    /// `from`. Both names refer to the same contents afterwards. Links can't
+    /// span file systems and directories can't be linked.
+    ///
+    /// A symbolic link at `from` is not followed, so the new name refers to
+    /// the link itself.
+    pub fn link(&self, from: &str, to: &str) -> Result<Arc<DirectoryEntry>, IoError> {
+        let source = self.lstat(from)?;
+
+        // Additional names for a directory would let it become its own parent
+        if source.node.is_directory() {
+            return Err(IoError::NotAFile);
+        }
+
+        if self.resolve_path_no_follow(to)?.is_some() {
+            return Err(IoError::AlreadyExists);
+        }
+
+        let (parent, link_name) = self.resolve_path_parent_directory(to)?;
+        self.check_name_length(&parent, &link_name)?;
+
+        // Nodes only exist within a single file system
+        if parent.node.mount_id != source.node.mount_id {
+            return Err(IoError::OperationNotSupported);
+        }
+
+        // Lock the parent and check again so that nothing else can create the
+        // same name before we do
+        let _guard = parent.node.structure_lock.lock();
+        self.check_name_free(&parent, &link_name)?;
+
+        let fs = parent.node.file_system();
+        fs.directory_operations()
+            .link(&parent, &link_name, &source.node)?;
+
+        source.node.increment_link_count();
+
+        let entry = self.directory_cache.write().insert(
+            Some(parent.clone()),
+            source.node.clone(),
+            link_name,
+        );
+
+        Ok(entry)
+    }
+
+    /// Removes an empty directory from its parent directory
+    pub fn remove_directory(&self, path: &str) -> Result<(), IoError> {
+        let (parent, name) = self.resolve_path_parent_directory(path)?;
+
+        // Structure locks are always acquired from the parent down to the
+        // child so that concurrent operations can't deadlock on each other
+        let _parent_guard = parent.node.structure_lock.lock();
+
+        // Resolve the name through the VFS so that mount points are also found
+        let entry = self
+            .walk(parent.clone(), core::iter::once(name.as_str()), false)?
+            .ok_or(IoError::EntryNotFound)?;
+
+        if !entry.node.is_directory() {
+            return Err(IoError::NotADirectory);
+        }
+
+        // The root of another file system is not an entry of the parent
+        if entry.node.mount_id != parent.node.mount_id {
+            return Err(IoError::Busy);
+        }
+
+        let _child_guard = entry.node.structure_lock.lock();
+
+        let fs = parent.node.file_system();
+        fs.directory_operations().remove_directory(&parent, &name)?;
+
+        self.directory_cache.write().remove(&parent, &name);
+
+        Ok(())
+    }
+
+    /// Moves the entry at one path to another path within the same file
+    /// system. An existing file at the destination is replaced, but an
+    /// existing directory is not.
+    pub fn rename(&self, from: &str, to: &str) -> Result<(), IoError> {
+        let (old_parent, old_name) = self.resolve_path_parent_directory(from)?;
+        let (new_parent, new_name) = self.resolve_path_parent_directory(to)?;
+        self.check_name_length(&new_parent, &new_name)?;
+
+        // Nodes can't be moved between file systems without copying them
+        if old_parent.node.mount_id != new_parent.node.mount_id {
+            return Err(IoError::OperationNotSupported);
+        }
+
+        // Lock both parents to make sure that nothing else is created or
+        // removed in them concurrently. If they are different, the locks are
+        // always acquired in the order of the entry ids so that two renames
+        // in opposite directions can't deadlock.
+        let (first, second) = if old_parent.id <= new_parent.id {
+            (&old_parent, &new_parent)
+        } else {
+            (&new_parent, &old_parent)
+        };
+
+        let _first_guard = first.node.structure_lock.lock();
+        let _second_guard = (first != second).then(|| second.node.structure_lock.lock());
+
+        // Resolve the names through the VFS so that mount points are also found
+        let source = self
+            .walk(
+                old_parent.clone(),
+                core::iter::once(old_name.as_str()),
+                false,
+            )?
+            .ok_or(IoError::EntryNotFound)?;
+
+        // The root of another file system is not an entry of the parent
+        if source.node.mount_id != old_parent.node.mount_id {
+            return Err(IoError::Busy);
+        }
+
+        // A directory can't be moved into itself or one of its children
+        let mut ancestor = Some(new_parent.clone());
+        while let Some(directory) = ancestor {
+            if directory == source {
+                return Err(IoError::InvalidPath);
+            }
+
+            ancestor = directory.parent.clone();
+        }
+
+        let destination = self.walk(
+            new_parent.clone(),
+            core::iter::once(new_name.as_str()),
+            false,
+        )?;
+
+        if let Some(destination) = &destination {
+            // Renaming an entry to itself does nothing
+            if destination.node == source.node {
+                return Ok(());
+            }
+
+            if destination.node.is_directory() {
+                return Err(IoError::AlreadyExists);
+            }
+
+            if source.node.is_directory() {
+                return Err(IoError::NotADirectory);
+            }
+        }
+
+        let fs = old_parent.node.file_system();
+        fs.directory_operations()
+            .rename(&old_parent, &old_name, &new_parent, &new_name)?;
+
+        // The replaced entry lost its name
+        if let Some(destination) = destination {
+            self.release_node(&destination.node);
+        }
+
+        // Names of cached entries can't change since the cache guarantees a
+        // single entry per name, so both names are evicted instead and the
+        // moved entry is looked up again under its new name when needed.
+        //
+        // FIXME: file systems mounted below a moved directory stay attached
+        // to the old entry
+        let mut cache = self.directory_cache.write();
+        cache.remove(&old_parent, &old_name);
+        cache.remove(&new_parent, &new_name);
+
+        Ok(())
+    }
+
+    /// Copies the contents of a regular file to another path, creating the
+    /// destination or replacing its contents if it already exists. If the
+    /// destination is an existing directory, the file is copied into it under
+    /// its original name.
+    ///
+    /// `progress` is called after each chunk with the total number of bytes
+    /// copied so far. Returns the number of bytes copied.
+    pub fn copy_file(
+        &self,
+        from: &str,
+        to: &str,
+        mut progress: impl FnMut(usize),
+    ) -> Result<usize, IoError> {
+        let source = self.stat(from)?;
+
+        // Devices can be infinite, so only regular files are copied
+        if source.node.kind != FsNodeKind::File {
+            return Err(IoError::NotAFile);
+        }
+
+        let destination = match self.resolve_path(to)? {
+            Some(entry) if entry.node.is_directory() => {
+                let path = Path::from_str(from)?;
+                let name = path
+                    .segments()
+                    .filter(|s| !s.is_empty())
+                    .last()
+                    .ok_or(IoError::InvalidPath)?;
+
+                format!("{}/{}", to.trim_end_matches('/'), name)
+            }
+            _ => to.to_string(),
+        };
+
+        // Opening the source as the destination would truncate it before any
+        // of it is read
+        if self
+            .resolve_path(&destination)?
+            .is_some_and(|entry| entry.node == source.node)
+        {
+            return Err(IoError::SameFile);
+        }
+
+        let source_fd = self.open(from, FileMode::Read)?;
+        let _close_source = defer_handle!({
+            let _ = self.close(source_fd);
+        });
+
+        let destination_fd = self.open(&destination, FileMode::Write)?;
+        let _close_destination = defer_handle!({
+            let _ = self.close(destination_fd);
+        });
+
+        let mut buffer = [0u8; 512];
+        let mut copied = 0;
+
+        loop {
+            let n = self.read(source_fd, &mut buffer)?;
+
+            if n == 0 {
+                return Ok(copied);
+            }
+
+            // Writes may be partial, so keep going until the chunk is done
+            let mut written = 0;
+            while written < n {
+                match self.write(destination_fd, &buffer[written..n])? {
+                    0 => return Err(IoError::NoSpaceLeft),
+                    w => written += w,
+                }
+            }
+
+            copied += n;
+            progress(copied);
+        }
+    }
+
+    /// Returns the directory which relative paths are currently resolved
+    /// against
+    pub fn current_directory(&self) -> Result<Arc<DirectoryEntry>, IoError> {
+        if let Some(directory) = self.working_directory.read().clone() {
+            return Ok(directory);
+        }
+
+        self.directory_cache
+            .read()
+            .get_root()
+            .ok_or(IoError::NoRootDirectory)
+    }
+
+    /// Changes the directory which relative paths are resolved against. The
+    /// path may itself be relative to the current working directory.
+    pub fn change_directory(&self, path: &str) -> Result<Arc<DirectoryEntry>, IoError> {
+        let directory = self.resolve_path(path)?.ok_or(IoError::EntryNotFound)?;
+
+        if !directory.node.is_directory() {
+            return Err(IoError::NotADirectory);
+        }
+
+        // Keep the file system from being unmounted from under us
+        self.acquire_mount(directory.node.mount_id)?;
+
+        let previous = self.working_directory.write().replace(directory.clone());
+        if let Some(previous) = previous {
+            self.release_mount(previous.node.mount_id);
+        }
+
+        Ok(directory)
+    }
+
+    pub fn stat(&self, path: &str) -> Result<Arc<DirectoryEntry>, IoError> {
+        self.resolve_path(path)?.ok_or(IoError::EntryNotFound)
+    }
+
+    /// Updates the access and/or modification time of an existing entry to the
+    /// current time, like `touch` does. The entry is never opened, so its
+    /// contents stay the same.
+    pub fn touch(
+        &self,
+        path: &str,
+        accessed: bool,
+        modified: bool,
+    ) -> Result<Arc<DirectoryEntry>, IoError> {
+        let entry = self.stat(path)?;
+        entry.node.metadata.lock().touch(accessed, modified);
+
+        Ok(entry)
+    }
+
+    /// Replaces the permission bits of an existing entry. Links are followed,
+    /// so the permissions of the target are changed.
+    pub fn chmod(&self, path: &str, mode: u16) -> Result<Arc<DirectoryEntry>, IoError> {
+        let entry = self.stat(path)?;
+
+        {
+            let mut meta = entry.node.metadata.lock();
+            meta.mode = mode & MODE_MASK;
+            meta.dirty = true;
+        }
+
+        Ok(entry)
+    }
+
+    /// Same as [`VirtualFileSystem::stat`], except that if the path names a
+    /// symbolic link, the link itself is returned instead of its target
+    pub fn lstat(&self, path: &str) -> Result<Arc<DirectoryEntry>, IoError> {
+        self.resolve_path_no_follow(path)?
+            .ok_or(IoError::EntryNotFound)
+    }
+
+    /// Creates a symbolic link at `path` which points to `target`. The target
+    /// is stored as given and is not required to exist. A relative target is
+    /// resolved against the directory containing the link each time the link
+    /// is followed.
+    pub fn create_symlink(&self, target: &str, path: &str) -> Result<Arc<DirectoryEntry>, IoError> {
+        // Make sure the target can be parsed when the link is followed later
+        Path::from_str(target)?;
+
+        if self.resolve_path_no_follow(path)?.is_some() {
+            return Err(IoError::AlreadyExists);
+        }
+
+        let (parent, link_name) = self.resolve_path_parent_directory(path)?;
+        self.check_name_length(&parent, &link_name)?;
+
+        // Lock the parent and check again so that nothing else can create the
+        // same name before we do
+        let _guard = parent.node.structure_lock.lock();
+        self.check_name_free(&parent, &link_name)?;
+
+        let fs = parent.node.file_system();
+        let node = fs
+            .directory_operations()
+            .create_symlink(&parent, &link_name, target)?;
+
+        let entry = self
+            .directory_cache
+            .write()
+            .insert(Some(parent.clone()), node, link_name);
+
+        Ok(entry)
+    }
+
+    /// Returns the target of the symbolic link at the provided path
+    pub fn read_link(&self, path: &str) -> Result<String, IoError> {
+        let link = self.lstat(path)?;
+
+        if link.node.kind != FsNodeKind::Symlink {
+            return Err(IoError::InvalidPath);
+        }
+
+        let fs = link.node.file_system();
+        fs.file_operations().readlink(&link.node)
+    }
+
+    /// Returns true if an entry exists at the provided path.
+    ///
+    /// A path which cannot be resolved because a segment is missing, the path
+    /// is malformed or it passes through something other than a directory is
+    /// treated as absent. Any other error (i.e. the root has not been mounted
+    /// yet or the fs driver failed to perform a lookup) is propagated since it
+    /// says nothing about whether the entry exists.
+    pub fn exists(&self, path: &str) -> Result<bool, IoError> {
+        Ok(self.try_stat(path)?.is_some())
+    }
+
+    /// Returns true if the provided path exists and refers to a directory. See
+    /// [`VirtualFileSystem::exists`] for how resolution errors are handled.
+    pub fn is_dir(&self, path: &str) -> Result<bool, IoError> {
+        Ok(self
+            .try_stat(path)?
+            .is_some_and(|entry| entry.node.is_directory()))
+    }
+
+    /// Returns true if the provided path exists and refers to a regular file.
+    /// See [`VirtualFileSystem::exists`] for how resolution errors are handled.
+    pub fn is_file(&self, path: &str) -> Result<bool, IoError> {
+        Ok(self
+            .try_stat(path)?
+            .is_some_and(|entry| entry.node.kind == FsNodeKind::File))
+    }
+
+    /// Resolves a path, mapping errors which mean that the entry cannot exist
+    /// to None
+    fn try_stat(&self, path: &str) -> Result<Option<Arc<DirectoryEntry>>, IoError> {
+        match self.resolve_path(path) {
+            Ok(entry) => Ok(entry),
+            Err(
+                IoError::EntryNotFound
+                | IoError::NotADirectory
+                | IoError::InvalidPath
+                | IoError::NameTooLong
+                | IoError::TooManySymlinks,
+            ) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reports the space usage of the file system which contains the provided
+    /// path
+    pub fn statfs(&self, path: &str) -> Result<FileSystemStats, IoError> {
+        let entry = self.stat(path)?;
+
+        entry.node.file_system().statfs()
+    }
+
+    /// Flushes every mounted file system to its backing device. All mounts are
+    /// synced even if one of them fails, in which case the first error is
+    /// returned.
+    pub fn sync(&self) -> Result<(), IoError> {
+        // Don't hold the mount table lock while performing io
+        let mounts = self
+            .mount_table
+            .read()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut result = Ok(());
+
+        for mount in mounts {
+            if let Err(e) = mount.file_system.sync() {
+                result = result.and(Err(e));
+            }
+        }
+
+        result
+    }
+
+    /// Locks the directory cache and performs a prune operation to free unused
+    /// memory. Should really only be called while the system is under high
+    /// memory pressure.
+    pub fn prune_directory_cache(&self) {
+        let mut cache = self.directory_cache.write();
+        cache.prune();
+    }
+}
+
+pub struct VfsMount {
+    /// Uniquely identifies this mount (fs instance) within the VFS. Regenerated
+    /// on each successful mount invocation.
+    id: MountId,
+    /// The device or path the file system was mounted from. Empty for virtual
+    /// file systems.
+    source: String,
+    /// A reference to the root directory which this file system is mounted on.
+    /// Keeping a strong reference here prevents the entry from ever being
     /// evicted from the directory cache
     root: Arc<DirectoryEntry>,
     /// A reference to the instance of the mounted file system
     pub file_system: Arc<dyn FileSystem>,
-    // TODO: do we need a counter of references to this mount so we know if we
-    // can safely unmount it?
+    /// The number of open files and working directories on this file system.
+    /// It can only be unmounted while this is 0.
+    references: AtomicUsize,
+}
+
+/// A snapshot of a single entry in the mount table
+#[derive(Debug, Clone)]
+pub struct MountInfo {
+    pub id: MountId,
+    pub source: String,
+    /// The absolute path the file system is mounted at
+    pub target: String,
+    pub file_system_type: &'static str,
+    pub flags: MountFlags,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -551,6 +1596,10 @@ impl MountId {
 
         Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
 }
 
 /// Entries can only be created by the DirectoryCache. This ensures that no more
@@ -591,6 +1640,32 @@ impl PartialEq for DirectoryEntry {
 }
 
 impl DirectoryEntry {
+    /// Reconstructs the absolute path of this entry in the VFS by walking the
+    /// parent links back up to the root. Since the root of a mounted file
+    /// system is named after its mount point, this is the path as seen through
+    /// the VFS rather than a path within the mounted file system.
+    pub fn path(&self) -> String {
+        let mut names = Vec::new();
+        let mut current = self;
+
+        while let Some(parent) = &current.parent {
+            names.push(current.name.clone());
+            current = parent.as_ref();
+        }
+
+        if names.is_empty() {
+            return "/".to_string();
+        }
+
+        let mut path = String::new();
+        for name in names.iter().rev() {
+            path.push('/');
+            path.push_str(name);
+        }
+
+        path
+    }
+
     /// Removes entries in the child cache which have already been garbage
     /// collected
     fn prune_children(&self) {
@@ -694,6 +1769,25 @@ impl DirectoryCache {
         self.table.get(&key).and_then(|w| w.upgrade())
     }
 
+    /// Evicts an entry from the cache (i.e. after it was removed from disk) so
+    /// that subsequent lookups go back to the file system. Existing strong
+    /// references to the entry stay valid but can no longer be found by name.
+    fn remove(&mut self, parent: &Arc<DirectoryEntry>, name: &str) {
+        let key = DirectoryCacheKey(parent.id, name.into());
+        self.table.remove(&key);
+
+        parent.children.write().remove(name);
+    }
+
+    /// Evicts every entry which belongs to the given mount, along with its root
+    /// which is attached to the parent under the given name
+    fn evict_mount(&mut self, parent: &Arc<DirectoryEntry>, name: &str, id: MountId) {
+        self.remove(parent, name);
+
+        self.table
+            .retain(|_, w| w.upgrade().is_some_and(|e| e.node.mount_id != id));
+    }
+
     /// Removes any entries from the table which havve a reference count of 0
     fn prune(&mut self) {
         self.table.retain(|_, w| w.strong_count() > 0);
@@ -759,6 +1853,30 @@ impl IntoIterator for DirectoryIterationContext {
     }
 }
 
+/// The most bytes which are passed to the driver of the file system in a single
+/// read or write
+fn max_transfer_size(fs: &Arc<dyn FileSystem>) -> usize {
+    fs.metadata().block_size.max(1) * MAX_TRANSFER_BLOCKS
+}
+
+/// Checks the permission bits of a node against the mode it is being opened
+/// with. Everything is owned by `root`, so only the owner bits are checked.
+///
+/// FIXME: check the group and other bits once there are other users
+fn check_access(node: &FsNode, mode: FileMode) -> Result<(), IoError> {
+    let required = if mode.is_mutating() {
+        MODE_OWNER_WRITE
+    } else {
+        MODE_OWNER_READ
+    };
+
+    if node.metadata.lock().mode & required == 0 {
+        return Err(IoError::PermissionDenied);
+    }
+
+    Ok(())
+}
+
 static VFS: OnceCell<VirtualFileSystem> = OnceCell::uninit();
 
 /// Allocates memory for the VFS and mounts the init ram fs
@@ -774,6 +1892,9 @@ pub fn init() {
     )
     .expect("Failed to mount devfs");
 
+    vfs.mount("", "/proc", Some("procfs"), MountFlags::READ)
+        .expect("Failed to mount procfs");
+
     let f = vfs
         .open("/test.txt", FileMode::Write)
         .expect("Failed to open file for writing");
@@ -787,3 +1908,498 @@ pub fn init() {
 pub fn get() -> &'static VirtualFileSystem {
     VFS.get().expect("VFS not yet initialized")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fs::path::MAX_NAME_LENGTH, testing::scratch_directory, util::time};
+
+    /// Opens a file with the given mode and writes data to it
+    fn write_with_mode(path: &str, mode: FileMode, data: &[u8]) {
+        let vfs = get();
+        let fd = vfs.open(path, mode).unwrap();
+        assert_eq!(vfs.write(fd, data), Ok(data.len()));
+        vfs.close(fd).unwrap();
+    }
+
+    /// Reads a file from the start until a read comes back empty
+    fn read_contents(path: &str) -> Vec<u8> {
+        let vfs = get();
+        let fd = vfs.open(path, FileMode::Read).unwrap();
+
+        let mut contents = Vec::new();
+        let mut buffer = [0u8; 64];
+        loop {
+            let n = vfs.read(fd, &mut buffer).unwrap();
+            if n == 0 {
+                break;
+            }
+
+            contents.extend_from_slice(&buffer[..n]);
+        }
+
+        vfs.close(fd).unwrap();
+        contents
+    }
+
+    #[test_case]
+    fn create_directory_all_creates_parents() {
+        let scratch = scratch_directory("create_directory_all");
+        let vfs = get();
+
+        vfs.create_directory_all(&format!("{}/a/b/c", scratch))
+            .unwrap();
+
+        for path in ["a", "a/b", "a/b/c"] {
+            assert_eq!(vfs.is_dir(&format!("{}/{}", scratch, path)), Ok(true));
+        }
+
+        // Creating it again is not an error
+        vfs.create_directory_all(&format!("{}/a/b/c", scratch))
+            .unwrap();
+        vfs.create_directory_all(&format!("{}/a/b/../b/./c/", scratch))
+            .unwrap();
+    }
+
+    #[test_case]
+    fn create_directory_all_stops_at_files() {
+        let scratch = scratch_directory("create_directory_all_files");
+        let vfs = get();
+
+        let file = format!("{}/file", scratch);
+        vfs.close(vfs.open(&file, FileMode::Write).unwrap())
+            .unwrap();
+
+        assert_eq!(
+            vfs.create_directory_all(&file).err(),
+            Some(IoError::AlreadyExists)
+        );
+        assert_eq!(
+            vfs.create_directory_all(&format!("{}/x", file)).err(),
+            Some(IoError::NotADirectory)
+        );
+    }
+
+    #[test_case]
+    fn create_directory_all_follows_links() {
+        let scratch = scratch_directory("create_directory_all_links");
+        let vfs = get();
+
+        vfs.create_directory(&format!("{}/target", scratch))
+            .unwrap();
+        vfs.create_symlink("target", &format!("{}/link", scratch))
+            .unwrap();
+
+        vfs.create_directory_all(&format!("{}/link/x", scratch))
+            .unwrap();
+        assert_eq!(vfs.is_dir(&format!("{}/target/x", scratch)), Ok(true));
+    }
+
+    #[test_case]
+    fn create_directory_all_keeps_dangling_links() {
+        let scratch = scratch_directory("create_directory_all_dangling");
+        let vfs = get();
+
+        let link = format!("{}/link", scratch);
+        vfs.create_symlink("missing", &link).unwrap();
+
+        assert_eq!(
+            vfs.create_directory_all(&link).err(),
+            Some(IoError::AlreadyExists)
+        );
+        assert_eq!(
+            vfs.create_directory_all(&format!("{}/x", link)).err(),
+            Some(IoError::EntryNotFound)
+        );
+
+        assert_eq!(vfs.lstat(&link).unwrap().node.kind, FsNodeKind::Symlink);
+    }
+
+    #[test_case]
+    fn exists_reports_kinds() {
+        let scratch = scratch_directory("exists");
+        let vfs = get();
+
+        let file = format!("{}/file", scratch);
+        let fd = vfs.open(&file, FileMode::Write).unwrap();
+        vfs.close(fd).unwrap();
+
+        assert_eq!(vfs.exists(&file), Ok(true));
+        assert_eq!(vfs.is_file(&file), Ok(true));
+        assert_eq!(vfs.is_dir(&file), Ok(false));
+
+        assert_eq!(vfs.exists(&scratch), Ok(true));
+        assert_eq!(vfs.is_file(&scratch), Ok(false));
+        assert_eq!(vfs.is_dir(&scratch), Ok(true));
+
+        // Devices are neither files nor directories
+        assert_eq!(vfs.exists("/dev/null"), Ok(true));
+        assert_eq!(vfs.is_file("/dev/null"), Ok(false));
+        assert_eq!(vfs.is_dir("/dev/null"), Ok(false));
+    }
+
+    #[test_case]
+    fn exists_treats_unresolvable_paths_as_absent() {
+        let scratch = scratch_directory("exists_unresolvable");
+        let vfs = get();
+
+        let file = format!("{}/file", scratch);
+        let fd = vfs.open(&file, FileMode::Write).unwrap();
+        vfs.close(fd).unwrap();
+
+        let long_name = format!("{}/{}", scratch, "a".repeat(MAX_NAME_LENGTH + 1));
+        let paths = [
+            format!("{}/missing", scratch),
+            format!("{}/missing/file", scratch),
+            // Passes through something which isn't a directory
+            format!("{}/file/child", scratch),
+            String::new(),
+            long_name,
+        ];
+
+        for path in &paths {
+            assert_eq!(vfs.exists(path), Ok(false));
+            assert_eq!(vfs.is_file(path), Ok(false));
+            assert_eq!(vfs.is_dir(path), Ok(false));
+        }
+    }
+
+    #[test_case]
+    fn append_writes_at_end() {
+        let path = format!("{}/file", scratch_directory("append"));
+
+        write_with_mode(&path, FileMode::Write, b"abc");
+        write_with_mode(&path, FileMode::Append, b"def");
+        assert_eq!(read_contents(&path), b"abcdef");
+
+        // Appends go to the end even after the cursor was moved back
+        let vfs = get();
+        let fd = vfs.open(&path, FileMode::Append).unwrap();
+        vfs.seek(fd, SeekFrom::Start(0)).unwrap();
+        vfs.write(fd, b"g").unwrap();
+        vfs.close(fd).unwrap();
+        assert_eq!(read_contents(&path), b"abcdefg");
+    }
+
+    #[test_case]
+    fn write_mode_truncates_existing_files() {
+        let path = format!("{}/file", scratch_directory("truncate"));
+
+        write_with_mode(&path, FileMode::Write, b"a longer line\n");
+        write_with_mode(&path, FileMode::Write, b"short\n");
+
+        assert_eq!(read_contents(&path), b"short\n");
+        assert_eq!(get().stat(&path).unwrap().node.metadata.lock().size, 6);
+
+        // Appending keeps what is already there
+        write_with_mode(&path, FileMode::Append, b"more\n");
+        assert_eq!(read_contents(&path), b"short\nmore\n");
+    }
+
+    #[test_case]
+    fn reads_and_writes_update_timestamps() {
+        let path = format!("{}/file", scratch_directory("timestamps"));
+        write_with_mode(&path, FileMode::Write, b"abc");
+
+        let vfs = get();
+        let node = vfs.stat(&path).unwrap().node.clone();
+
+        // Backdate the node so that the update is visible without waiting for
+        // the clock to reach the next second
+        let reset = || {
+            let mut metadata = node.metadata.lock();
+            metadata.accessed_at = 0;
+            metadata.modified_at = 0;
+            metadata.dirty = false;
+        };
+
+        reset();
+        let before = time::now();
+        assert_eq!(read_contents(&path), b"abc");
+        {
+            let metadata = node.metadata.lock();
+            assert!(metadata.accessed_at >= before);
+            assert_eq!(metadata.modified_at, 0);
+            assert!(metadata.dirty);
+        }
+
+        reset();
+        write_with_mode(&path, FileMode::Append, b"def");
+        let metadata = node.metadata.lock();
+        assert!(metadata.accessed_at >= before);
+        assert!(metadata.modified_at >= before);
+        assert!(metadata.dirty);
+    }
+
+    #[test_case]
+    fn hard_links_share_contents() {
+        let scratch = scratch_directory("hard_links");
+        let vfs = get();
+
+        let original = format!("{}/original", scratch);
+        let link = format!("{}/link", scratch);
+        write_with_mode(&original, FileMode::Write, b"abc");
+        vfs.link(&original, &link).unwrap();
+
+        let node = vfs.stat(&original).unwrap().node.clone();
+        assert_eq!(vfs.stat(&link).unwrap().node, node);
+        assert_eq!(node.metadata.lock().link_count, 2);
+
+        // A change through either name shows up through the other
+        write_with_mode(&link, FileMode::Append, b"def");
+        assert_eq!(read_contents(&original), b"abcdef");
+        write_with_mode(&original, FileMode::Write, b"new");
+        assert_eq!(read_contents(&link), b"new");
+
+        // The contents stay around until the last name is removed
+        vfs.remove_file(&original).unwrap();
+        assert_eq!(node.metadata.lock().link_count, 1);
+        assert_eq!(read_contents(&link), b"new");
+    }
+
+    #[test_case]
+    fn hard_links_are_restricted() {
+        let scratch = scratch_directory("hard_link_limits");
+        let vfs = get();
+
+        let file = format!("{}/file", scratch);
+        write_with_mode(&file, FileMode::Write, b"");
+
+        assert_eq!(
+            vfs.link(&file, "/dev/file").err(),
+            Some(IoError::OperationNotSupported)
+        );
+        assert_eq!(
+            vfs.link(&scratch, &format!("{}/directory", scratch)).err(),
+            Some(IoError::NotAFile)
+        );
+        assert_eq!(vfs.link(&file, &file).err(), Some(IoError::AlreadyExists));
+    }
+
+    #[test_case]
+    fn statfs_reports_the_containing_file_system() {
+        let vfs = get();
+
+        // Devices don't take up any space
+        let stats = vfs.statfs("/dev/null").unwrap();
+        assert_eq!(stats.used_blocks, 0);
+        assert_eq!(stats.total_blocks(), Some(0));
+
+        assert_eq!(vfs.statfs("/missing").err(), Some(IoError::EntryNotFound));
+    }
+
+    #[test_case]
+    fn exclusive_create_makes_new_file() {
+        let scratch = scratch_directory("exclusive_create_new");
+        let vfs = get();
+
+        let path = format!("{}/file", scratch);
+        let fd = vfs
+            .open_with_flags(&path, FileMode::Write, OpenFlags::EXCL)
+            .unwrap();
+        vfs.write(fd, b"abc").unwrap();
+        vfs.close(fd).unwrap();
+
+        assert_eq!(vfs.is_file(&path), Ok(true));
+    }
+
+    #[test_case]
+    fn exclusive_create_fails_on_existing_names() {
+        let scratch = scratch_directory("exclusive_create_existing");
+        let vfs = get();
+
+        let path = format!("{}/file", scratch);
+        vfs.close(
+            vfs.open_with_flags(&path, FileMode::Write, OpenFlags::EXCL)
+                .unwrap(),
+        )
+        .unwrap();
+
+        let fd = vfs.open(&path, FileMode::Write).unwrap();
+        vfs.write(fd, b"abc").unwrap();
+        vfs.close(fd).unwrap();
+
+        assert_eq!(
+            vfs.open_with_flags(&path, FileMode::Write, OpenFlags::EXCL),
+            Err(IoError::AlreadyExists)
+        );
+
+        // The failed open must not have truncated the file
+        let fd = vfs.open(&path, FileMode::Read).unwrap();
+        let mut buffer = [0; 8];
+        assert_eq!(vfs.read(fd, &mut buffer), Ok(3));
+        assert_eq!(&buffer[..3], b"abc");
+        vfs.close(fd).unwrap();
+
+        // Directories and links count as existing names too, even dangling
+        // ones
+        let link = format!("{}/link", scratch);
+        vfs.create_symlink("missing", &link).unwrap();
+
+        for path in [scratch.as_str(), link.as_str()] {
+            assert_eq!(
+                vfs.open_with_flags(path, FileMode::Write, OpenFlags::EXCL),
+                Err(IoError::AlreadyExists)
+            );
+        }
+    }
+
+    #[test_case]
+    fn copy_file_refuses_same_file() {
+        let scratch = scratch_directory("copy_file_same");
+        let vfs = get();
+
+        let path = format!("{}/file", scratch);
+        let fd = vfs.open(&path, FileMode::Write).unwrap();
+        vfs.write(fd, b"abc").unwrap();
+        vfs.close(fd).unwrap();
+
+        // Directly, through "." and by copying into its own directory
+        for destination in [path.clone(), format!("{}/./file", scratch), scratch.clone()] {
+            assert_eq!(
+                vfs.copy_file(&path, &destination, |_| {}),
+                Err(IoError::SameFile)
+            );
+        }
+
+        // The file must not have been truncated
+        assert_eq!(
+            vfs.copy_file(&path, &format!("{}/copy", scratch), |_| {}),
+            Ok(3)
+        );
+    }
+
+    #[test_case]
+    fn creating_over_existing_names_fails() {
+        let scratch = scratch_directory("create_existing");
+        let vfs = get();
+
+        let file = format!("{}/file", scratch);
+        let link = format!("{}/link", scratch);
+        vfs.close(vfs.open(&file, FileMode::Write).unwrap())
+            .unwrap();
+        vfs.create_symlink("missing", &link).unwrap();
+
+        for name in [&file, &link] {
+            assert_eq!(
+                vfs.create_symlink("x", name).err(),
+                Some(IoError::AlreadyExists)
+            );
+            assert_eq!(vfs.link(&file, name).err(), Some(IoError::AlreadyExists));
+            assert_eq!(
+                vfs.create_directory(name).err(),
+                Some(IoError::AlreadyExists)
+            );
+        }
+    }
+
+    #[test_case]
+    fn parent_of_mount_root_is_in_parent_mount() {
+        let vfs = get();
+        let root = vfs.stat("/").unwrap();
+        let dev = vfs.stat("/dev").unwrap();
+
+        assert_ne!(dev.node.mount_id, root.node.mount_id);
+        assert_eq!(vfs.stat("/dev/..").unwrap().node, root.node);
+        assert_eq!(vfs.stat("/dev/../dev/null").unwrap().path(), "/dev/null");
+
+        // The same for a mount below the root directory
+        let scratch = scratch_directory("mount_parent");
+        let mnt = format!("{}/mnt", scratch);
+        vfs.mount(
+            "",
+            &mnt,
+            Some("ramfs"),
+            MountFlags::READ | MountFlags::WRITE,
+        )
+        .unwrap();
+
+        let parent = vfs.stat(&format!("{}/..", mnt)).unwrap();
+        assert_eq!(parent.path(), scratch);
+        assert_eq!(
+            parent.node.mount_id,
+            vfs.stat(&scratch).unwrap().node.mount_id
+        );
+
+        vfs.unmount(&mnt).unwrap();
+    }
+
+    #[test_case]
+    fn change_directory_across_mounts() {
+        let vfs = get();
+        let previous = vfs.current_directory().unwrap().path();
+
+        vfs.change_directory("/dev").unwrap();
+        assert_eq!(vfs.current_directory().unwrap().path(), "/dev");
+        // Relative paths resolve within the mounted file system
+        assert_eq!(vfs.stat("null").unwrap().path(), "/dev/null");
+
+        vfs.change_directory("..").unwrap();
+        let current = vfs.current_directory().unwrap();
+        assert_eq!(current.path(), "/");
+        assert_eq!(current.node.mount_id, vfs.stat("/").unwrap().node.mount_id);
+
+        vfs.change_directory(&previous).unwrap();
+    }
+
+    #[test_case]
+    fn mount_targets_must_be_directories() {
+        let scratch = scratch_directory("mount_targets");
+        let vfs = get();
+        let flags = MountFlags::READ | MountFlags::WRITE;
+
+        let file = format!("{}/file", scratch);
+        let fd = vfs.open(&file, FileMode::Write).unwrap();
+        vfs.close(fd).unwrap();
+
+        assert_eq!(
+            vfs.mount("", &file, Some("ramfs"), flags),
+            Err(IoError::NotADirectory)
+        );
+        // A new mount point can't be created inside of a file either
+        assert_eq!(
+            vfs.mount("", &format!("{}/mnt", file), Some("ramfs"), flags),
+            Err(IoError::NotADirectory)
+        );
+        assert_eq!(vfs.is_file(&file), Ok(true));
+
+        let directory = format!("{}/directory", scratch);
+        vfs.create_directory(&directory).unwrap();
+        let id = vfs.mount("", &directory, Some("ramfs"), flags).unwrap();
+
+        assert_eq!(vfs.stat(&directory).unwrap().node.mount_id, id);
+        vfs.unmount(&directory).unwrap();
+        assert_eq!(vfs.is_dir(&directory), Ok(true));
+    }
+
+    #[test_case]
+    fn mount_shadows_directory_contents() {
+        let mnt = format!("{}/mnt", scratch_directory("mount_shadows"));
+        let vfs = get();
+
+        vfs.create_directory(&mnt).unwrap();
+        let old = format!("{}/old", mnt);
+        write_with_mode(&old, FileMode::Write, b"old");
+
+        vfs.mount(
+            "",
+            &mnt,
+            Some("ramfs"),
+            MountFlags::READ | MountFlags::WRITE,
+        )
+        .unwrap();
+
+        // Only the new file system is visible while it is mounted
+        assert_eq!(vfs.exists(&old), Ok(false));
+        assert_eq!(vfs.read_directory(&mnt).unwrap().into_iter().count(), 0);
+
+        let new = format!("{}/new", mnt);
+        write_with_mode(&new, FileMode::Write, b"new");
+        assert_eq!(read_contents(&new), b"new");
+
+        vfs.unmount(&mnt).unwrap();
+
+        assert_eq!(read_contents(&old), b"old");
+        assert_eq!(vfs.exists(&new), Ok(false));
+    }
+}
@@ -1,15 +1,27 @@
+use conquer_once::spin::OnceCell;
 use static_cell::StaticCell;
 use x86_64::{
     VirtAddr,
-    registers::segmentation::Segment,
+    registers::segmentation::{CS, DS, ES, SS, Segment},
     structures::{
-        gdt::{Descriptor, GlobalDescriptorTable},
+        gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector},
         tss::TaskStateSegment,
     },
 };
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
+/// The selectors of the GDT segments which are needed after initialization.
+/// Both have a requested privilege level of 3, so they can be used to enter
+/// ring 3.
+#[derive(Debug)]
+pub struct Selectors {
+    pub user_data: SegmentSelector,
+    pub user_code: SegmentSelector,
+}
+
+static SELECTORS: OnceCell<Selectors> = OnceCell::uninit();
+
 /// Initializes the Global Descriptor Table (GDT) and the Task State Segment
 /// (TSS). Must only be called once during initialization to prevent a panic.
 #[allow(clippy::let_and_return)]
@@ -23,7 +35,12 @@ pub fn init() {
         .try_init(GlobalDescriptorTable::new())
         .expect("Tried to initialize GDT more than once");
 
-    let code_segment = gdt.append(Descriptor::kernel_code_segment());
+    // NOTE: the user data segment has to come right before the user code
+    // segment in case `sysret` is used later, which expects them in that order
+    let kernel_code = gdt.append(Descriptor::kernel_code_segment());
+    let kernel_data = gdt.append(Descriptor::kernel_data_segment());
+    let user_data = gdt.append(Descriptor::user_data_segment());
+    let user_code = gdt.append(Descriptor::user_code_segment());
 
     /* Init TSS */
 
@@ -41,6 +58,19 @@ pub fn init() {
         stack_end
     };
 
+    // The CPU switches to this stack whenever an interrupt or syscall arrives
+    // while ring 3 code is running, since the stack of the user program can't
+    // be trusted
+    tss.privilege_stack_table[0] = {
+        const STACK_SIZE: usize = 4096 * 8;
+        static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+
+        let stack_start = VirtAddr::from_ptr(&raw const STACK);
+        let stack_end = stack_start + STACK_SIZE as u64;
+
+        stack_end
+    };
+
     let tss_segment = gdt.append(Descriptor::tss_segment(tss));
 
     /* Load GDT and TSS */
@@ -48,7 +78,49 @@ pub fn init() {
     gdt.load();
 
     unsafe {
-        x86_64::registers::segmentation::CS::set_reg(code_segment);
+        CS::set_reg(kernel_code);
+        SS::set_reg(kernel_data);
+        DS::set_reg(kernel_data);
+        ES::set_reg(kernel_data);
         x86_64::instructions::tables::load_tss(tss_segment);
     }
+
+    // A stack which is missing from the TSS only shows up once it is needed,
+    // as a triple fault instead of a double fault or as a crash on the first
+    // interrupt in ring 3, so make sure everything is in place right away
+    assert_eq!(CS::get_reg(), kernel_code, "kernel code segment not loaded");
+    assert_eq!(task_register(), tss_segment, "TSS not loaded");
+    assert_ne!(tss_segment.0, 0, "TSS selector is null");
+    assert!(
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize].as_u64() != 0,
+        "double fault stack not installed"
+    );
+    assert!(
+        tss.privilege_stack_table[0].as_u64() != 0,
+        "ring 0 stack not installed"
+    );
+
+    SELECTORS
+        .try_init_once(|| Selectors {
+            user_data,
+            user_code,
+        })
+        .expect("Tried to initialize GDT more than once");
+}
+
+/// Reads the selector of the TSS which is currently loaded
+fn task_register() -> SegmentSelector {
+    let selector: u16;
+
+    // SAFETY: storing the task register has no side effects
+    unsafe {
+        core::arch::asm!("str {0:x}", out(reg) selector, options(nomem, nostack, preserves_flags));
+    }
+
+    SegmentSelector(selector)
+}
+
+/// Returns the selectors of the segments in the GDT
+pub fn selectors() -> &'static Selectors {
+    SELECTORS.get().expect("GDT not yet initialized")
 }
@@ -1,9 +1,15 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use pic8259::ChainedPics;
 use spin::Mutex;
 use static_cell::StaticCell;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::{
+    PrivilegeLevel, VirtAddr,
+    instructions::port::Port,
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
+};
 
-use crate::{gdt, vga::println};
+use crate::{gdt, memory, user::syscall, vga::println};
 
 /// Initializes the Interrupt Descriptor Table (IDT). Must only be called once
 /// during initialization to prevent a panic.
@@ -14,7 +20,13 @@ pub fn init_idt() {
         .try_init(InterruptDescriptorTable::new())
         .expect("Tried to initialize IDT more than once");
 
+    idt.divide_error.set_handler_fn(divide_error_handler);
     idt.breakpoint.set_handler_fn(breakpoint_handler);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+    idt.stack_segment_fault
+        .set_handler_fn(stack_segment_fault_handler);
+    idt.general_protection_fault
+        .set_handler_fn(general_protection_fault_handler);
     idt.page_fault.set_handler_fn(page_fault_handler);
 
     unsafe {
@@ -25,6 +37,16 @@ pub fn init_idt() {
 
     idt[InterruptIndex::Timer.as_u8()].set_handler_fn(timer_interrupt_handler);
     idt[InterruptIndex::Keyboard.as_u8()].set_handler_fn(keyboard_interrupt_handler);
+    idt[InterruptIndex::Floppy.as_u8()].set_handler_fn(floppy_interrupt_handler);
+
+    // The system call gate is the only one which ring 3 is allowed to raise
+    // with `int`. Its entry saves the registers itself, so it is installed by
+    // address rather than as an `x86-interrupt` function.
+    unsafe {
+        idt[syscall::SYSCALL_INTERRUPT]
+            .set_handler_addr(VirtAddr::new(syscall::syscall_entry as *const () as u64))
+            .set_privilege_level(PrivilegeLevel::Ring3);
+    }
 
     idt.load();
 }
@@ -39,11 +61,94 @@ extern "x86-interrupt" fn page_fault_handler(
 ) {
     use x86_64::registers::control::Cr2;
 
+    // Pages which are mapped on demand are simply mapped, after which the
+    // faulting instruction is retried. Anything else is a genuine fault.
+    if let Ok(address) = Cr2::read()
+        && memory::handle_page_fault(address, error_code)
+    {
+        return;
+    }
+
     println!("EXCEPTION: PAGE FAULT");
     println!("Accessed Address: {:?}", Cr2::read());
     println!("Error Code: {:?}", error_code);
     println!("{:#?}", stack_frame);
 
+    hlt_loop();
+}
+
+extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
+    println!("EXCEPTION: DIVIDE ERROR");
+    println!("Instruction Pointer: {:?}", stack_frame.instruction_pointer);
+    println!("{:#?}", stack_frame);
+
+    hlt_loop();
+}
+
+extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+    println!("EXCEPTION: INVALID OPCODE");
+    println!("Instruction Pointer: {:?}", stack_frame.instruction_pointer);
+    println!("{:#?}", stack_frame);
+
+    hlt_loop();
+}
+
+extern "x86-interrupt" fn stack_segment_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    println!("EXCEPTION: STACK SEGMENT FAULT");
+    println!("Instruction Pointer: {:?}", stack_frame.instruction_pointer);
+    print_selector_error_code(error_code);
+    println!("{:#?}", stack_frame);
+
+    hlt_loop();
+}
+
+extern "x86-interrupt" fn general_protection_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    println!("EXCEPTION: GENERAL PROTECTION FAULT");
+    println!("Instruction Pointer: {:?}", stack_frame.instruction_pointer);
+    print_selector_error_code(error_code);
+    println!("{:#?}", stack_frame);
+
+    hlt_loop();
+}
+
+/// Prints the error code of a fault which refers to a segment selector. The
+/// code is 0 if the fault wasn't caused by a selector.
+fn print_selector_error_code(error_code: u64) {
+    if error_code == 0 {
+        println!("Error Code: 0");
+        return;
+    }
+
+    // Bit 0 marks an event external to the program, bits 1-2 name the table
+    // and the rest is the index into it
+    let table = match (error_code >> 1) & 0b11 {
+        0b00 => "GDT",
+        0b10 => "LDT",
+        _ => "IDT",
+    };
+
+    println!(
+        "Error Code: {:#x} ({} index {}{})",
+        error_code,
+        table,
+        (error_code >> 3) & 0x1FFF,
+        if error_code & 1 != 0 {
+            ", external"
+        } else {
+            ""
+        }
+    );
+}
+
+/// Halts the CPU for good after a fault which can't be recovered from. Other
+/// interrupts are still handled so the output stays visible.
+fn hlt_loop() -> ! {
     loop {
         x86_64::instructions::hlt();
     }
@@ -71,6 +176,72 @@ pub fn init_pics() {
     unsafe {
         pics.initialize();
     }
+
+    set_timer_frequency(TIMER_FREQUENCY_HZ);
+}
+
+/* Programmable Interval Timer (PIT) */
+
+/// The rate the timer interrupt is programmed to fire at during boot
+const TIMER_FREQUENCY_HZ: u32 = 1000;
+
+/// The frequency of the oscillator which drives the PIT. Each channel divides
+/// it by a 16 bit divisor to get its output frequency.
+pub const PIT_BASE_FREQUENCY_HZ: u32 = 1_193_182;
+
+/// A divisor of 0 is treated as 65536 by the hardware, giving the slowest rate
+const PIT_MAX_DIVISOR: u32 = 65536;
+/// The rate generator mode does not allow a divisor of 1
+const PIT_MIN_DIVISOR: u32 = 2;
+
+const PIT_CHANNEL_0_PORT: u16 = 0x40;
+const PIT_COMMAND_PORT: u16 = 0x43;
+
+/// Selects channel 0 (bits 6-7), low byte then high byte access (bits 4-5),
+/// mode 2 which is the rate generator (bits 1-3) and binary counting (bit 0)
+const PIT_CHANNEL_0_RATE_GENERATOR: u8 = 0x34;
+
+/// The divisor channel 0 is currently programmed with. The BIOS leaves it at
+/// the maximum, which fires at roughly 18.2 Hz.
+static TIMER_DIVISOR: AtomicU32 = AtomicU32::new(PIT_MAX_DIVISOR);
+
+/// Programs channel 0 of the PIT, which drives the timer interrupt, to fire as
+/// close to the given frequency as its divisor allows. Frequencies outside of
+/// the range the PIT supports (19 Hz to about 597 kHz) are clamped.
+///
+/// NOTE: the uptime is derived from the current rate, so this should only be
+/// called during initialization.
+pub fn set_timer_frequency(hz: u32) {
+    let divisor = (PIT_BASE_FREQUENCY_HZ / hz.max(1)).clamp(PIT_MIN_DIVISOR, PIT_MAX_DIVISOR);
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut command_port = Port::<u8>::new(PIT_COMMAND_PORT);
+        let mut data_port = Port::<u8>::new(PIT_CHANNEL_0_PORT);
+
+        // The maximum divisor doesn't fit in 16 bits and is written as 0
+        let value = (divisor % PIT_MAX_DIVISOR) as u16;
+
+        unsafe {
+            command_port.write(PIT_CHANNEL_0_RATE_GENERATOR);
+            data_port.write((value & 0xFF) as u8);
+            data_port.write((value >> 8) as u8);
+        }
+
+        TIMER_DIVISOR.store(divisor, Ordering::Relaxed);
+    });
+}
+
+/// Returns the divisor channel 0 is programmed with. The exact rate of the
+/// timer is [`PIT_BASE_FREQUENCY_HZ`] divided by this.
+pub fn timer_divisor() -> u32 {
+    TIMER_DIVISOR.load(Ordering::Relaxed)
+}
+
+/// Returns the rate the timer interrupt fires at, rounded to the nearest hertz
+pub fn timer_frequency() -> u32 {
+    let divisor = timer_divisor();
+
+    (PIT_BASE_FREQUENCY_HZ + divisor / 2) / divisor
 }
 
 unsafe fn acknowledge_interrupt(index: InterruptIndex) {
@@ -84,6 +255,7 @@ unsafe fn acknowledge_interrupt(index: InterruptIndex) {
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    Floppy = PIC_1_OFFSET + 6,
 }
 
 impl InterruptIndex {
@@ -93,17 +265,22 @@ impl InterruptIndex {
 }
 
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    crate::vga::print!(".");
+    crate::util::time::tick();
+    crate::task::timer::wake_expired();
 
     unsafe { acknowledge_interrupt(InterruptIndex::Timer) };
 }
 
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use x86_64::instructions::port::Port;
-
     let mut port = Port::new(0x60);
     let scancode: u8 = unsafe { port.read() };
     crate::shell::keyboard::add_scancode(scancode);
 
     unsafe { acknowledge_interrupt(InterruptIndex::Keyboard) };
 }
+
+extern "x86-interrupt" fn floppy_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    crate::drivers::block::floppy::handle_interrupt();
+
+    unsafe { acknowledge_interrupt(InterruptIndex::Floppy) };
+}
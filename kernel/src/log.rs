@@ -0,0 +1,169 @@
+//! Leveled logging for kernel messages
+//!
+//! Records are printed to the screen with a colored level tag and are mirrored
+//! to the serial port once it has been set up. Records below the current level
+//! are skipped before their arguments are formatted, so disabled log calls cost
+//! no more than loading an atomic.
+
+use core::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use crate::{
+    drivers::char::serial::{self, serial_println},
+    vga::{self, Color, print, println},
+};
+
+/// How important a log record is, from least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Trace = 0,
+    Debug = 1,
+    Info = 2,
+    Warn = 3,
+    Error = 4,
+}
+
+impl Level {
+    /// Every level, from least to most important
+    pub const ALL: [Level; 5] = [
+        Level::Trace,
+        Level::Debug,
+        Level::Info,
+        Level::Warn,
+        Level::Error,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+
+    /// The color of the level tag on the screen
+    const fn color(self) -> Color {
+        match self {
+            Level::Trace => Color::DarkGray,
+            Level::Debug => Color::LightBlue,
+            Level::Info => Color::LightGreen,
+            Level::Warn => Color::Yellow,
+            Level::Error => Color::LightRed,
+        }
+    }
+
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Level::Trace,
+            1 => Level::Debug,
+            2 => Level::Info,
+            3 => Level::Warn,
+            _ => Level::Error,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLevelError;
+
+impl FromStr for Level {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Level::ALL
+            .into_iter()
+            .find(|level| level.name().eq_ignore_ascii_case(s))
+            .ok_or(ParseLevelError)
+    }
+}
+
+/// The least important level which still gets printed
+static MIN_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// Returns the least important level which still gets printed
+pub fn level() -> Level {
+    Level::from_u8(MIN_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Changes the least important level which still gets printed
+pub fn set_level(level: Level) {
+    MIN_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns true if records of the level are printed
+#[inline(always)]
+pub fn enabled(level: Level) -> bool {
+    level as u8 >= MIN_LEVEL.load(Ordering::Relaxed)
+}
+
+#[doc(hidden)]
+pub fn _log(level: Level, file: &str, line: u32, args: fmt::Arguments) {
+    vga::with_color(level.color(), || print!("[{:<5}] ", level.name()));
+    vga::with_color(Color::DarkGray, || print!("{}:{}: ", file, line));
+    println!("{}", args);
+
+    if serial::is_initialized() {
+        serial_println!("[{:<5}] {}:{}: {}", level.name(), file, line, args);
+    }
+}
+
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {{
+        let level = $level;
+
+        if $crate::log::enabled(level) {
+            $crate::log::_log(level, file!(), line!(), format_args!($($arg)*));
+        }
+    }};
+}
+pub(crate) use log;
+
+// NOTE: not every level has a caller yet, so the unused ones are allowed to
+// stay around until they do
+
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($($arg:tt)*) => ($crate::log::log!($crate::log::Level::Trace, $($arg)*));
+}
+#[allow(unused_imports)]
+pub(crate) use trace;
+
+macro_rules! debug {
+    ($($arg:tt)*) => ($crate::log::log!($crate::log::Level::Debug, $($arg)*));
+}
+pub(crate) use debug;
+
+#[allow(unused_macros)]
+macro_rules! info {
+    ($($arg:tt)*) => ($crate::log::log!($crate::log::Level::Info, $($arg)*));
+}
+#[allow(unused_imports)]
+pub(crate) use info;
+
+// NOTE: `warn` can't be defined directly since it clashes with the built-in
+// lint attribute of the same name
+#[allow(unused_macros)]
+macro_rules! log_warn {
+    ($($arg:tt)*) => ($crate::log::log!($crate::log::Level::Warn, $($arg)*));
+}
+#[allow(unused_imports)]
+pub(crate) use log_warn as warn;
+
+#[allow(unused_macros)]
+macro_rules! error {
+    ($($arg:tt)*) => ($crate::log::log!($crate::log::Level::Error, $($arg)*));
+}
+#[allow(unused_imports)]
+pub(crate) use error;
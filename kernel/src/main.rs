@@ -1,7 +1,9 @@
 #![no_std]
 #![no_main]
 #![feature(abi_x86_interrupt)]
-#![feature(str_from_raw_parts)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::run)]
+#![reexport_test_harness_main = "test_main"]
 
 extern crate alloc;
 
@@ -14,13 +16,19 @@ use x86_64::VirtAddr;
 mod allocator;
 mod device;
 mod drivers;
+mod exec;
 mod fs;
 mod gdt;
 mod interrupts;
+mod log;
 mod memory;
 mod panic;
+mod power;
 mod shell;
 mod task;
+#[cfg(test)]
+mod testing;
+mod user;
 mod util;
 mod vga;
 
@@ -35,6 +43,7 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     gdt::init();
     interrupts::init_idt();
     interrupts::init_pics();
+    util::time::init();
 
     x86_64::instructions::interrupts::enable();
 
@@ -43,11 +52,17 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
 
     allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::install(mapper, frame_allocator);
 
     drivers::char::init().expect("failed to init char dev drivers");
+    drivers::block::init().expect("failed to init block dev drivers");
     fs::init();
 
+    // The tests run on the fully initialized kernel and exit QEMU when done
+    #[cfg(test)]
+    test_main();
+
     let mut executor = Executor::new();
-    executor.spawn(Task::new(shell::run()));
+    executor.spawn(Task::with_name("shell", shell::run()));
     executor.run();
 }
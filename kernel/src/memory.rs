@@ -1,7 +1,15 @@
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use conquer_once::spin::OnceCell;
+use spin::Mutex;
 use x86_64::{
     PhysAddr, VirtAddr,
-    structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB},
+    structures::{
+        idt::PageFaultErrorCode,
+        paging::{
+            FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame,
+            Size4KiB,
+        },
+    },
 };
 
 /// Initialize a new OffsetPageTable.
@@ -77,3 +85,156 @@ unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
         frame
     }
 }
+
+struct KernelMemory {
+    mapper: OffsetPageTable<'static>,
+    frame_allocator: BootInfoFrameAllocator,
+}
+
+static KERNEL_MEMORY: OnceCell<Mutex<KernelMemory>> = OnceCell::uninit();
+
+/// Hands the page table mapper and frame allocator over to the memory
+/// subsystem after boot so that other parts of the kernel (like the program
+/// loader) can map memory. Must only be called once.
+pub fn install(mapper: OffsetPageTable<'static>, frame_allocator: BootInfoFrameAllocator) {
+    KERNEL_MEMORY
+        .try_init_once(|| {
+            Mutex::new(KernelMemory {
+                mapper,
+                frame_allocator,
+            })
+        })
+        .expect("Tried to install kernel memory more than once");
+}
+
+/// Executes the given function with exclusive access to the active page table
+/// and the frame allocator. Must not be nested.
+///
+/// The function must not allocate, since touching a heap page which is mapped
+/// on demand for the first time faults, and the fault can't be resolved while
+/// the page tables are locked.
+pub fn with_mapper<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut OffsetPageTable<'static>, &mut BootInfoFrameAllocator) -> R,
+{
+    let memory = KERNEL_MEMORY
+        .get()
+        .expect("kernel memory not yet installed");
+
+    // We have to disable interrupts while holding the lock so that an
+    // interrupt handler can never deadlock trying to map memory
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut memory = memory.lock();
+        let KernelMemory {
+            mapper,
+            frame_allocator,
+        } = &mut *memory;
+
+        f(mapper, frame_allocator)
+    })
+}
+
+/* Demand paging */
+
+/// The most regions which can be mapped on demand
+const MAX_DEMAND_REGIONS: usize = 4;
+
+/// A range of kernel memory whose pages are only backed by a frame once they
+/// are first accessed
+#[derive(Debug, Clone, Copy)]
+struct DemandRegion {
+    start: VirtAddr,
+    end: VirtAddr,
+}
+
+/// The page fault handler runs with interrupts disabled and must not allocate,
+/// so the regions are kept in a fixed size list
+static DEMAND_REGIONS: Mutex<heapless::Vec<DemandRegion, MAX_DEMAND_REGIONS>> =
+    Mutex::new(heapless::Vec::new());
+
+/// Reserves `size` bytes starting at `start` to be mapped on demand. The range
+/// must not be mapped yet, except for pages which were mapped up front. Only
+/// meant to be called during initialization, so it panics if there are too
+/// many regions.
+pub fn reserve_on_demand(start: VirtAddr, size: u64) {
+    let region = DemandRegion {
+        start,
+        end: start + size,
+    };
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        DEMAND_REGIONS
+            .lock()
+            .push(region)
+            .expect("too many demand paged regions");
+    });
+}
+
+/// Returns true if any part of `start..end` lies in a region which is mapped
+/// on demand. Pages of such a region may not be mapped yet but are still in
+/// use, so nothing else may be mapped there.
+pub fn is_reserved(start: VirtAddr, end: VirtAddr) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        DEMAND_REGIONS
+            .lock()
+            .iter()
+            .any(|region| start < region.end && region.start < end)
+    })
+}
+
+/// Tries to resolve a page fault by mapping the page which was accessed, if it
+/// lies in a region reserved with [`reserve_on_demand`] and isn't mapped yet.
+/// Returns false if the fault is genuine and must not be retried.
+///
+/// Faults which happen while the page tables are locked (i.e. from inside of
+/// [`with_mapper`]) can't be resolved either, since mapping the page would
+/// deadlock.
+pub fn handle_page_fault(address: VirtAddr, error_code: PageFaultErrorCode) -> bool {
+    // Only missing kernel pages are mapped. A protection violation means the
+    // page is already there, and ring 3 has no business in these regions.
+    if error_code
+        .intersects(PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::USER_MODE)
+    {
+        return false;
+    }
+
+    let in_region = DEMAND_REGIONS
+        .try_lock()
+        .is_some_and(|regions| regions.iter().any(|r| (r.start..r.end).contains(&address)));
+
+    if !in_region {
+        return false;
+    }
+
+    let Some(mut memory) = KERNEL_MEMORY.get().and_then(|memory| memory.try_lock()) else {
+        return false;
+    };
+
+    let KernelMemory {
+        mapper,
+        frame_allocator,
+    } = &mut *memory;
+
+    let Some(frame) = frame_allocator.allocate_frame() else {
+        return false;
+    };
+
+    let page = Page::<Size4KiB>::containing_address(address);
+
+    // Same as the pages which are mapped up front. They stay executable since
+    // flat binaries run from the heap.
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    // SAFETY: the frame was just allocated, so nothing else refers to it
+    match unsafe { mapper.map_to(page, frame, flags, frame_allocator) } {
+        Ok(flush) => flush.flush(),
+        Err(_) => return false,
+    }
+
+    // Fresh frames contain whatever was left in physical memory
+    unsafe {
+        core::ptr::write_bytes(page.start_address().as_mut_ptr::<u8>(), 0, 4096);
+    }
+
+    true
+}
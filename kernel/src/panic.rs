@@ -3,13 +3,19 @@
 
 use core::panic::PanicInfo;
 
-use crate::vga::{self, Color, ColorCode, print, println};
+#[cfg(not(test))]
+use crate::{
+    drivers::char::serial::serial_println,
+    power,
+    vga::{self, Color, print, println},
+};
 
 /// Our function for handling panics within Rust code
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     // Reset color code in case we were doing something weird
-    vga::set_color_code(ColorCode::new(Color::White, Color::Black));
+    vga::set_color_code(vga::DEFAULT_COLOR_CODE);
 
     /* Create a separator to print panic information */
 
@@ -38,12 +44,24 @@ fn panic(info: &PanicInfo) -> ! {
 
     vga::with_color(Color::LightGray, || println!("{}", info.message()));
 
+    /* Repeat everything on the serial port so that it shows up in logs */
+
+    match info.location() {
+        Some(loc) => serial_println!("ERROR: kernel panicked (at {}):", loc),
+        None => serial_println!("ERROR: kernel panicked (at <unspecified>):"),
+    }
+    serial_println!("{}", info.message());
+
     vga::disable_cursor();
 
     /* Hang the processor */
 
-    x86_64::instructions::interrupts::disable();
-    loop {
-        x86_64::instructions::hlt();
-    }
+    power::halt();
+}
+
+/// A panic while testing means that the running test failed
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    crate::testing::fail(info)
 }
@@ -0,0 +1,101 @@
+//! This module contains functions for stopping and restarting the machine
+
+use x86_64::{
+    VirtAddr,
+    instructions::{port::Port, tables::lidt},
+    structures::DescriptorTablePointer,
+};
+
+/// Status and command port of the 8042 keyboard controller
+const KEYBOARD_CONTROLLER_PORT: u16 = 0x64;
+/// Set in the controller status while it hasn't taken the last input yet
+const KEYBOARD_CONTROLLER_INPUT_FULL: u8 = 0x02;
+/// Pulses the reset line of the CPU
+const KEYBOARD_CONTROLLER_RESET: u8 = 0xFE;
+/// How often the controller status is read before giving up on it. Without a
+/// controller the port reads 0xFF, so the input never looks empty.
+const KEYBOARD_CONTROLLER_MAX_POLLS: usize = 100_000;
+
+/// Ports which power off the machine when `ACPI_SHUTDOWN_VALUE` is written to
+/// them. They are NOT a standard interface, they are where emulators put the
+/// ACPI PM1a control register:
+/// - `0x604` is used by QEMU with the default (i440FX/PIIX) and q35 machines
+/// - `0xB004` is used by Bochs and older versions of QEMU
+///
+/// Real hardware needs the port from the ACPI FADT instead.
+///
+/// FIXME: read the PM1a control port and the sleep type from the ACPI tables
+/// once there is ACPI support
+const EMULATOR_SHUTDOWN_PORTS: [u16; 2] = [0x604, 0xB004];
+/// Sets SLP_EN with a sleep type of 0, which is S5 (soft off) in both
+/// emulators
+const ACPI_SHUTDOWN_VALUE: u16 = 0x2000;
+
+/// Stops the CPU for good. Interrupts are disabled first so that nothing can
+/// wake it up again.
+pub fn halt() -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Restarts the machine through the keyboard controller. If that doesn't
+/// work, a triple fault resets the CPU instead.
+pub fn reboot() -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    let mut port = Port::<u8>::new(KEYBOARD_CONTROLLER_PORT);
+
+    let ready = (0..KEYBOARD_CONTROLLER_MAX_POLLS).any(|_| {
+        core::hint::spin_loop();
+
+        // SAFETY: reading the status has no side effects
+        unsafe { port.read() & KEYBOARD_CONTROLLER_INPUT_FULL == 0 }
+    });
+
+    if ready {
+        // SAFETY: the keyboard controller is only sent the reset command,
+        // which doesn't return anyway
+        unsafe { port.write(KEYBOARD_CONTROLLER_RESET) };
+
+        // Give the controller some time to pull the reset line
+        for _ in 0..1_000_000 {
+            core::hint::spin_loop();
+        }
+    }
+
+    triple_fault()
+}
+
+/// Powers the machine off. Only works under emulators (see
+/// [`EMULATOR_SHUTDOWN_PORTS`]), otherwise the CPU is halted instead.
+pub fn shutdown() -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    for port in EMULATOR_SHUTDOWN_PORTS {
+        // SAFETY: writing the value either turns the machine off or is
+        // ignored by a port which has no device behind it
+        unsafe { Port::<u16>::new(port).write(ACPI_SHUTDOWN_VALUE) };
+    }
+
+    halt()
+}
+
+/// Resets the CPU by raising an exception without a usable IDT. The CPU can't
+/// deliver the exception or the double fault which follows, so it resets.
+fn triple_fault() -> ! {
+    let empty = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::zero(),
+    };
+
+    // SAFETY: nothing is expected to run after this, the CPU resets as soon
+    // as the breakpoint is raised
+    unsafe { lidt(&empty) };
+    x86_64::instructions::interrupts::int3();
+
+    // A reset never returns, this only keeps the compiler happy
+    halt()
+}
@@ -0,0 +1,183 @@
+use alloc::{format, string::String, vec::Vec};
+
+/// The arguments of a command, split into options and operands.
+///
+/// Options come in two forms:
+/// - short options are single characters after a `-`, several of which can be
+///   clustered into one argument (`-la` is the same as `-l -a`)
+/// - long options are names after a `--` (`--all`)
+///
+/// Options are looked up by their name without the dashes, so `-a` is found
+/// with `"a"` and `--all` with `"all"`. A lone `-` is an operand, and `--` ends
+/// the options so that every argument after it is an operand even if it starts
+/// with a dash.
+#[derive(Debug, Default)]
+pub struct Arguments<'a> {
+    /// Every option in the order given, along with its value if it took one
+    options: Vec<(&'a str, Option<&'a str>)>,
+    operands: Vec<&'a str>,
+}
+
+impl<'a> Arguments<'a> {
+    /// Parses arguments where no option takes a value
+    pub fn new(args: &[&'a str]) -> Self {
+        Self::parse(args, &[]).expect("options without values can't be missing one")
+    }
+
+    /// Parses arguments where the options named in `value_options` take a
+    /// value. A value is either attached to the option (`-n5`, `--lines=5`) or
+    /// the next argument (`-n 5`, `--lines 5`). Any long option can be given a
+    /// value with `=`.
+    ///
+    /// Fails if an option which takes a value is the last argument.
+    pub fn parse(args: &[&'a str], value_options: &[&str]) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut args = args.iter().copied();
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                parsed.operands.extend(args);
+                break;
+            }
+
+            if let Some(option) = arg.strip_prefix("--") {
+                let (name, value) = match option.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None if value_options.contains(&option) => match args.next() {
+                        Some(value) => (option, Some(value)),
+                        None => return Err(format!("option '--{}' requires an argument", option)),
+                    },
+                    None => (option, None),
+                };
+
+                parsed.options.push((name, value));
+                continue;
+            }
+
+            let Some(cluster) = arg.strip_prefix('-').filter(|c| !c.is_empty()) else {
+                parsed.operands.push(arg);
+                continue;
+            };
+
+            for (i, c) in cluster.char_indices() {
+                let name = &cluster[i..i + c.len_utf8()];
+
+                if !value_options.contains(&name) {
+                    parsed.options.push((name, None));
+                    continue;
+                }
+
+                // The rest of the cluster is the value if there is any left
+                let rest = &cluster[i + c.len_utf8()..];
+                let value = if !rest.is_empty() {
+                    rest
+                } else {
+                    args.next()
+                        .ok_or_else(|| format!("option requires an argument -- '{}'", name))?
+                };
+
+                parsed.options.push((name, Some(value)));
+                break;
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// Returns true if the option was given at all
+    pub fn has(&self, name: &str) -> bool {
+        self.options.iter().any(|(n, _)| *n == name)
+    }
+
+    /// Returns the value of an option. If the option is given more than once
+    /// the last value wins.
+    pub fn value(&self, name: &str) -> Option<&'a str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(n, _)| *n == name)
+            .and_then(|(_, value)| *value)
+    }
+
+    /// Returns the value of an option parsed as a number
+    pub fn numeric_value(&self, name: &str) -> Result<Option<usize>, String> {
+        self.value(name)
+            .map(|text| {
+                text.parse::<usize>()
+                    .map_err(|_| format!("invalid number: '{}'", text))
+            })
+            .transpose()
+    }
+
+    /// The arguments which are not options, in the order given
+    pub fn operands(&self) -> &[&'a str] {
+        &self.operands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn splits_clustered_short_options() {
+        let args = Arguments::new(&["-la", "-R", "dir"]);
+
+        assert!(args.has("l"));
+        assert!(args.has("a"));
+        assert!(args.has("R"));
+        assert!(!args.has("la"));
+        assert_eq!(args.operands(), ["dir"]);
+    }
+
+    #[test_case]
+    fn short_option_values() {
+        let args = Arguments::parse(&["-vn5", "-n", "7", "file"], &["n"]).unwrap();
+
+        assert!(args.has("v"));
+        // The last value wins
+        assert_eq!(args.value("n"), Some("7"));
+        assert_eq!(args.numeric_value("n"), Ok(Some(7)));
+        assert_eq!(args.operands(), ["file"]);
+
+        assert!(Arguments::parse(&["-n"], &["n"]).is_err());
+    }
+
+    #[test_case]
+    fn long_option_values() {
+        let args = Arguments::parse(&["--lines=3", "--name", "*.rs", "--all"], &["name"]).unwrap();
+
+        assert_eq!(args.value("lines"), Some("3"));
+        assert_eq!(args.value("name"), Some("*.rs"));
+        assert!(args.has("all"));
+        assert_eq!(args.value("all"), None);
+        assert!(args.operands().is_empty());
+
+        assert!(Arguments::parse(&["--name"], &["name"]).is_err());
+    }
+
+    #[test_case]
+    fn double_dash_ends_options() {
+        let args = Arguments::new(&["-a", "--", "-b", "--c", "-"]);
+
+        assert!(args.has("a"));
+        assert!(!args.has("b"));
+        assert!(!args.has("c"));
+        assert_eq!(args.operands(), ["-b", "--c", "-"]);
+    }
+
+    #[test_case]
+    fn lone_dash_is_an_operand() {
+        let args = Arguments::new(&["-", "x"]);
+
+        assert_eq!(args.operands(), ["-", "x"]);
+    }
+
+    #[test_case]
+    fn rejects_invalid_numbers() {
+        let args = Arguments::parse(&["-n", "ten"], &["n"]).unwrap();
+
+        assert!(args.numeric_value("n").is_err());
+        assert_eq!(args.numeric_value("c"), Ok(None));
+    }
+}
@@ -0,0 +1,127 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::COMMANDS;
+use crate::fs::{FsNodeKind, vfs};
+
+/// The outcome of completing the word under the cursor
+#[derive(Debug)]
+pub enum Completion {
+    /// Nothing matched the word
+    None,
+    /// Text which should be inserted at the cursor to complete the word
+    Insert(String),
+    /// Several entries matched and none of them can be completed further. The
+    /// names of the candidates are listed for the user to choose from.
+    Candidates(Vec<String>),
+}
+
+/// Completes the last word of the given line (everything before the cursor).
+/// The first word of a command is completed against the built-in commands and
+/// any other word is treated as a path.
+///
+/// NOTE: words are split on whitespace and `;` only, so quoted words are not
+/// completed correctly yet.
+pub fn complete(line: &str) -> Completion {
+    let is_separator = |c: char| c.is_whitespace() || c == ';';
+
+    let word_start = line.rfind(is_separator).map_or(0, |i| i + 1);
+    let word = &line[word_start..];
+
+    // The word is the name of a command if only whitespace comes between it
+    // and the start of the command
+    let command_start = line[..word_start].rfind(';').map_or(0, |i| i + 1);
+    let is_command = line[command_start..word_start].trim().is_empty();
+
+    // Each candidate is the full completed word including any suffix
+    let candidates = if is_command {
+        complete_command(word)
+    } else {
+        complete_path(word)
+    };
+
+    match candidates.as_slice() {
+        [] => Completion::None,
+        [candidate] => Completion::Insert(candidate[word.len()..].to_string()),
+        _ => {
+            let common = common_prefix(&candidates);
+
+            if common.len() > word.len() {
+                return Completion::Insert(common[word.len()..].to_string());
+            }
+
+            // List the candidates by name, leaving out the directory which
+            // they all share and the trailing space
+            let name_start = word.rfind('/').map_or(0, |i| i + 1);
+
+            Completion::Candidates(
+                candidates
+                    .iter()
+                    .map(|c| c[name_start..].trim_end().to_string())
+                    .collect(),
+            )
+        }
+    }
+}
+
+fn complete_command(word: &str) -> Vec<String> {
+    COMMANDS
+        .iter()
+        .filter(|c| c.name.starts_with(word))
+        .map(|c| format!("{} ", c.name))
+        .collect()
+}
+
+fn complete_path(word: &str) -> Vec<String> {
+    // Split the word into the directory to search (including its trailing
+    // slash) and the prefix of the name within it
+    let (directory, prefix) = match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+
+    let Ok(entries) = vfs::get().read_directory(if directory.is_empty() { "." } else { directory })
+    else {
+        return Vec::new();
+    };
+
+    entries
+        .into_iter()
+        .filter(|e| e.name.starts_with(prefix))
+        // Like `ls`, only show hidden entries when they are asked for
+        .filter(|e| prefix.starts_with('.') || !e.name.starts_with('.'))
+        .map(|e| {
+            let suffix = if e.kind == FsNodeKind::Directory {
+                "/"
+            } else {
+                " "
+            };
+
+            format!("{directory}{}{suffix}", e.name)
+        })
+        .collect()
+}
+
+/// Returns the longest prefix which all of the strings share
+fn common_prefix(strings: &[String]) -> &str {
+    let Some((first, rest)) = strings.split_first() else {
+        return "";
+    };
+
+    let mut len = first.len();
+
+    for s in rest {
+        len = first
+            .char_indices()
+            .zip(s.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8())
+            .min(len);
+    }
+
+    &first[..len]
+}
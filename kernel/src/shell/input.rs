@@ -0,0 +1,182 @@
+//! Turns the bytes read from the console back into key presses
+
+use pc_keyboard::{DecodedKey, KeyCode};
+
+/// Where the decoder is within a multi-byte key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum State {
+    #[default]
+    Ground,
+    /// Saw an ESC byte and expecting the `[` which starts a control sequence
+    Escape,
+    /// Inside a control sequence, waiting for the final byte
+    ControlSequence,
+    /// Collecting the continuation bytes of a UTF-8 encoded character
+    Utf8 { needed: usize },
+}
+
+/// Decodes the byte stream of the console one byte at a time
+#[derive(Default)]
+pub struct InputDecoder {
+    state: State,
+    /// The bytes of the key being decoded so far
+    buffer: heapless::Vec<u8, 4>,
+}
+
+impl InputDecoder {
+    /// Adds the next byte and returns the key if it was the last byte of one
+    pub fn push(&mut self, byte: u8) -> Option<DecodedKey> {
+        match self.state {
+            State::Ground => match byte {
+                0x1b => {
+                    self.state = State::Escape;
+                    None
+                }
+                0x00..=0x7f => Some(DecodedKey::Unicode(byte as char)),
+                // The lead byte says how many continuation bytes follow
+                0xc0..=0xdf => self.start_character(byte, 1),
+                0xe0..=0xef => self.start_character(byte, 2),
+                0xf0..=0xf7 => self.start_character(byte, 3),
+                // A stray continuation byte or an invalid lead byte
+                _ => None,
+            },
+            State::Escape => {
+                // Anything but a control sequence drops the ESC, but the byte
+                // itself is still a key of its own
+                if byte != b'[' {
+                    self.state = State::Ground;
+                    return self.push(byte);
+                }
+
+                self.buffer.clear();
+                self.state = State::ControlSequence;
+
+                None
+            }
+            State::ControlSequence => {
+                // The final byte of the sequence
+                if (0x40..=0x7e).contains(&byte) {
+                    self.state = State::Ground;
+                    return control_sequence_key(&self.buffer, byte);
+                }
+
+                // Sequences which are too long can't be a key we know
+                if self.buffer.push(byte).is_err() {
+                    self.state = State::Ground;
+                }
+
+                None
+            }
+            State::Utf8 { needed } => {
+                // The character was cut short, so the byte starts the next key
+                if byte & 0xc0 != 0x80 {
+                    self.state = State::Ground;
+                    return self.push(byte);
+                }
+
+                if self.buffer.push(byte).is_err() {
+                    self.state = State::Ground;
+                    return None;
+                }
+
+                if needed > 1 {
+                    self.state = State::Utf8 { needed: needed - 1 };
+                    return None;
+                }
+
+                self.state = State::Ground;
+
+                core::str::from_utf8(&self.buffer)
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .map(DecodedKey::Unicode)
+            }
+        }
+    }
+
+    fn start_character(&mut self, byte: u8, needed: usize) -> Option<DecodedKey> {
+        self.buffer.clear();
+        let _ = self.buffer.push(byte);
+        self.state = State::Utf8 { needed };
+
+        None
+    }
+}
+
+/// Maps a control sequence to the key which sends it
+fn control_sequence_key(parameters: &[u8], final_byte: u8) -> Option<DecodedKey> {
+    let key = match (parameters, final_byte) {
+        (b"", b'A') => KeyCode::ArrowUp,
+        (b"", b'B') => KeyCode::ArrowDown,
+        (b"", b'C') => KeyCode::ArrowRight,
+        (b"", b'D') => KeyCode::ArrowLeft,
+        (b"", b'H') => KeyCode::Home,
+        (b"", b'F') => KeyCode::End,
+        (b"3", b'~') => KeyCode::Delete,
+        _ => return None,
+    };
+
+    Some(DecodedKey::RawKey(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Vec<DecodedKey> {
+        let mut decoder = InputDecoder::default();
+
+        bytes.iter().filter_map(|&b| decoder.push(b)).collect()
+    }
+
+    #[test_case]
+    fn decodes_ascii() {
+        assert_eq!(
+            decode(b"ls\n"),
+            [
+                DecodedKey::Unicode('l'),
+                DecodedKey::Unicode('s'),
+                DecodedKey::Unicode('\n'),
+            ]
+        );
+    }
+
+    #[test_case]
+    fn decodes_control_sequences() {
+        assert_eq!(
+            decode(b"\x1b[A\x1b[3~"),
+            [
+                DecodedKey::RawKey(KeyCode::ArrowUp),
+                DecodedKey::RawKey(KeyCode::Delete),
+            ]
+        );
+
+        // Unknown sequences are dropped entirely
+        assert_eq!(decode(b"\x1b[5~x"), [DecodedKey::Unicode('x')]);
+    }
+
+    #[test_case]
+    fn keeps_byte_after_lone_escape() {
+        assert_eq!(
+            decode(b"\x1bab"),
+            [DecodedKey::Unicode('a'), DecodedKey::Unicode('b')]
+        );
+        assert_eq!(
+            decode(b"\x1b\x1b[D"),
+            [DecodedKey::RawKey(KeyCode::ArrowLeft)]
+        );
+    }
+
+    #[test_case]
+    fn decodes_utf8() {
+        assert_eq!(
+            decode("é€".as_bytes()),
+            [DecodedKey::Unicode('é'), DecodedKey::Unicode('€')]
+        );
+
+        // A truncated character doesn't swallow the key after it
+        assert_eq!(decode(b"\xc3a"), [DecodedKey::Unicode('a')]);
+    }
+}
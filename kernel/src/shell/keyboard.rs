@@ -1,65 +1,201 @@
 use core::{
+    future::Future,
     pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
     task::{Context, Poll},
 };
 
 use conquer_once::spin::OnceCell;
 use crossbeam_queue::ArrayQueue;
-use futures_util::{Stream, task::AtomicWaker};
+use futures_util::task::AtomicWaker;
 
 use crate::println;
 
 static WAKER: AtomicWaker = AtomicWaker::new();
 static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
 
+/// The number of scancodes which can be buffered before input is dropped
+pub const SCANCODE_QUEUE_CAPACITY: usize = 100;
+
+/// The total number of scancodes which have been dropped because the queue was
+/// full when they arrived
+static DROPPED_SCANCODES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of scancodes which are waiting to be processed
+pub fn queued_scancodes() -> usize {
+    SCANCODE_QUEUE.try_get().map(|q| q.len()).unwrap_or(0)
+}
+
+/// Discards all scancodes which are waiting to be processed
+pub fn drain_scancodes() {
+    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
+        while queue.pop().is_some() {}
+    }
+}
+
+/// Returns the total number of scancodes dropped due to queue overflow
+pub fn dropped_scancodes() -> u64 {
+    DROPPED_SCANCODES.load(Ordering::Relaxed)
+}
+
+/// Scancodes (set 1) which are needed to detect Ctrl-C from the interrupt
+/// handler. The right control key sends the same codes with an 0xE0 prefix.
+const SCANCODE_CTRL_PRESSED: u8 = 0x1D;
+const SCANCODE_CTRL_RELEASED: u8 = 0x9D;
+const SCANCODE_C_PRESSED: u8 = 0x2E;
+
+static CTRL_HELD: AtomicBool = AtomicBool::new(false);
+static INTERRUPT_REQUESTED: AtomicBool = AtomicBool::new(false);
+/// Set once a command has been cancelled by a Ctrl-C
+static INTERRUPT_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if Ctrl-C was pressed since the last call, clearing the flag.
+/// Long running commands should poll this to allow the user to cancel them
+/// since the shell can not process input while a command is executing.
+pub fn take_interrupt() -> bool {
+    let requested = INTERRUPT_REQUESTED.swap(false, Ordering::Relaxed);
+
+    if requested {
+        INTERRUPT_TAKEN.store(true, Ordering::Relaxed);
+    }
+
+    requested
+}
+
+/// Returns true if a command was cancelled with [`take_interrupt`] since the
+/// last call, clearing the flag
+pub fn take_cancellation() -> bool {
+    INTERRUPT_TAKEN.swap(false, Ordering::Relaxed)
+}
+
+/// Discards any pending Ctrl-C so it doesn't cancel the next command
+pub fn clear_interrupt() {
+    INTERRUPT_REQUESTED.store(false, Ordering::Relaxed);
+    INTERRUPT_TAKEN.store(false, Ordering::Relaxed);
+}
+
 /// Called by the keyboard interrupt handler
 ///
 /// Must not block or allocate.
 pub(crate) fn add_scancode(scancode: u8) {
+    // Ctrl-C is detected here rather than by the shell because the shell task
+    // is blocked while a command is running
+    match scancode {
+        SCANCODE_CTRL_PRESSED => CTRL_HELD.store(true, Ordering::Relaxed),
+        SCANCODE_CTRL_RELEASED => CTRL_HELD.store(false, Ordering::Relaxed),
+        SCANCODE_C_PRESSED if CTRL_HELD.load(Ordering::Relaxed) => {
+            INTERRUPT_REQUESTED.store(true, Ordering::Relaxed)
+        }
+        _ => {}
+    }
+
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
-        if queue.push(scancode).is_err() {
-            println!("WARNING: scancode queue full; dropping keyboard input");
-        } else {
-            WAKER.wake();
+        // If the consumer has fallen behind (i.e. a long running command is
+        // blocking the shell), the oldest scancode is replaced so that the most
+        // recent input is never lost
+        if queue.force_push(scancode).is_some() {
+            let dropped = DROPPED_SCANCODES.fetch_add(1, Ordering::Relaxed) + 1;
+
+            // Only warn on powers of two so that a burst of dropped input
+            // doesn't flood the screen
+            if dropped.is_power_of_two() {
+                println!(
+                    "WARNING: scancode queue full; dropped {} keyboard inputs so far",
+                    dropped
+                );
+            }
         }
+
+        WAKER.wake();
     } else {
         println!("WARNING: scancode queue uninitialized");
     }
 }
 
-pub struct ScancodeStream {
-    _private: (),
+/// Creates the scancode queue. Scancodes which arrive before this are dropped.
+pub fn init() {
+    SCANCODE_QUEUE
+        .try_init_once(|| ArrayQueue::new(SCANCODE_QUEUE_CAPACITY))
+        .expect("keyboard::init should only be called once");
 }
 
-impl ScancodeStream {
-    pub fn new() -> Self {
-        SCANCODE_QUEUE
-            .try_init_once(|| ArrayQueue::new(100))
-            .expect("ScancodeStream::new should only be called once");
-        ScancodeStream { _private: () }
-    }
+/// Removes the oldest queued scancode, if there is one
+pub fn pop_scancode() -> Option<u8> {
+    SCANCODE_QUEUE.try_get().ok()?.pop()
 }
 
-impl Stream for ScancodeStream {
-    type Item = u8;
+/// Waits until at least one scancode is queued. The scancode is left in the
+/// queue for whoever reads it next.
+pub fn wait_for_scancode() -> ScancodeReady {
+    ScancodeReady { _private: () }
+}
+
+/// The future returned by [`wait_for_scancode`]
+pub struct ScancodeReady {
+    _private: (),
+}
+
+impl Future for ScancodeReady {
+    type Output = ();
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
         let queue = SCANCODE_QUEUE
             .try_get()
             .expect("scancode queue not initialized");
 
         // fast path
-        if let Some(scancode) = queue.pop() {
-            return Poll::Ready(Some(scancode));
+        if !queue.is_empty() {
+            return Poll::Ready(());
         }
 
         WAKER.register(cx.waker());
-        match queue.pop() {
-            Some(scancode) => {
-                WAKER.take();
-                Poll::Ready(Some(scancode))
-            }
-            None => Poll::Pending,
+        if queue.is_empty() {
+            Poll::Pending
+        } else {
+            WAKER.take();
+            Poll::Ready(())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test_case]
+    fn overflow_drops_oldest_scancodes() {
+        // Interrupts are disabled so that a real key press can't end up in the
+        // queue while it's being checked
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            drain_scancodes();
+            let dropped_before = dropped_scancodes();
+
+            // Skip the codes which would be seen as Ctrl-C
+            let scancodes: Vec<u8> = (0..=u8::MAX)
+                .filter(|code| {
+                    ![
+                        SCANCODE_CTRL_PRESSED,
+                        SCANCODE_CTRL_RELEASED,
+                        SCANCODE_C_PRESSED,
+                    ]
+                    .contains(code)
+                })
+                .take(SCANCODE_QUEUE_CAPACITY + 3)
+                .collect();
+
+            for &scancode in &scancodes {
+                add_scancode(scancode);
+            }
+
+            assert_eq!(queued_scancodes(), SCANCODE_QUEUE_CAPACITY);
+            assert_eq!(dropped_scancodes() - dropped_before, 3);
+
+            for &scancode in &scancodes[3..] {
+                assert_eq!(pop_scancode(), Some(scancode));
+            }
+            assert_eq!(pop_scancode(), None);
+        });
+    }
+}
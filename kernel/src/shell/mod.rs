@@ -1,106 +1,344 @@
-use alloc::{collections::vec_deque::VecDeque, format, string::String, vec::Vec};
+use alloc::{
+    collections::vec_deque::VecDeque,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{
+    cmp::Reverse,
+    ops::{ControlFlow, Range},
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    time::Duration,
+};
 
-use futures_util::StreamExt;
-use keyboard::ScancodeStream;
-use parser::Parser;
-use pc_keyboard::{DecodedKey, HandleControl, Keyboard, ScancodeSet1, layouts::Us104Key};
+use args::Arguments;
+use completion::Completion;
+use input::InputDecoder;
+use parser::{Parser, Token};
+use pc_keyboard::{DecodedKey, KeyCode};
 
 use crate::{
+    allocator,
+    drivers::{char::console, rtc},
+    exec,
     fs::{
-        FileMode, FsNodeKind,
+        FileDescriptor, FileMode, FsNodeKind, MODE_MASK, MountFlags, OpenFlags, Permissions,
+        SeekFrom,
+        path::{Path, PathParseError},
         vfs::{self, DirectoryEntry, DirectoryIterationEntry, IoError},
     },
+    interrupts,
+    log::{self, debug},
+    power,
+    task::{self, executor},
+    user,
+    util::{
+        defer::defer_handle,
+        fmt::human_bytes,
+        time::{self, DateTime},
+    },
     vga::{self, Color, print, println},
 };
 
+pub mod args;
+pub mod completion;
+pub mod input;
 pub mod keyboard;
 pub mod parser;
+mod selftest;
 
 const INPUT_BUFFER_LEN: usize = vga::BUFFER_WIDTH - get_prompt().len() - 1;
 type InputBuffer = heapless::String<INPUT_BUFFER_LEN>;
 
+/// Whether the commands being run were typed at the prompt, so that their
+/// output is watched on the console as it is printed. Commands run by
+/// `selftest` aren't, and output which only makes sense live (like progress
+/// which is redrawn in place) is left out for them.
+static INTERACTIVE: AtomicBool = AtomicBool::new(true);
+
+/// The exit status of the last command, following the coreutils convention
+/// for comparisons: 0 if the inputs are the same, 1 if they differ and 2 if
+/// they couldn't be compared. Only `cmp` and `diff` report a status so far,
+/// every other command leaves it at 0.
+static LAST_STATUS: AtomicU8 = AtomicU8::new(0);
+
 pub async fn run() {
-    let mut scancodes = ScancodeStream::new();
-    let mut keyboard = Keyboard::new(ScancodeSet1::new(), Us104Key, HandleControl::Ignore);
+    let console = vfs::get()
+        .open("/dev/console", FileMode::Read)
+        .expect("failed to open the console");
+    let mut decoder = InputDecoder::default();
+    let mut bytes = [0; 16];
 
     let mut history = heapless::Deque::<InputBuffer, 16>::new();
 
     let mut input_buffer = InputBuffer::new();
-    let mut cursor_position = 0u8;
+    // The byte offset in the input buffer where characters are inserted
+    let mut cursor_position = 0usize;
+
+    // Which history entry is shown while scrolling with Up/Down (0 being the
+    // newest) and the line that was being typed before scrolling started
+    let mut history_position: Option<usize> = None;
+    let mut pending_input = InputBuffer::new();
 
-    vga::enable_cursor(13, 15);
+    vga::restore_cursor();
 
     print_prompt();
 
-    while let Some(scancode) = scancodes.next().await {
-        if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-            if let Some(key) = keyboard.process_keyevent(key_event) {
-                match key {
-                    DecodedKey::Unicode(character) => {
-                        // Handle enter
-                        if character == '\n' {
-                            println!();
-
-                            if parse_and_execute(&input_buffer).await {
-                                vga::disable_cursor();
-                                return;
-                            }
+    // NOTE: this loop only exits when the user runs `exit`
+    loop {
+        keyboard::wait_for_scancode().await;
 
-                            print_prompt();
+        // NOTE: a scancode which doesn't complete a key press reads nothing
+        let n = vfs::get()
+            .read(console, &mut bytes)
+            .expect("failed to read from the console");
 
-                            // Pop the last item if the history is full and push this command into the queue
-                            if history.is_full() {
-                                history.pop_back();
-                            }
-                            history.push_front(input_buffer.clone()).unwrap();
+        for key in bytes[..n].iter().filter_map(|&byte| decoder.push(byte)) {
+            match key {
+                DecodedKey::Unicode(character) => {
+                    // Handle enter
+                    if character == '\n' {
+                        println!();
+
+                        if parse_and_execute(&input_buffer).await {
+                            vga::disable_cursor();
+                            let _ = vfs::get().close(console);
+                            return;
+                        }
+
+                        print_prompt();
+
+                        // Pop the last item if the history is full and push this command into the queue
+                        if history.is_full() {
+                            history.pop_back();
+                        }
+                        history.push_front(input_buffer.clone()).unwrap();
+
+                        input_buffer.clear();
+                        cursor_position = 0;
+                        history_position = None;
+                        continue;
+                    }
 
+                    // Handle backspace. The console sends ^H for
+                    // Ctrl-Backspace, which clears the whole line.
+                    if character == '\x7f' || character == '\x08' {
+                        if character == '\x08' {
                             input_buffer.clear();
                             cursor_position = 0;
-                            continue;
+                        } else if let Some(start) =
+                            previous_char_boundary(&input_buffer, cursor_position)
+                        {
+                            splice_input(&mut input_buffer, start..cursor_position, "");
+                            cursor_position = start;
                         }
 
-                        // Handle backspace
-                        if character == '\x08' {
-                            if keyboard.get_modifiers().is_ctrl() {
-                                input_buffer.clear();
-                                cursor_position = 0;
-                            } else {
-                                input_buffer.pop();
-                                cursor_position = cursor_position.saturating_sub(1);
-                            }
+                        redraw_input(&input_buffer, cursor_position, cursor_position);
 
-                            let col = get_prompt().len() as u8 + cursor_position;
+                        continue;
+                    }
 
-                            vga::set_column_position(col);
-                            for _ in
-                                (get_prompt().len() + cursor_position as usize)..vga::BUFFER_WIDTH
-                            {
-                                print!(" ");
-                            }
-                            vga::set_column_position(col);
+                    // Handle Ctrl-W by deleting the word before the cursor
+                    // along with any spaces between it and the cursor
+                    if character == '\x17' {
+                        let start = previous_word_boundary(&input_buffer, cursor_position);
 
-                            vga::set_cursor_position(col, vga::BUFFER_HEIGHT as u8 - 1);
+                        splice_input(&mut input_buffer, start..cursor_position, "");
+                        cursor_position = start;
 
-                            continue;
+                        redraw_input(&input_buffer, cursor_position, cursor_position);
+                        continue;
+                    }
+
+                    // Handle Ctrl-K by deleting everything after the cursor
+                    if character == '\x0b' {
+                        input_buffer.truncate(cursor_position);
+
+                        redraw_input(&input_buffer, cursor_position, cursor_position);
+                        continue;
+                    }
+
+                    // Handle Ctrl-C by abandoning the line and starting over
+                    // on a fresh prompt
+                    if character == '\x03' {
+                        move_input_cursor(&input_buffer, input_buffer.len());
+                        println!("^C");
+
+                        // The interrupt handler saw it as well, but there is no
+                        // command running for it to cancel
+                        keyboard::clear_interrupt();
+
+                        input_buffer.clear();
+                        cursor_position = 0;
+                        history_position = None;
+
+                        print_prompt();
+                        continue;
+                    }
+
+                    // Handle tab
+                    if character == '\t' {
+                        match completion::complete(&input_buffer[..cursor_position]) {
+                            Completion::Insert(text) => {
+                                if splice_input(
+                                    &mut input_buffer,
+                                    cursor_position..cursor_position,
+                                    &text,
+                                ) {
+                                    let from = cursor_position;
+                                    cursor_position += text.len();
+
+                                    redraw_input(&input_buffer, from, cursor_position);
+                                }
+                            }
+                            Completion::Candidates(candidates) => {
+                                println!();
+                                println!("{}", candidates.join("  "));
+
+                                print_prompt();
+                                redraw_input(&input_buffer, 0, cursor_position);
+                            }
+                            Completion::None => {}
                         }
 
-                        // Handle normal character
-                        if input_buffer.push(character).is_ok() {
-                            cursor_position += 1;
-                            print!("{}", character);
+                        continue;
+                    }
+
+                    // Other control characters (i.e. escape) would garble
+                    // the line if they were echoed
+                    if character.is_control() {
+                        continue;
+                    }
+
+                    // Handle normal character
+                    let mut encoded = [0; 4];
+                    let encoded = character.encode_utf8(&mut encoded);
+
+                    if splice_input(&mut input_buffer, cursor_position..cursor_position, encoded) {
+                        let from = cursor_position;
+                        cursor_position += encoded.len();
+
+                        redraw_input(&input_buffer, from, cursor_position);
+                    }
+                }
+                DecodedKey::RawKey(direction @ (KeyCode::ArrowUp | KeyCode::ArrowDown)) => {
+                    let newer = direction == KeyCode::ArrowDown;
+
+                    let new_history_position = match (history_position, newer) {
+                        (None, false) if !history.is_empty() => Some(0),
+                        (Some(i), false) if i + 1 < history.len() => Some(i + 1),
+                        (Some(0), true) => None,
+                        (Some(i), true) => Some(i - 1),
+                        // Already at the oldest entry or not scrolling
+                        _ => continue,
+                    };
+
+                    if history_position.is_none() {
+                        pending_input = input_buffer.clone();
+                    }
 
-                            let col = get_prompt().len() as u8 + cursor_position;
+                    input_buffer = match new_history_position {
+                        Some(i) => history.iter().nth(i).cloned().unwrap_or_default(),
+                        None => pending_input.clone(),
+                    };
+                    history_position = new_history_position;
+                    cursor_position = input_buffer.len();
 
-                            vga::set_cursor_position(col, vga::BUFFER_HEIGHT as u8 - 1);
+                    redraw_input(&input_buffer, 0, cursor_position);
+                }
+                DecodedKey::RawKey(key) => {
+                    let new_position = match key {
+                        KeyCode::ArrowLeft => {
+                            previous_char_boundary(&input_buffer, cursor_position)
                         }
+                        KeyCode::ArrowRight => input_buffer[cursor_position..]
+                            .chars()
+                            .next()
+                            .map(|c| cursor_position + c.len_utf8()),
+                        KeyCode::Home => Some(0),
+                        KeyCode::End => Some(input_buffer.len()),
+                        _ => None,
+                    };
+
+                    if let Some(new_position) = new_position {
+                        cursor_position = new_position;
+                        move_input_cursor(&input_buffer, cursor_position);
                     }
-                    DecodedKey::RawKey(_) => {}
                 }
             }
         }
     }
 }
 
+/// Returns the byte offset of the character before `position` in the input, or
+/// None if `position` is at the start
+fn previous_char_boundary(input: &str, position: usize) -> Option<usize> {
+    input[..position].char_indices().next_back().map(|(i, _)| i)
+}
+
+/// Returns the byte offset where the word before `position` starts. Whitespace
+/// right before `position` is skipped first, so repeated calls move back one
+/// word at a time.
+fn previous_word_boundary(input: &str, position: usize) -> usize {
+    input[..position]
+        .trim_end()
+        .trim_end_matches(|c: char| !c.is_whitespace())
+        .len()
+}
+
+/// Replaces the given byte range of the input buffer with `replacement`.
+/// Returns false and leaves the buffer untouched if the result does not fit.
+fn splice_input(input: &mut InputBuffer, range: Range<usize>, replacement: &str) -> bool {
+    let mut spliced = InputBuffer::new();
+
+    let fits = spliced
+        .push_str(&input[..range.start])
+        .and_then(|_| spliced.push_str(replacement))
+        .and_then(|_| spliced.push_str(&input[range.end..]))
+        .is_ok();
+
+    if fits {
+        *input = spliced;
+    }
+
+    fits
+}
+
+/// Reprints the input line from the byte offset `from` to the end, blanking out
+/// anything left over from before, and then moves the cursor to `cursor`.
+///
+/// NOTE: the VGA writer prints every character as one cell, even if it takes
+/// several bytes, so byte offsets in the input have to be converted to columns
+/// with [`input_column`].
+fn redraw_input(input: &str, from: usize, cursor: usize) {
+    let prompt_len = get_prompt().len();
+
+    vga::set_column_position((prompt_len + input_column(input, from)) as u8);
+    print!("{}", &input[from..]);
+    for _ in (prompt_len + input_column(input, input.len()))..vga::BUFFER_WIDTH {
+        print!(" ");
+    }
+
+    move_input_cursor(input, cursor);
+}
+
+/// Moves both the writer and the hardware cursor to the given byte offset in
+/// the input line
+fn move_input_cursor(input: &str, position: usize) {
+    let col = (get_prompt().len() + input_column(input, position)) as u8;
+
+    vga::set_column_position(col);
+    vga::set_cursor_position(col, vga::BUFFER_HEIGHT as u8 - 1);
+}
+
+/// Returns the column of the character at a byte offset in the input, not
+/// counting the prompt
+fn input_column(input: &str, position: usize) -> usize {
+    input[..position].chars().count()
+}
+
 const fn get_prompt() -> &'static str {
     "root@riptide> "
 }
@@ -113,40 +351,501 @@ fn print_prompt() {
 }
 
 async fn parse_and_execute(input: &str) -> bool {
-    vga::with_color(Color::LightGray, || println!("input: {:?}", input));
+    debug!("input: {:?}", input);
 
-    let mut args = VecDeque::<&str>::new();
+    // Split the input into separate commands at each `;`
+    let mut commands = vec![Vec::new()];
 
     for token in Parser::new(input) {
-        args.push_back(token);
+        match token {
+            Token::Word(word) => commands.last_mut().unwrap().push(word),
+            Token::Separator => commands.push(Vec::new()),
+        }
+    }
+
+    for words in commands {
+        // Skip empty commands (i.e. between `;;`)
+        if words.is_empty() {
+            continue;
+        }
+
+        if execute(&words).await {
+            return true;
+        }
     }
 
-    vga::with_color(Color::LightGray, || println!("args: {:?}", args));
+    false
+}
+
+/// A built-in command, as described by `help`
+struct Command {
+    name: &'static str,
+    /// The arguments the command takes, following its name
+    usage: &'static str,
+    /// A short description which fits in a column of the `help` listing
+    summary: &'static str,
+    /// Each option the command accepts and what it does
+    options: &'static [(&'static str, &'static str)],
+}
+
+/// Every built-in command. Used by `help`, for tab completion and to find
+/// commands which are listed but have no implementation in [`execute`].
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "[COMMAND]",
+        summary: "Show help for commands",
+        options: &[],
+    },
+    Command {
+        name: "whoami",
+        usage: "",
+        summary: "Print the current user",
+        options: &[],
+    },
+    Command {
+        name: "echo",
+        usage: "[-neE] [ARG]...",
+        summary: "Print the arguments",
+        options: &[
+            ("-n", "don't print a newline at the end"),
+            ("-e", "interpret \\n, \\t, \\\\ and \\xHH"),
+            ("-E", "print backslashes as is (the default)"),
+        ],
+    },
+    Command {
+        name: "print",
+        usage: "[-neE] [ARG]...",
+        summary: "Same as echo",
+        options: &[],
+    },
+    Command {
+        name: "pwd",
+        usage: "",
+        summary: "Print the working directory",
+        options: &[],
+    },
+    Command {
+        name: "uname",
+        usage: "[-a]",
+        summary: "Print system information",
+        options: &[("-a", "also print the release and machine")],
+    },
+    Command {
+        name: "ls",
+        usage: "[-aRlhimtSr] [PATH]",
+        summary: "List directory contents",
+        options: &[
+            ("-a", "show entries whose names start with a dot"),
+            ("-R", "list subdirectories recursively"),
+            ("-l", "show the metadata of each entry"),
+            ("-h", "print sizes like 1.5K with -l"),
+            ("-i", "show the node ID of each entry"),
+            ("-m", "show the mount of each entry"),
+            ("-t", "sort by modification time, newest first"),
+            ("-S", "sort by size, largest first"),
+            ("-r", "reverse the order"),
+        ],
+    },
+    Command {
+        name: "tree",
+        usage: "[-L LEVEL] [PATH]",
+        summary: "Show a directory tree",
+        options: &[("-L LEVEL", "only descend LEVEL directories deep")],
+    },
+    Command {
+        name: "find",
+        usage: "[PATH] [-name GLOB] [-type KIND]",
+        summary: "Search for entries",
+        options: &[
+            ("-name GLOB", "only print entries whose names match"),
+            (
+                "-type KIND",
+                "only print entries of a kind (f, d, c, b or l)",
+            ),
+        ],
+    },
+    Command {
+        name: "cat",
+        usage: "[-f] PATH...",
+        summary: "Print the contents of files",
+        options: &[("-f", "print files which look binary as well")],
+    },
+    Command {
+        name: "head",
+        usage: "[-n COUNT | -c BYTES] PATH",
+        summary: "Print the first lines",
+        options: &[
+            ("-n COUNT", "the number of lines (10 by default)"),
+            ("-c BYTES", "print the first bytes instead of lines"),
+        ],
+    },
+    Command {
+        name: "tail",
+        usage: "[-n COUNT] PATH",
+        summary: "Print the last lines",
+        options: &[("-n COUNT", "the number of lines (10 by default)")],
+    },
+    Command {
+        name: "wc",
+        usage: "[-lwc] PATH...",
+        summary: "Count lines, words and bytes",
+        options: &[
+            ("-l", "count lines"),
+            ("-w", "count words"),
+            ("-c", "count bytes"),
+        ],
+    },
+    Command {
+        name: "grep",
+        usage: "[-ni] PATTERN PATH",
+        summary: "Print lines with a pattern",
+        options: &[("-n", "show line numbers"), ("-i", "ignore case")],
+    },
+    Command {
+        name: "chmod",
+        usage: "MODE PATH...",
+        summary: "Change permission bits",
+        options: &[],
+    },
+    Command {
+        name: "touch",
+        usage: "[-acm] PATH...",
+        summary: "Update file timestamps",
+        options: &[
+            ("-a", "only change the access time"),
+            ("-c", "don't create missing files"),
+            ("-m", "only change the modification time"),
+        ],
+    },
+    Command {
+        name: "mkdir",
+        usage: "[-p] PATH...",
+        summary: "Create directories",
+        options: &[("-p", "create missing parents, allow existing")],
+    },
+    Command {
+        name: "ln",
+        usage: "[-s] TARGET LINK",
+        summary: "Create a link to an entry",
+        options: &[("-s", "create a symbolic link instead of a hard link")],
+    },
+    Command {
+        name: "cp",
+        usage: "[-v] SOURCE DESTINATION",
+        summary: "Copy a file",
+        options: &[("-v", "show the progress of the copy")],
+    },
+    Command {
+        name: "mv",
+        usage: "SOURCE DESTINATION",
+        summary: "Move or rename an entry",
+        options: &[],
+    },
+    Command {
+        name: "cmp",
+        usage: "FILE1 FILE2",
+        summary: "Compare files byte by byte",
+        options: &[],
+    },
+    Command {
+        name: "diff",
+        usage: "FILE1 FILE2",
+        summary: "Compare files line by line",
+        options: &[],
+    },
+    Command {
+        name: "hexdump",
+        usage: "[-n LEN] [-s OFFSET] PATH",
+        summary: "Print a file as hex bytes",
+        options: &[
+            ("-n", "only print LEN bytes"),
+            ("-s", "start OFFSET bytes into the file"),
+        ],
+    },
+    Command {
+        name: "xxd",
+        usage: "[-n LEN] [-s OFFSET] PATH",
+        summary: "Same as hexdump",
+        options: &[],
+    },
+    Command {
+        name: "kbd",
+        usage: "",
+        summary: "Show keyboard queue stats",
+        options: &[],
+    },
+    Command {
+        name: "free",
+        usage: "[-h] [-s]",
+        summary: "Show kernel heap usage",
+        options: &[
+            ("-h", "print sizes like 1.5K"),
+            ("-s", "also show the blocks of each slab"),
+        ],
+    },
+    Command {
+        name: "date",
+        usage: "",
+        summary: "Show the date and time",
+        options: &[],
+    },
+    Command {
+        name: "uptime",
+        usage: "",
+        summary: "Show the time since boot",
+        options: &[],
+    },
+    Command {
+        name: "sleep",
+        usage: "SECONDS",
+        summary: "Wait for a number of seconds",
+        options: &[],
+    },
+    Command {
+        name: "ps",
+        usage: "",
+        summary: "List running tasks",
+        options: &[],
+    },
+    Command {
+        name: "sched",
+        usage: "",
+        summary: "Show scheduler statistics",
+        options: &[],
+    },
+    Command {
+        name: "clear",
+        usage: "",
+        summary: "Clear the screen",
+        options: &[],
+    },
+    Command {
+        name: "reset",
+        usage: "",
+        summary: "Reset the terminal",
+        options: &[],
+    },
+    Command {
+        name: "cursor",
+        usage: "block|underline|off",
+        summary: "Change the cursor shape",
+        options: &[],
+    },
+    Command {
+        name: "loglevel",
+        usage: "[LEVEL]",
+        summary: "Show or set the log level",
+        options: &[],
+    },
+    Command {
+        name: "exec",
+        usage: "PATH [ARG]...",
+        summary: "Run a program",
+        options: &[],
+    },
+    Command {
+        name: "test",
+        usage: "EXPRESSION",
+        summary: "Evaluate an expression",
+        options: &[],
+    },
+    Command {
+        name: "[",
+        usage: "EXPRESSION ]",
+        summary: "Same as test",
+        options: &[],
+    },
+    Command {
+        name: "rm",
+        usage: "PATH",
+        summary: "Remove a file",
+        options: &[],
+    },
+    Command {
+        name: "rmdir",
+        usage: "PATH",
+        summary: "Remove an empty directory",
+        options: &[],
+    },
+    Command {
+        name: "mount",
+        usage: "[-t TYPE] [SOURCE] TARGET",
+        summary: "Mount a file system",
+        options: &[(
+            "-t TYPE",
+            "the file system type, detected from the source if missing",
+        )],
+    },
+    Command {
+        name: "umount",
+        usage: "TARGET",
+        summary: "Unmount a file system",
+        options: &[],
+    },
+    Command {
+        name: "df",
+        usage: "[-h]",
+        summary: "Show file system space usage",
+        options: &[("-h", "print sizes like 1.5K")],
+    },
+    Command {
+        name: "stat",
+        usage: "[-h] PATH",
+        summary: "Show details about an entry",
+        options: &[("-h", "describe a symbolic link instead of its target")],
+    },
+    Command {
+        name: "realpath",
+        usage: "[-s] PATH",
+        summary: "Print the canonical path",
+        options: &[("-s", "resolve \"..\" without following links")],
+    },
+    Command {
+        name: "basename",
+        usage: "PATH [SUFFIX]",
+        summary: "Strip directories from a path",
+        options: &[],
+    },
+    Command {
+        name: "cd",
+        usage: "[PATH]",
+        summary: "Change the working directory",
+        options: &[],
+    },
+    Command {
+        name: "usertest",
+        usage: "",
+        summary: "Run a test program in ring 3",
+        options: &[],
+    },
+    Command {
+        name: "selftest",
+        usage: "",
+        summary: "Run commands with bad paths",
+        options: &[],
+    },
+    Command {
+        name: "halt",
+        usage: "",
+        summary: "Stop the machine",
+        options: &[],
+    },
+    Command {
+        name: "reboot",
+        usage: "",
+        summary: "Restart the machine",
+        options: &[],
+    },
+    Command {
+        name: "shutdown",
+        usage: "",
+        summary: "Power off the machine",
+        options: &[],
+    },
+    Command {
+        name: "exit",
+        usage: "",
+        summary: "Exit the shell",
+        options: &[],
+    },
+];
+
+/// Runs a single command. Returns true if the shell should exit.
+async fn execute(words: &[String]) -> bool {
+    let mut args = words.iter().map(String::as_str).collect::<VecDeque<&str>>();
+
+    debug!("args: {:?}", args);
+
+    // Ignore any Ctrl-C that was pressed before this command started
+    keyboard::clear_interrupt();
+    LAST_STATUS.store(0, Ordering::Relaxed);
 
     loop {
         match args.pop_front() {
             Some("help") => {
-                println!("TODO: insert a help message here")
+                let Some(name) = args.front() else {
+                    // Two commands per row, which leaves the last column of
+                    // the screen free so that rows don't wrap
+                    for row in COMMANDS.chunks(2) {
+                        for (i, command) in row.iter().enumerate() {
+                            vga::with_color(Color::LightCyan, || print!("{:<9}", command.name));
+
+                            if i == 0 {
+                                print!("{:<31}", command.summary);
+                            } else {
+                                print!("{}", command.summary);
+                            }
+                        }
+
+                        println!();
+                    }
+
+                    println!("Run `help COMMAND` for details about a command");
+                    break;
+                };
+
+                let Some(command) = COMMANDS.iter().find(|c| c.name == *name) else {
+                    println!("help: no help topics match `{}'", name);
+                    break;
+                };
+
+                print!("usage: ");
+                vga::with_color(Color::LightCyan, || print!("{}", command.name));
+                println!(" {}", command.usage);
+                println!("{}", command.summary);
+
+                if !command.options.is_empty() {
+                    println!();
+                }
+
+                for (option, description) in command.options {
+                    println!("  {:<10} {}", option, description);
+                }
             }
             Some("whoami") => {
                 println!("root")
             }
             Some("echo" | "print") => {
-                let len = args.len();
-
-                for (i, arg) in args.iter().enumerate() {
-                    print!("{arg}");
+                let mut interpret_escapes = false;
+                let mut trailing_newline = true;
 
-                    if i < len - 1 {
-                        print!(" ");
+                // Like coreutils, only leading arguments made up entirely of
+                // known flags are options. Anything else is printed as is.
+                while let Some(flags) = args
+                    .front()
+                    .and_then(|a| a.strip_prefix('-'))
+                    .filter(|f| !f.is_empty() && f.chars().all(|c| matches!(c, 'n' | 'e' | 'E')))
+                {
+                    for c in flags.chars() {
+                        match c {
+                            'n' => trailing_newline = false,
+                            'e' => interpret_escapes = true,
+                            _ => interpret_escapes = false,
+                        }
                     }
+
+                    args.pop_front();
                 }
 
-                println!();
-            }
-            Some("pwd") => {
-                println!("/");
+                let text = args.iter().cloned().collect::<Vec<_>>().join(" ");
+
+                if interpret_escapes {
+                    // The console decodes what it is given as UTF-8, so bytes
+                    // which don't form a character show up as a replacement
+                    print!("{}", String::from_utf8_lossy(&expand_escapes(&text)));
+                } else {
+                    print!("{}", text);
+                }
+
+                if trailing_newline {
+                    println!();
+                }
             }
+            Some("pwd") => match vfs::get().current_directory() {
+                Ok(directory) => println!("{}", directory.path()),
+                Err(e) => println!("pwd: {}", e),
+            },
             Some("uname") => {
                 print!("Riptide");
 
@@ -157,157 +856,2307 @@ async fn parse_and_execute(input: &str) -> bool {
                 println!();
             }
             Some("ls") => {
-                let args = args.make_contiguous();
+                let args = Arguments::new(args.make_contiguous());
 
-                let path = without_flags(args).last().cloned().unwrap_or("/"); // FIXME: use pwd
+                let path = args.operands().last().cloned().unwrap_or(".");
 
-                let all = has_boolean_option(args, 'a');
-                let long = has_boolean_option(args, 'l');
-                let human_readable = has_boolean_option(args, 'h');
-                let show_node_ids = has_boolean_option(args, 'i');
+                let listing = Listing {
+                    all: args.has("a") || args.has("all"),
+                    long: args.has("l"),
+                    human_readable: args.has("h") || args.has("human-readable"),
+                    show_node_ids: args.has("i") || args.has("inode"),
+                    show_mounts: args.has("m"),
+                    sort_by_time: args.has("t"),
+                    sort_by_size: args.has("S"),
+                    reverse: args.has("r") || args.has("reverse"),
+                    recursive: args.has("R") || args.has("recursive"),
+                };
 
                 let e = match vfs::get().stat(path) {
                     Ok(e) => e,
-                    Err(IoError::EntryNotFound) => {
-                        println!("ls: {}: No such file or directory", path);
+                    Err(e) => {
+                        println!("ls: {}: {}", path, e);
                         break;
                     }
-                    Err(_) => todo!(),
                 };
 
-                let format_entry_short = |entry: &DirectoryIterationEntry| {
-                    if show_node_ids {
-                        print!("{} ", entry.id.as_u64());
+                let entry_path = Path::from_str(&e.path()).unwrap();
+
+                if !e.node.is_directory() {
+                    if listing.long {
+                        listing.print_entry_long(&e);
+                    } else {
+                        // Entries are looked up relative to the directory
+                        // they are in
+                        let parent = entry_path.parent().unwrap_or_default();
+                        listing.print_entry_short(&parent, &e.as_ref().into());
                     }
 
-                    vga::with_color(entry.kind.color_code(), || println!("{}", entry.name));
+                    break;
+                }
+
+                // Like coreutils, recursive listings label every directory
+                // including the first one
+                if listing.recursive {
+                    println!("{}:", path);
+                }
+
+                if listing.print_directory(&entry_path, path, 0).is_break() {
+                    println!("^C");
+                }
+            }
+            Some("tree") => {
+                let args = match Arguments::parse(args.make_contiguous(), &["L"]) {
+                    Ok(args) => args,
+                    Err(e) => {
+                        println!("tree: {}", e);
+                        break;
+                    }
                 };
 
-                let format_entry_long = |entry: &DirectoryEntry| {
-                    if show_node_ids {
-                        print!("{} ", entry.node.id.as_u64());
+                let max_depth = match args.numeric_value("L") {
+                    Ok(Some(0)) => {
+                        println!("tree: invalid level, must be greater than 0");
+                        break;
+                    }
+                    Ok(max_depth) => max_depth,
+                    Err(e) => {
+                        println!("tree: {}", e);
+                        break;
                     }
+                };
 
-                    let meta = entry.node.metadata.lock();
+                let path = args.operands().first().cloned().unwrap_or(".");
 
-                    println!(
-                        "{}rw-r--r--@ 1 root root {:>3} {:>2} {}",
-                        entry.node.kind, meta.size, meta.modified_at, entry.name
-                    );
+                let directory = match vfs::get().stat(path) {
+                    Ok(e) if e.node.is_directory() => Path::from_str(&e.path()).unwrap(),
+                    Ok(_) => {
+                        println!("tree: {}: Not a directory", path);
+                        break;
+                    }
+                    Err(e) => {
+                        println!("tree: {}: {}", path, e);
+                        break;
+                    }
                 };
 
-                if e.node.is_directory() {
-                    let entries = match vfs::get().read_directory(path) {
-                        Ok(v) => v,
-                        Err(_) => todo!(),
-                    };
+                vga::with_color(FsNodeKind::Directory.color_code(), || println!("{}", path));
 
-                    for child in entries {
-                        if long {
-                            // FIXME: create a path join abstraction
+                let mut walk = TreeWalk {
+                    max_depth,
+                    directories: 0,
+                    files: 0,
+                };
 
-                            let child_path = if e.name.as_ref() == "/" {
-                                format!("/{}", child.name)
-                            } else {
-                                format!("{}/{}", e.name, child.name)
-                            };
+                if walk.print_directory(&directory, "", 1).is_break() {
+                    println!("^C");
+                }
+
+                println!();
+                println!("{} directories, {} files", walk.directories, walk.files);
+            }
+            Some("find") => {
+                let mut path = None;
+                let mut query = FindQuery {
+                    name: None,
+                    kind: None,
+                };
 
-                            let c = vfs::get().stat(&child_path).unwrap();
+                let mut error = None;
 
-                            format_entry_long(&c);
-                        } else {
-                            format_entry_short(&child);
+                while let Some(arg) = args.pop_front() {
+                    match arg {
+                        "-name" => match args.pop_front() {
+                            Some(pattern) => query.name = Some(pattern),
+                            None => error = Some("missing argument to `-name'".to_string()),
+                        },
+                        "-type" => match args.pop_front() {
+                            Some("f") => query.kind = Some(FsNodeKind::File),
+                            Some("d") => query.kind = Some(FsNodeKind::Directory),
+                            Some("c") => query.kind = Some(FsNodeKind::CharDevice),
+                            Some("b") => query.kind = Some(FsNodeKind::BlockDevice),
+                            Some("l") => query.kind = Some(FsNodeKind::Symlink),
+                            Some(kind) => {
+                                error = Some(format!("unknown argument to -type: {}", kind))
+                            }
+                            None => error = Some("missing argument to `-type'".to_string()),
+                        },
+                        _ if arg.starts_with('-') => {
+                            error = Some(format!("unknown predicate `{}'", arg))
                         }
+                        _ if path.is_none() => path = Some(arg),
+                        _ => error = Some(format!("paths must precede expression: `{}'", arg)),
                     }
-                } else if long {
-                    format_entry_long(&e);
-                } else {
-                    format_entry_short(&e.as_ref().into());
-                }
-            }
-            Some("cat") => {
-                let Some(path) = args.front() else {
-                    println!("error: no path provided");
-                    break;
-                };
 
-                let f = vfs::get().open(path, FileMode::Read).unwrap();
+                    if error.is_some() {
+                        break;
+                    }
+                }
 
-                let mut data = [0u8; 512];
+                if let Some(e) = error {
+                    println!("find: {}", e);
+                    break;
+                }
 
-                let bytes = vfs::get().read(f, &mut data).unwrap();
+                let path = path.unwrap_or(".");
 
-                let data = &data[..bytes];
+                let kind = match vfs::get().stat(path) {
+                    Ok(e) => e.node.kind,
+                    Err(e) => {
+                        println!("find: {}: {}", path, e);
+                        break;
+                    }
+                };
 
-                println!("{}", String::from_utf8_lossy(data));
-            }
-            Some("touch") => {
-                let Some(path) = args.front() else {
-                    println!("error: no path provided");
+                let Ok(start) = Path::from_str(path) else {
+                    println!("find: {}: Invalid path", path);
                     break;
                 };
 
-                let f = vfs::get().open(path, FileMode::Write).unwrap();
-                vfs::get().close(f).unwrap();
-            }
-            Some("mkdir") => {
-                let args = args.make_contiguous();
+                // The starting point is matched by its own name, or the whole
+                // path if it has none (i.e. `/` or `.`)
+                let name = start.file_name().unwrap_or(path);
 
-                let Some(path) = without_flags(args).last().cloned() else {
-                    println!("error: no path provided");
-                    break;
-                };
+                let mut print = |path: &Path| println!("{}", path);
 
-                match vfs::get().create_directory(path) {
-                    Ok(_) => {}
-                    Err(e) => panic!("{e:?}"),
+                if find_entries(&start, name, kind, &query, &mut print).is_break() {
+                    println!("^C");
                 }
             }
-            Some("rm") => println!("error: not implemented yet"),
-            Some("realpath") => println!("error: not implemented yet"),
-            Some("basename") => println!("error: not implemented yet"),
-            Some("cd") => println!("error: not implemented yet"),
-            Some("exit") => {
-                return true;
-            }
-            // Unrecognized command
-            Some(cmd) => {
-                println!("command not found: {}", cmd)
-            }
-            // Got no actual input (just whitespace)
-            None => {}
-        }
+            Some("cat") => {
+                let args = Arguments::new(args.make_contiguous());
 
-        break;
-    }
+                if args.operands().is_empty() {
+                    println!("cat: missing operand");
+                    break;
+                }
 
-    false
-}
+                let force = args.has("f") || args.has("force");
+                let mut at_line_start = true;
 
-/// Parses argument list for single character option flags
-fn has_boolean_option(args: &[&str], flag: char) -> bool {
-    for arg in args {
-        if !arg.starts_with("-") {
-            continue;
-        }
+                for path in args.operands() {
+                    let error = match print_file(path, force, &mut at_line_start) {
+                        Ok(ControlFlow::Continue(())) => continue,
+                        Ok(ControlFlow::Break(())) => break,
+                        Err(e) => e,
+                    };
 
-        if arg.starts_with("--") {
-            todo!("parse named arguments");
-        }
+                    // Keep going with the next file like coreutils does
+                    if !at_line_start {
+                        println!();
+                        at_line_start = true;
+                    }
 
-        for c in arg.chars().skip(1) {
-            if c == flag {
-                return true;
+                    println!("cat: {}: {}", path, error);
+                }
+
+                if !at_line_start {
+                    println!();
+                }
             }
-        }
-    }
+            Some(command @ ("head" | "tail")) => {
+                let value_options: &[&str] = if command == "head" {
+                    &["n", "c"]
+                } else {
+                    &["n"]
+                };
 
-    false
+                let args = match Arguments::parse(args.make_contiguous(), value_options) {
+                    Ok(args) => args,
+                    Err(e) => {
+                        println!("{}: {}", command, e);
+                        break;
+                    }
+                };
+
+                let bytes = match args.numeric_value("c") {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        println!("{}: {}", command, e);
+                        break;
+                    }
+                };
+
+                let count = match args.numeric_value("n") {
+                    Ok(count) => count.unwrap_or(10),
+                    Err(e) => {
+                        println!("{}: {}", command, e);
+                        break;
+                    }
+                };
+
+                let Some(path) = args.operands().first().cloned() else {
+                    println!("{}: missing operand", command);
+                    break;
+                };
+
+                let result = if let Some(bytes) = bytes {
+                    print_head_bytes(path, bytes)
+                } else if command == "head" {
+                    print_head(path, count)
+                } else {
+                    print_tail(path, count)
+                };
+
+                match result {
+                    Ok(()) => {}
+                    Err(e) => println!("{}: {}: {}", command, path, e),
+                }
+            }
+            Some("wc") => {
+                let args = Arguments::new(args.make_contiguous());
+
+                let paths = args.operands();
+                if paths.is_empty() {
+                    println!("wc: missing operand");
+                    break;
+                }
+
+                let mut show_lines = args.has("l");
+                let mut show_words = args.has("w");
+                let mut show_bytes = args.has("c");
+
+                // Like coreutils, every count is shown if none were selected
+                if !show_lines && !show_words && !show_bytes {
+                    (show_lines, show_words, show_bytes) = (true, true, true);
+                }
+
+                let mut results = Vec::new();
+                let mut total = WordCount::default();
+
+                for path in paths {
+                    match count_words(path) {
+                        Ok(Some(count)) => {
+                            total.lines += count.lines;
+                            total.words += count.words;
+                            total.bytes += count.bytes;
+
+                            results.push((*path, count));
+                        }
+                        Ok(None) => {
+                            println!("^C");
+                            break;
+                        }
+                        Err(e) => println!("wc: {}: {}", path, e),
+                    }
+                }
+
+                let fields = |count: &WordCount| {
+                    [
+                        (show_lines, count.lines),
+                        (show_words, count.words),
+                        (show_bytes, count.bytes),
+                    ]
+                    .into_iter()
+                    .filter_map(|(show, n)| show.then_some(n))
+                };
+
+                // Every column is as wide as the largest number shown so that
+                // the columns line up
+                let width = fields(&total).max().unwrap_or(0).to_string().len();
+
+                let print_row = |count: &WordCount, name: &str| {
+                    for n in fields(count) {
+                        print!("{:>width$} ", n, width = width);
+                    }
+
+                    println!("{}", name);
+                };
+
+                for (path, count) in &results {
+                    print_row(count, path);
+                }
+
+                if paths.len() > 1 {
+                    print_row(&total, "total");
+                }
+            }
+            Some("grep") => {
+                let args = Arguments::new(args.make_contiguous());
+
+                let operands = args.operands();
+                let (Some(pattern), Some(path)) = (operands.first(), operands.get(1)) else {
+                    println!("grep: missing operand");
+                    break;
+                };
+
+                let line_numbers = args.has("n");
+                let ignore_case = args.has("i");
+
+                match grep_file(path, pattern, line_numbers, ignore_case) {
+                    Ok(()) => {}
+                    Err(IoError::NotAFile) => println!("grep: {}: Is a directory", path),
+                    Err(e) => println!("grep: {}: {}", path, e),
+                }
+            }
+            Some("touch") => {
+                let args = Arguments::new(args.make_contiguous());
+
+                if args.operands().is_empty() {
+                    println!("touch: missing operand");
+                    break;
+                }
+
+                // Both timestamps change unless only one was asked for
+                let (mut accessed, mut modified) = (args.has("a"), args.has("m"));
+                if !accessed && !modified {
+                    (accessed, modified) = (true, true);
+                }
+
+                for path in args.operands() {
+                    let result = match vfs::get().touch(path, accessed, modified) {
+                        Err(IoError::EntryNotFound) if !args.has("c") => create_empty_file(path),
+                        Err(IoError::EntryNotFound) => Ok(()),
+                        result => result.map(|_| ()),
+                    };
+
+                    if let Err(e) = result {
+                        println!("touch: {}: {}", path, e);
+                    }
+                }
+            }
+            Some("chmod") => {
+                let args = Arguments::new(args.make_contiguous());
+
+                let Some((mode, paths)) = args.operands().split_first() else {
+                    println!("chmod: missing operand");
+                    break;
+                };
+
+                if paths.is_empty() {
+                    println!("chmod: missing operand after '{}'", mode);
+                    break;
+                }
+
+                // Only octal modes are supported (i.e. 644)
+                let Some(mode) = u16::from_str_radix(mode, 8)
+                    .ok()
+                    .filter(|mode| mode & !MODE_MASK == 0)
+                else {
+                    println!("chmod: invalid mode: '{}'", mode);
+                    break;
+                };
+
+                for path in paths {
+                    if let Err(e) = vfs::get().chmod(path, mode) {
+                        println!("chmod: {}: {}", path, e);
+                    }
+                }
+            }
+            Some("mkdir") => {
+                let args = Arguments::new(args.make_contiguous());
+
+                if args.operands().is_empty() {
+                    println!("mkdir: missing operand");
+                    break;
+                }
+
+                for path in args.operands() {
+                    let result = if args.has("p") {
+                        vfs::get().create_directory_all(path)
+                    } else {
+                        vfs::get().create_directory(path)
+                    };
+
+                    if let Err(e) = result {
+                        println!("mkdir: cannot create directory '{}': {}", path, e);
+                    }
+                }
+            }
+            Some("ln") => {
+                let args = Arguments::new(args.make_contiguous());
+
+                let paths = args.operands();
+                let (Some(target), Some(link)) = (paths.first(), paths.get(1)) else {
+                    println!("ln: missing operand");
+                    break;
+                };
+
+                let result = if args.has("s") {
+                    vfs::get().create_symlink(target, link)
+                } else {
+                    vfs::get().link(target, link)
+                };
+
+                match result {
+                    Ok(_) => {}
+                    Err(IoError::NotAFile) => {
+                        println!("ln: {}: hard link not allowed for directory", target)
+                    }
+                    Err(e) => println!("ln: {}: {}", link, e),
+                }
+            }
+            Some("cp") => {
+                let args = Arguments::new(args.make_contiguous());
+
+                let paths = args.operands();
+                let (Some(source), Some(destination)) = (paths.first(), paths.get(1)) else {
+                    println!("cp: missing operand");
+                    break;
+                };
+
+                let size = match vfs::get().stat(source) {
+                    Ok(e) => e.node.metadata.lock().size,
+                    Err(e) => {
+                        println!("cp: {}: {}", source, e);
+                        break;
+                    }
+                };
+
+                let mut progress = if args.has("v") || args.has("progress") {
+                    Progress::new(source, size)
+                } else {
+                    None
+                };
+
+                let result = vfs::get().copy_file(source, destination, |copied| {
+                    if let Some(progress) = &mut progress {
+                        progress.update(copied);
+                    }
+                });
+
+                // Finish the progress line so that errors go on their own
+                drop(progress);
+
+                match result {
+                    Ok(_) => {}
+                    Err(IoError::NotAFile) => {
+                        println!(
+                            "cp: {}: is not a regular file (directories need -r)",
+                            source
+                        )
+                    }
+                    Err(IoError::SameFile) => {
+                        println!("cp: {} and {} are the same file", source, destination)
+                    }
+                    Err(e) => println!("cp: {}: {}", destination, e),
+                }
+            }
+            Some("mv") => {
+                let (Some(source), Some(destination)) = (args.front(), args.get(1)) else {
+                    println!("mv: missing operand");
+                    break;
+                };
+
+                // Moving onto a directory moves the entry into it
+                let destination = match vfs::get().is_dir(destination) {
+                    Ok(true) => {
+                        let source_path = Path::from_str(source);
+                        let name = source_path.as_ref().ok().and_then(Path::file_name);
+
+                        match (name, Path::from_str(destination)) {
+                            (Some(name), Ok(directory)) => directory.join(name).to_string(),
+                            _ => {
+                                println!("mv: {}: Invalid path", source);
+                                break;
+                            }
+                        }
+                    }
+                    _ => destination.to_string(),
+                };
+
+                match vfs::get().rename(source, &destination) {
+                    Ok(()) => {}
+                    Err(IoError::AlreadyExists) => {
+                        println!("mv: {}: Directory already exists", destination)
+                    }
+                    Err(IoError::InvalidPath) => {
+                        println!("mv: can't move {} to {}", source, destination)
+                    }
+                    Err(IoError::OperationNotSupported) => {
+                        println!("mv: can't move {} across file systems", source)
+                    }
+                    Err(e) => println!("mv: {}: {}", source, e),
+                }
+            }
+            Some(command @ ("cmp" | "diff")) => {
+                let (Some(a), Some(b)) = (args.front(), args.get(1)) else {
+                    println!("{}: missing operand", command);
+                    LAST_STATUS.store(2, Ordering::Relaxed);
+                    break;
+                };
+
+                let result = if command == "cmp" {
+                    compare_files(a, b)
+                } else {
+                    diff_files(a, b)
+                };
+
+                let status = match result {
+                    Ok(true) => 0,
+                    Ok(false) => 1,
+                    Err(e) => {
+                        println!("{}: {}", command, e);
+                        2
+                    }
+                };
+
+                LAST_STATUS.store(status, Ordering::Relaxed);
+            }
+            Some(command @ ("hexdump" | "xxd")) => {
+                let args = match Arguments::parse(args.make_contiguous(), &["n", "s"]) {
+                    Ok(args) => args,
+                    Err(e) => {
+                        println!("{}: {}", command, e);
+                        break;
+                    }
+                };
+
+                let (length, skip) = match (args.numeric_value("n"), args.numeric_value("s")) {
+                    (Ok(length), Ok(skip)) => (length, skip.unwrap_or(0)),
+                    (Err(e), _) | (_, Err(e)) => {
+                        println!("{}: {}", command, e);
+                        break;
+                    }
+                };
+
+                let Some(path) = args.operands().first().cloned() else {
+                    println!("{}: missing operand", command);
+                    break;
+                };
+
+                match hexdump_file(path, skip, length) {
+                    Ok(()) => {}
+                    Err(e) => println!("{}: {}: {}", command, path, e),
+                }
+            }
+            Some("kbd") => {
+                println!(
+                    "scancodes: {}/{} queued, {} dropped",
+                    keyboard::queued_scancodes(),
+                    keyboard::SCANCODE_QUEUE_CAPACITY,
+                    keyboard::dropped_scancodes()
+                );
+            }
+            Some("free") => {
+                let args = Arguments::new(args.make_contiguous());
+                let stats = allocator::stats();
+
+                let size = |n: usize| {
+                    if args.has("h") {
+                        human_bytes(n).to_string()
+                    } else {
+                        n.to_string()
+                    }
+                };
+
+                println!(
+                    "{:<6}{:>10}{:>10}{:>10}{:>10}",
+                    "", "total", "used", "free", "cached"
+                );
+                println!(
+                    "{:<6}{:>10}{:>10}{:>10}{:>10}",
+                    "Heap:",
+                    size(stats.size),
+                    size(stats.used()),
+                    size(stats.available()),
+                    size(stats.cached())
+                );
+
+                if args.has("s") {
+                    println!();
+                    println!("{:<6}{:>10}{:>10}{:>10}", "slab", "blocks", "used", "free");
+
+                    for slab in &stats.slabs {
+                        println!(
+                            "{:<6}{:>10}{:>10}{:>10}",
+                            slab.block_size,
+                            slab.blocks,
+                            slab.blocks - slab.free_blocks,
+                            slab.free_blocks
+                        );
+                    }
+                }
+            }
+            Some("date") => {
+                // Read the RTC again rather than going by the boot time so
+                // that this shows the clock as it is now
+                println!("{}", rtc::now());
+            }
+            Some("uptime") => {
+                let ms = time::uptime_ms();
+                let seconds = ms / 1000;
+
+                println!(
+                    "up {}:{:02}:{:02}.{:03} ({} ticks at {} Hz)",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60,
+                    ms % 1000,
+                    time::ticks(),
+                    interrupts::timer_frequency()
+                );
+            }
+            Some("sleep") => {
+                let Some(seconds) = args.front().and_then(|s| s.parse::<u64>().ok()) else {
+                    println!("sleep: invalid time interval");
+                    break;
+                };
+
+                // Sleep in short steps so that the user can cancel with Ctrl-C
+                let deadline =
+                    time::ticks().saturating_add(time::ticks_for(Duration::from_secs(seconds)));
+
+                while time::ticks() < deadline {
+                    if keyboard::take_interrupt() {
+                        println!("^C");
+                        break;
+                    }
+
+                    task::sleep(Duration::from_millis(100)).await;
+                }
+            }
+            Some("ps") => {
+                println!("{:>4} NAME", "ID");
+
+                for task in executor::tasks() {
+                    println!("{:>4} {}", task.id.as_u64(), task.name.unwrap_or("-"));
+                }
+            }
+            Some("sched") => {
+                println!(
+                    "polls: {}, idle halts: {}",
+                    executor::polls(),
+                    executor::halts()
+                );
+            }
+            Some("clear") => {
+                // The prompt is reprinted on the cleared screen after we return
+                vga::clear_screen();
+            }
+            Some("reset") => {
+                // Recover from anything which garbled the terminal (i.e. cat of
+                // a binary file). The prompt is reprinted after we return.
+                console::discard_input();
+                keyboard::clear_interrupt();
+
+                vga::set_color_code(vga::DEFAULT_COLOR_CODE);
+                vga::clear_screen();
+                vga::restore_cursor();
+            }
+            Some("cursor") => match args.front() {
+                Some(&"block") => vga::set_cursor_shape(vga::CURSOR_BLOCK.0, vga::CURSOR_BLOCK.1),
+                Some(&"underline") => {
+                    vga::set_cursor_shape(vga::CURSOR_UNDERLINE.0, vga::CURSOR_UNDERLINE.1)
+                }
+                Some(&"off") => vga::hide_cursor(),
+                _ => println!("usage: cursor <block|underline|off>"),
+            },
+            Some("loglevel") => {
+                let Some(name) = args.pop_front() else {
+                    println!("{}", log::level());
+                    break;
+                };
+
+                match name.parse::<log::Level>() {
+                    Ok(level) => log::set_level(level),
+                    Err(_) => {
+                        print!("loglevel: invalid level '{}' (expected one of:", name);
+                        for level in log::Level::ALL {
+                            print!(" {}", level);
+                        }
+                        println!(")");
+                    }
+                }
+            }
+            Some("exec") => {
+                let Some(path) = args.pop_front() else {
+                    println!("exec: missing operand");
+                    break;
+                };
+
+                let args = args.make_contiguous();
+
+                match exec::exec(path, args) {
+                    Ok(0) => {}
+                    Ok(status) => println!("exec: {}: exited with status {}", path, status),
+                    Err(e) => println!("exec: {}: {}", path, e),
+                }
+            }
+            Some(command @ ("test" | "[")) => {
+                // `[` is the same as `test` except that the expression must be
+                // closed with a `]`
+                if command == "[" && args.pop_back() != Some("]") {
+                    println!("[: missing ']'");
+                    break;
+                }
+
+                // FIXME: nothing can check LAST_STATUS from the command line
+                // yet, so the result is printed instead
+                match evaluate_test(args.make_contiguous()) {
+                    Ok(result) => println!("{}", result),
+                    Err(e) => println!("{}: {}", command, e),
+                }
+            }
+            Some("rm") => {
+                let Some(path) = args.front() else {
+                    println!("rm: missing operand");
+                    break;
+                };
+
+                match vfs::get().remove_file(path) {
+                    Ok(()) => {}
+                    Err(IoError::NotAFile) => {
+                        println!("rm: {}: is a directory (use rmdir)", path)
+                    }
+                    Err(e) => println!("rm: {}: {}", path, e),
+                }
+            }
+            Some("rmdir") => {
+                let Some(path) = args.front() else {
+                    println!("rmdir: missing operand");
+                    break;
+                };
+
+                match vfs::get().remove_directory(path) {
+                    Ok(()) => {}
+                    Err(e) => println!("rmdir: {}: {}", path, e),
+                }
+            }
+            Some("mount") => {
+                let args = match Arguments::parse(args.make_contiguous(), &["t"]) {
+                    Ok(args) => args,
+                    Err(_) => {
+                        println!("mount: -t: missing file system type");
+                        break;
+                    }
+                };
+
+                // Without any arguments, list the mount table
+                if args.operands().is_empty() && !args.has("t") {
+                    for mount in vfs::get().mounts() {
+                        let source = match mount.source.as_str() {
+                            "" => "none",
+                            source => source,
+                        };
+
+                        let mode = if mount.flags.contains(MountFlags::WRITE) {
+                            "rw"
+                        } else {
+                            "ro"
+                        };
+
+                        println!(
+                            "{} on {} type {} ({})",
+                            source, mount.target, mount.file_system_type, mode
+                        );
+                    }
+
+                    break;
+                }
+
+                let kind = args.value("t");
+
+                // Virtual file systems don't need a source
+                let (source, target) = match args.operands() {
+                    [target] => ("", *target),
+                    [source, target] => (*source, *target),
+                    _ => {
+                        println!("usage: mount [-t TYPE] [SOURCE] TARGET");
+                        break;
+                    }
+                };
+
+                match vfs::get().mount(source, target, kind, MountFlags::READ | MountFlags::WRITE) {
+                    Ok(_) => {}
+                    Err(IoError::FileSystemTypeNotFound) => {
+                        println!(
+                            "mount: {}: unknown file system type",
+                            kind.unwrap_or(source)
+                        )
+                    }
+                    Err(e) => println!("mount: {}: {}", target, e),
+                }
+            }
+            Some("umount") => {
+                let Some(target) = args.front() else {
+                    println!("umount: missing operand");
+                    break;
+                };
+
+                match vfs::get().unmount(target) {
+                    Ok(()) => {}
+                    Err(IoError::InvalidPath) => println!("umount: {}: not mounted", target),
+                    Err(IoError::Busy) => println!("umount: {}: target is busy", target),
+                    Err(e) => println!("umount: {}: {}", target, e),
+                }
+            }
+            Some("df") => {
+                let human_readable = Arguments::new(args.make_contiguous()).has("h");
+
+                let format_size = |bytes: Option<usize>| match bytes {
+                    Some(bytes) if human_readable => human_bytes(bytes).to_string(),
+                    Some(bytes) => bytes.to_string(),
+                    // File systems without a fixed size have no limit to report
+                    None => "-".to_string(),
+                };
+
+                println!(
+                    "{:<10} {:<6} {:>5} {:>10} {:>10} {:>10} Mounted on",
+                    "Filesystem", "Type", "Block", "Size", "Used", "Avail"
+                );
+
+                for mount in vfs::get().mounts() {
+                    let stats = match vfs::get().statfs(&mount.target) {
+                        Ok(stats) => stats,
+                        Err(e) => {
+                            println!("df: {}: {}", mount.target, e);
+                            continue;
+                        }
+                    };
+
+                    let source = match mount.source.as_str() {
+                        "" => "none",
+                        source => source,
+                    };
+
+                    let block_size = stats.block_size;
+
+                    println!(
+                        "{:<10} {:<6} {:>5} {:>10} {:>10} {:>10} {}",
+                        source,
+                        mount.file_system_type,
+                        block_size,
+                        format_size(stats.total_blocks().map(|b| b * block_size)),
+                        format_size(Some(stats.used_blocks * block_size)),
+                        format_size(stats.free_blocks.map(|b| b * block_size)),
+                        mount.target
+                    );
+                }
+            }
+            Some("stat") => {
+                let args = Arguments::new(args.make_contiguous());
+
+                let Some(path) = args.operands().first().cloned() else {
+                    println!("stat: missing operand");
+                    break;
+                };
+
+                // With -h a link is described itself instead of its target
+                let result = if args.has("h") {
+                    vfs::get().lstat(path)
+                } else {
+                    vfs::get().stat(path)
+                };
+
+                let entry = match result {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        println!("stat: {}: {}", path, e);
+                        break;
+                    }
+                };
+
+                let node = &entry.node;
+                let meta = node.metadata.lock();
+
+                if node.kind == FsNodeKind::Symlink {
+                    let target = vfs::get()
+                        .read_link(&entry.path())
+                        .unwrap_or_else(|_| "?".into());
+
+                    println!("  File: {} -> {}", entry.path(), target);
+                } else {
+                    println!("  File: {}", entry.path());
+                }
+
+                println!(
+                    "  Kind: {} ({:04o}/{}{})",
+                    node.kind.description(),
+                    meta.mode,
+                    node.kind,
+                    Permissions(meta.mode)
+                );
+                println!(
+                    "  Node: {:<10} Mount: {} ({})",
+                    node.id.as_u64(),
+                    node.mount_id.as_u64(),
+                    mount_label(&entry)
+                );
+                println!("  Size: {:<10} Links: {}", meta.size, meta.link_count);
+                println!("Access: {}", DateTime::from_timestamp(meta.accessed_at));
+                println!("Modify: {}", DateTime::from_timestamp(meta.modified_at));
+                println!("Create: {}", DateTime::from_timestamp(meta.created_at));
+            }
+            Some("realpath") => {
+                let args = Arguments::new(args.make_contiguous());
+
+                let Some(&path) = args.operands().first() else {
+                    println!("realpath: missing operand");
+                    break;
+                };
+
+                if args.has("s") {
+                    // Lexical, so the path doesn't have to exist
+                    let joined = vfs::get()
+                        .current_directory()
+                        .map(|directory| directory.path())
+                        .and_then(|cwd| Ok(Path::from_str(&cwd)?.join(path).normalize()));
+
+                    match joined {
+                        Ok(path) => println!("{}", path),
+                        Err(e) => println!("realpath: {}: {}", path, e),
+                    }
+
+                    break;
+                }
+
+                // Resolving the entry checks that every segment exists, and
+                // its path in the directory cache is already canonical
+                match vfs::get().stat(path) {
+                    Ok(entry) => println!("{}", entry.path()),
+                    Err(e) => println!("realpath: {}: {}", path, e),
+                }
+            }
+            Some("basename") => {
+                let Some(path) = args.front() else {
+                    println!("basename: missing operand");
+                    break;
+                };
+
+                let path = match Path::from_str(path) {
+                    Ok(path) => path,
+                    // An empty path has an empty basename
+                    Err(PathParseError::Empty) => {
+                        println!();
+                        break;
+                    }
+                    Err(e) => {
+                        println!("basename: {}: {:?}", path, e);
+                        break;
+                    }
+                };
+
+                let name = match path.file_name() {
+                    Some(name) => name,
+                    None if path.parent().is_none() && path.is_absolute() => "/",
+                    None => "..",
+                };
+
+                // The suffix is only removed if something is left afterwards
+                let name = match args.get(1) {
+                    Some(suffix) if name != *suffix => name.strip_suffix(suffix).unwrap_or(name),
+                    _ => name,
+                };
+
+                println!("{}", name);
+            }
+            Some("cd") => {
+                // There are no home directories, so root's home is the root
+                let path = args.front().cloned().unwrap_or("/");
+
+                match vfs::get().change_directory(path) {
+                    Ok(_) => {}
+                    Err(e) => println!("cd: {}: {}", path, e),
+                }
+            }
+            Some("halt") => {
+                sync_before_power_off("halt");
+
+                vga::with_color(Color::Yellow, || println!("System halted"));
+                vga::disable_cursor();
+
+                power::halt();
+            }
+            Some("reboot") => {
+                sync_before_power_off("reboot");
+
+                vga::with_color(Color::Yellow, || println!("Rebooting..."));
+
+                power::reboot();
+            }
+            Some("shutdown") => {
+                sync_before_power_off("shutdown");
+
+                vga::with_color(Color::Yellow, || println!("Powering off..."));
+                vga::disable_cursor();
+
+                // Falls back to halting where powering off isn't supported
+                power::shutdown();
+            }
+            Some("usertest") => match user::run_test_program() {
+                Ok(status) => println!("usertest: exited with status {}", status),
+                Err(e) => println!("usertest: {}", e),
+            },
+            Some("selftest") => selftest::run().await,
+            Some("exit") => {
+                return true;
+            }
+            // Listed in the table but not handled above
+            Some(cmd) if COMMANDS.iter().any(|c| c.name == cmd) => {
+                println!("{}: not implemented", cmd)
+            }
+            // Unrecognized command
+            Some(cmd) => {
+                println!("command not found: {}", cmd)
+            }
+            // Got no actual input (just whitespace)
+            None => {}
+        }
+
+        break;
+    }
+
+    // The keys of a Ctrl-C which cancelled the command are still waiting in
+    // the console, where the prompt would take them for another Ctrl-C
+    if keyboard::take_cancellation() {
+        console::discard_input();
+    }
+
+    false
+}
+
+/// Lines longer than this are split by [`ByteReader::next_line`] so that
+/// devices which never produce a newline can't exhaust memory
+const MAX_LINE_LENGTH: usize = 1024;
+
+/// Streams the contents of an open file one byte at a time using chunked reads
+struct ByteReader {
+    fd: FileDescriptor,
+    buffer: [u8; 512],
+    length: usize,
+    position: usize,
+}
+
+impl ByteReader {
+    fn new(fd: FileDescriptor) -> Self {
+        Self {
+            fd,
+            buffer: [0; 512],
+            length: 0,
+            position: 0,
+        }
+    }
+
+    /// Returns the next byte in the file or None once the end is reached
+    fn next_byte(&mut self) -> Result<Option<u8>, IoError> {
+        if self.position == self.length {
+            self.length = vfs::get().read(self.fd, &mut self.buffer)?;
+            self.position = 0;
+
+            if self.length == 0 {
+                return Ok(None);
+            }
+        }
+
+        let byte = self.buffer[self.position];
+        self.position += 1;
+
+        Ok(Some(byte))
+    }
+
+    /// Returns the next line in the file without its newline or None once the
+    /// end is reached. The last line doesn't need to end with a newline.
+    fn next_line(&mut self) -> Result<Option<Vec<u8>>, IoError> {
+        let mut line = Vec::new();
+
+        loop {
+            match self.next_byte()? {
+                Some(b'\n') => return Ok(Some(line)),
+                Some(byte) => {
+                    line.push(byte);
+
+                    if line.len() == MAX_LINE_LENGTH {
+                        return Ok(Some(line));
+                    }
+                }
+                None if line.is_empty() => return Ok(None),
+                None => return Ok(Some(line)),
+            }
+        }
+    }
+}
+
+/// Decodes text which is read in chunks. A character whose bytes are split
+/// between two chunks is held back until the rest of it arrives, rather than
+/// being replaced like [`String::from_utf8_lossy`] would.
+#[derive(Default)]
+struct Utf8Decoder {
+    /// The start of a character at the end of the last chunk
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    /// Decodes the next chunk. Invalid bytes are replaced with U+FFFD.
+    fn decode(&mut self, chunk: &[u8]) -> String {
+        let mut bytes = core::mem::take(&mut self.pending);
+        bytes.extend_from_slice(chunk);
+
+        let mut text = String::new();
+        let mut rest = bytes.as_slice();
+
+        loop {
+            match core::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    return text;
+                }
+                Err(e) => {
+                    let (valid, invalid) = rest.split_at(e.valid_up_to());
+                    text.push_str(core::str::from_utf8(valid).unwrap());
+
+                    // The error has no length if the input ended in the middle
+                    // of a character, which the next chunk may complete
+                    let Some(length) = e.error_len() else {
+                        self.pending = invalid.to_vec();
+                        return text;
+                    };
+
+                    text.push(char::REPLACEMENT_CHARACTER);
+                    rest = &invalid[length..];
+                }
+            }
+        }
+    }
+
+    /// Returns a replacement for a character which was cut short by the end of
+    /// the input, if any
+    fn finish(self) -> String {
+        String::from_utf8_lossy(&self.pending).into_owned()
+    }
+}
+
+/// Shows how far along an operation is as a percentage, which is redrawn in
+/// place on one line. The line is finished once the progress is dropped, so
+/// that it's cleaned up however the operation ends.
+struct Progress<'a> {
+    label: &'a str,
+    total: usize,
+    shown: bool,
+}
+
+impl<'a> Progress<'a> {
+    /// Returns None if there is no point in showing progress, either since
+    /// the total is unknown (0) or since nobody watches the output live
+    fn new(label: &'a str, total: usize) -> Option<Self> {
+        if total == 0 || !INTERACTIVE.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        Some(Self {
+            label,
+            total,
+            shown: false,
+        })
+    }
+
+    fn update(&mut self, done: usize) {
+        print!(
+            "\r{}: {:>3}%",
+            self.label,
+            done.min(self.total) * 100 / self.total
+        );
+        self.shown = true;
+    }
+}
+
+impl Drop for Progress<'_> {
+    fn drop(&mut self) {
+        if self.shown {
+            println!();
+        }
+    }
+}
+
+/// Prints the contents of a file. `at_line_start` tracks whether the output so
+/// far ends with a newline so that the caller can start messages on a line of
+/// their own. Breaks if the user cancelled with Ctrl-C.
+fn print_file(
+    path: &str,
+    force: bool,
+    at_line_start: &mut bool,
+) -> Result<ControlFlow<()>, IoError> {
+    let fd = vfs::get().open(path, FileMode::Read)?;
+    let _close = defer_handle!({
+        let _ = vfs::get().close(fd);
+    });
+
+    let mut data = [0u8; 512];
+    let mut first_chunk = true;
+    let mut decoder = Utf8Decoder::default();
+
+    // Devices like /dev/zero never reach the end, so keep reading until the
+    // user cancels with Ctrl-C
+    loop {
+        if keyboard::take_interrupt() {
+            print!("^C");
+            *at_line_start = false;
+            return Ok(ControlFlow::Break(()));
+        }
+
+        let n = vfs::get().read(fd, &mut data)?;
+        if n == 0 {
+            print!("{}", decoder.finish());
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        // Dumping binary data garbles the screen, so only do it if the user
+        // really wants to
+        if first_chunk && !force && looks_binary(&data[..n]) {
+            if !*at_line_start {
+                println!();
+            }
+
+            println!("cat: {}: binary file (use hexdump)", path);
+            *at_line_start = true;
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        first_chunk = false;
+        print!("{}", decoder.decode(&data[..n]));
+        *at_line_start = data[n - 1] == b'\n';
+    }
+}
+
+/// Writes the file systems back before the machine stops. A failed flush must
+/// not prevent stopping since this may be the only way left to stop the
+/// machine safely.
+fn sync_before_power_off(cmd: &str) {
+    if let Err(e) = vfs::get().sync() {
+        println!("{}: failed to sync file systems: {}", cmd, e);
+    }
+}
+
+/// Creates an empty file, leaving it alone if it was created in the meantime
+fn create_empty_file(path: &str) -> Result<(), IoError> {
+    // An exclusive create never truncates a file which already exists
+    let fd = match vfs::get().open_with_flags(path, FileMode::Write, OpenFlags::EXCL) {
+        Ok(fd) => fd,
+        Err(IoError::AlreadyExists) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    vfs::get().close(fd)
+}
+
+/// Prints the first `count` lines of a file
+fn print_head(path: &str, count: usize) -> Result<(), IoError> {
+    let fd = vfs::get().open(path, FileMode::Read)?;
+    let _close = defer_handle!({
+        let _ = vfs::get().close(fd);
+    });
+
+    let mut reader = ByteReader::new(fd);
+
+    for _ in 0..count {
+        if keyboard::take_interrupt() {
+            println!("^C");
+            break;
+        }
+
+        let Some(line) = reader.next_line()? else {
+            break;
+        };
+
+        println!("{}", String::from_utf8_lossy(&line));
+    }
+
+    Ok(())
+}
+
+/// Prints up to `count` bytes of a file. Unlike lines, the byte count bounds
+/// reads from devices like /dev/zero which never reach the end.
+fn print_head_bytes(path: &str, count: usize) -> Result<(), IoError> {
+    let fd = vfs::get().open(path, FileMode::Read)?;
+    let _close = defer_handle!({
+        let _ = vfs::get().close(fd);
+    });
+
+    let mut data = [0u8; 512];
+    let mut remaining = count;
+    let mut at_line_start = true;
+    let mut decoder = Utf8Decoder::default();
+
+    while remaining > 0 {
+        if keyboard::take_interrupt() {
+            print!("^C");
+            at_line_start = false;
+            break;
+        }
+
+        let n = vfs::get().read(fd, &mut data[..remaining.min(data.len())])?;
+        if n == 0 {
+            break;
+        }
+
+        print!("{}", decoder.decode(&data[..n]));
+        at_line_start = data[n - 1] == b'\n';
+        remaining -= n;
+    }
+
+    print!("{}", decoder.finish());
+
+    if !at_line_start {
+        println!();
+    }
+
+    Ok(())
+}
+
+/// The number of bytes shown on each row of a hexdump
+const HEXDUMP_ROW_LENGTH: usize = 16;
+
+/// Prints up to `length` bytes of a file starting at `skip` in the same format
+/// as `hexdump -C`: the offset, the bytes in hex and then the bytes as ASCII.
+fn hexdump_file(path: &str, skip: usize, length: Option<usize>) -> Result<(), IoError> {
+    let fd = vfs::get().open(path, FileMode::Read)?;
+    let _close = defer_handle!({
+        let _ = vfs::get().close(fd);
+    });
+
+    let mut reader = ByteReader::new(fd);
+
+    // Devices can't always seek, so those skip ahead by reading instead
+    let mut offset = match vfs::get().seek(fd, SeekFrom::Start(skip)) {
+        Ok(offset) => offset,
+        Err(IoError::OperationNotSupported) => {
+            let mut skipped = 0;
+
+            while skipped < skip && reader.next_byte()?.is_some() {
+                skipped += 1;
+            }
+
+            skipped
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut remaining = length.unwrap_or(usize::MAX);
+
+    while remaining > 0 {
+        if keyboard::take_interrupt() {
+            println!("^C");
+            return Ok(());
+        }
+
+        let mut row = [0u8; HEXDUMP_ROW_LENGTH];
+        let mut n = 0;
+
+        while n < row.len().min(remaining) {
+            let Some(byte) = reader.next_byte()? else {
+                break;
+            };
+
+            row[n] = byte;
+            n += 1;
+        }
+
+        if n == 0 {
+            break;
+        }
+
+        print_hexdump_row(offset, &row[..n]);
+
+        offset += n;
+        remaining -= n;
+    }
+
+    // Like hexdump, the last line is the offset of the end
+    println!("{:08x}", offset);
+
+    Ok(())
+}
+
+/// Prints a single row of a hexdump. Rows which are shorter than a full row are
+/// padded so that the ASCII column still lines up.
+fn print_hexdump_row(offset: usize, row: &[u8]) {
+    print!("{:08x} ", offset);
+
+    for i in 0..HEXDUMP_ROW_LENGTH {
+        // An extra space splits the row into two groups of 8
+        if i % 8 == 0 {
+            print!(" ");
+        }
+
+        match row.get(i) {
+            Some(byte) => print!("{:02x} ", byte),
+            None => print!("   "),
+        }
+    }
+
+    print!(" |");
+    for &byte in row {
+        let c = if byte.is_ascii_graphic() || byte == b' ' {
+            byte as char
+        } else {
+            '.'
+        };
+
+        print!("{}", c);
+    }
+    println!("|");
+}
+
+/// The deepest level of subdirectories which `ls -R` descends into
+const MAX_LISTING_DEPTH: usize = 32;
+
+/// The options of an `ls` listing
+struct Listing {
+    /// Whether to show entries whose names start with a dot
+    all: bool,
+    long: bool,
+    human_readable: bool,
+    show_node_ids: bool,
+    show_mounts: bool,
+    sort_by_time: bool,
+    sort_by_size: bool,
+    reverse: bool,
+    recursive: bool,
+}
+
+impl Listing {
+    /// Prints the name of an entry of the given directory
+    fn print_entry_short(&self, directory: &Path, entry: &DirectoryIterationEntry) {
+        if self.show_node_ids {
+            print!("{} ", entry.id.as_u64());
+        }
+
+        if self.show_mounts {
+            // Entries from the directory listing don't carry their mount, so
+            // we have to resolve them
+            let label = vfs::get()
+                .stat(&directory.join(&entry.name).to_string())
+                .map(|entry| mount_label(&entry))
+                .unwrap_or_else(|_| "?".into());
+
+            print!("{} ", label);
+        }
+
+        vga::with_color(entry.kind.color_code(), || println!("{}", entry.name));
+    }
+
+    /// Prints an entry along with its metadata
+    fn print_entry_long(&self, entry: &DirectoryEntry) {
+        if self.show_node_ids {
+            print!("{} ", entry.node.id.as_u64());
+        }
+
+        if self.show_mounts {
+            print!("{} ", mount_label(entry));
+        }
+
+        let meta = entry.node.metadata.lock();
+
+        let size = if self.human_readable {
+            human_bytes(meta.size).to_string()
+        } else {
+            meta.size.to_string()
+        };
+
+        print!(
+            "{}{}@ 1 root root {:>4} {} {}",
+            entry.node.kind,
+            Permissions(meta.mode),
+            size,
+            DateTime::from_timestamp(meta.modified_at),
+            entry.name
+        );
+
+        if entry.node.kind == FsNodeKind::Symlink {
+            match vfs::get().read_link(&entry.path()) {
+                Ok(target) => print!(" -> {}", target),
+                Err(_) => print!(" -> ?"),
+            }
+        }
+
+        println!();
+    }
+
+    /// Prints the entries of a directory. In recursive mode, the subdirectories
+    /// are listed afterwards, each under a header with its path. The path
+    /// which is shown is built from the one the user gave rather than the
+    /// canonical one. Breaks if the user cancelled with Ctrl-C.
+    fn print_directory(&self, directory: &Path, shown_path: &str, depth: usize) -> ControlFlow<()> {
+        if keyboard::take_interrupt() {
+            return ControlFlow::Break(());
+        }
+
+        let entries = match vfs::get().read_directory(&directory.to_string()) {
+            Ok(entries) => entries,
+            Err(e) => {
+                println!("ls: {}: {}", shown_path, e);
+                return ControlFlow::Continue(());
+            }
+        };
+
+        // The metadata of the children is only needed by the long format and
+        // for sorting, so they are not looked up otherwise. Links are listed
+        // themselves rather than the entries they point to.
+        let needs_lookup = self.long || self.sort_by_time || self.sort_by_size;
+
+        let mut children = entries
+            .into_iter()
+            .filter(|child| self.all || !child.name.starts_with('.'))
+            .map(|child| {
+                // An entry which was removed since the directory was read
+                // is listed by name only
+                let entry = needs_lookup
+                    .then(|| {
+                        vfs::get()
+                            .lstat(&directory.join(&child.name).to_string())
+                            .ok()
+                    })
+                    .flatten();
+
+                (child, entry)
+            })
+            .collect::<Vec<_>>();
+
+        // Entries come out of the directory sorted by name. The sorts are
+        // stable, so ties stay in name order.
+        if self.sort_by_time {
+            children.sort_by_key(|(_, entry)| {
+                Reverse(
+                    entry
+                        .as_ref()
+                        .map(|entry| entry.node.metadata.lock().modified_at),
+                )
+            });
+        } else if self.sort_by_size {
+            children.sort_by_key(|(_, entry)| {
+                Reverse(entry.as_ref().map(|entry| entry.node.metadata.lock().size))
+            });
+        }
+
+        if self.reverse {
+            children.reverse();
+        }
+
+        for (child, entry) in &children {
+            match entry {
+                Some(entry) if self.long => self.print_entry_long(entry),
+                _ => self.print_entry_short(directory, child),
+            }
+        }
+
+        if !self.recursive {
+            return ControlFlow::Continue(());
+        }
+
+        // Mount points show up as directories, so they're descended into as
+        // well. Links to directories are not, since they could form a loop.
+        for (child, _) in children
+            .iter()
+            .filter(|(c, _)| c.kind == FsNodeKind::Directory)
+        {
+            let child_path = format!("{}/{}", shown_path.trim_end_matches('/'), child.name);
+
+            println!();
+            println!("{}:", child_path);
+
+            if depth >= MAX_LISTING_DEPTH {
+                println!("ls: {}: too many levels of directories", child_path);
+                continue;
+            }
+
+            self.print_directory(&directory.join(&child.name), &child_path, depth + 1)?;
+        }
+
+        ControlFlow::Continue(())
+    }
+}
+
+/// The state of a `tree` listing
+struct TreeWalk {
+    /// How many levels of directories to descend into, or None to show the
+    /// whole tree
+    max_depth: Option<usize>,
+    directories: usize,
+    files: usize,
+}
+
+impl TreeWalk {
+    /// Prints the entries of a directory and recurses into its subdirectories.
+    /// Each line starts with the prefix, which draws the branches of the parent
+    /// directories. Breaks if the user cancelled with Ctrl-C.
+    fn print_directory(&mut self, directory: &Path, prefix: &str, depth: usize) -> ControlFlow<()> {
+        if keyboard::take_interrupt() {
+            return ControlFlow::Break(());
+        }
+
+        let entries = match vfs::get().read_directory(&directory.to_string()) {
+            Ok(entries) => entries,
+            Err(e) => {
+                println!("{}└── [{}]", prefix, e);
+                return ControlFlow::Continue(());
+            }
+        };
+
+        // The entries are sorted by name, and the sort is stable, so this
+        // moves the directories to the front while keeping both groups sorted.
+        // Mount points show up as directories, so they're descended into as
+        // well.
+        let mut entries = entries.into_iter().collect::<Vec<_>>();
+        entries.sort_by_key(|e| e.kind != FsNodeKind::Directory);
+
+        for (i, entry) in entries.iter().enumerate() {
+            let is_last = i == entries.len() - 1;
+
+            print!("{}{}", prefix, if is_last { "└── " } else { "├── " });
+            vga::with_color(entry.kind.color_code(), || println!("{}", entry.name));
+
+            if entry.kind != FsNodeKind::Directory {
+                self.files += 1;
+                continue;
+            }
+
+            self.directories += 1;
+
+            if self.max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+
+            // Continue the branch of this entry past its children unless it
+            // was the last one
+            let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+
+            self.print_directory(&directory.join(&entry.name), &child_prefix, depth + 1)?;
+        }
+
+        ControlFlow::Continue(())
+    }
+}
+
+/// The tests an entry has to pass to be printed by `find`
+struct FindQuery<'a> {
+    /// A glob which the name of the entry has to match
+    name: Option<&'a str>,
+    kind: Option<FsNodeKind>,
+}
+
+impl FindQuery<'_> {
+    fn matches(&self, name: &str, kind: FsNodeKind) -> bool {
+        self.name.is_none_or(|pattern| glob_matches(pattern, name))
+            && self.kind.is_none_or(|k| k == kind)
+    }
+}
+
+/// Calls `found` with the path of every entry at or below the given path which
+/// matches the query. Breaks if the user cancelled with Ctrl-C.
+///
+/// NOTE: symbolic links below the starting path are matched as links and never
+/// descended into, otherwise a link to one of its parents would recurse
+/// forever.
+fn find_entries(
+    path: &Path,
+    name: &str,
+    kind: FsNodeKind,
+    query: &FindQuery,
+    found: &mut impl FnMut(&Path),
+) -> ControlFlow<()> {
+    if keyboard::take_interrupt() {
+        return ControlFlow::Break(());
+    }
+
+    if query.matches(name, kind) {
+        found(path);
+    }
+
+    if kind != FsNodeKind::Directory {
+        return ControlFlow::Continue(());
+    }
+
+    let entries = match vfs::get().read_directory(&path.to_string()) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("find: {}: {}", path, e);
+            return ControlFlow::Continue(());
+        }
+    };
+
+    for entry in entries {
+        find_entries(
+            &path.join(&entry.name),
+            &entry.name,
+            entry.kind,
+            query,
+            found,
+        )?;
+    }
+
+    ControlFlow::Continue(())
+}
+
+/// Prints every line of a file which contains the pattern. The pattern is
+/// matched literally, ignoring ASCII case if requested.
+fn grep_file(
+    path: &str,
+    pattern: &str,
+    line_numbers: bool,
+    ignore_case: bool,
+) -> Result<(), IoError> {
+    let fd = vfs::get().open(path, FileMode::Read)?;
+    let _close = defer_handle!({
+        let _ = vfs::get().close(fd);
+    });
+
+    let normalize = |line: &[u8]| -> Vec<u8> {
+        if ignore_case {
+            line.to_ascii_lowercase()
+        } else {
+            line.to_vec()
+        }
+    };
+
+    let pattern = normalize(pattern.as_bytes());
+
+    // The reader reassembles lines which are split between chunks
+    let mut reader = ByteReader::new(fd);
+    let mut line_number = 0;
+
+    while let Some(line) = reader.next_line()? {
+        // Devices can be infinite, so give the user a way out
+        if keyboard::take_interrupt() {
+            println!("^C");
+            break;
+        }
+
+        line_number += 1;
+
+        let haystack = normalize(&line);
+
+        let matches = pattern.is_empty() || haystack.windows(pattern.len()).any(|w| w == pattern);
+
+        if !matches {
+            continue;
+        }
+
+        if line_numbers {
+            vga::with_color(Color::Green, || print!("{}:", line_number));
+        }
+
+        println!("{}", String::from_utf8_lossy(&line));
+    }
+
+    Ok(())
+}
+
+/// The counts reported by `wc`
+#[derive(Debug, Default, Clone, Copy)]
+struct WordCount {
+    lines: usize,
+    words: usize,
+    bytes: usize,
+}
+
+/// Counts the newlines, whitespace separated words and bytes in a file. Returns
+/// None if the user cancelled with Ctrl-C.
+fn count_words(path: &str) -> Result<Option<WordCount>, IoError> {
+    let fd = vfs::get().open(path, FileMode::Read)?;
+    let _close = defer_handle!({
+        let _ = vfs::get().close(fd);
+    });
+
+    let mut reader = ByteReader::new(fd);
+    let mut count = WordCount::default();
+
+    // Tracked across reads so that words split between two chunks are only
+    // counted once
+    let mut in_word = false;
+
+    while let Some(byte) = reader.next_byte()? {
+        // Devices can be infinite, so give the user a way out
+        if count.bytes % 512 == 0 && keyboard::take_interrupt() {
+            return Ok(None);
+        }
+
+        count.bytes += 1;
+
+        if byte == b'\n' {
+            count.lines += 1;
+        }
+
+        if byte.is_ascii_whitespace() {
+            in_word = false;
+        } else if !in_word {
+            in_word = true;
+            count.words += 1;
+        }
+    }
+
+    Ok(Some(count))
+}
+
+/// Prints the last `count` lines of a file. The whole file has to be read since
+/// there's no way to find where the last lines start, so only the most recent
+/// lines are kept around while reading.
+fn print_tail(path: &str, count: usize) -> Result<(), IoError> {
+    let fd = vfs::get().open(path, FileMode::Read)?;
+    let _close = defer_handle!({
+        let _ = vfs::get().close(fd);
+    });
+
+    let mut reader = ByteReader::new(fd);
+    let mut lines = VecDeque::new();
+
+    while let Some(line) = reader.next_line()? {
+        // Devices can be infinite, so give the user a way out
+        if keyboard::take_interrupt() {
+            println!("^C");
+            return Ok(());
+        }
+
+        if lines.len() == count {
+            lines.pop_front();
+        }
+
+        if count > 0 {
+            lines.push_back(line);
+        }
+    }
+
+    for line in lines {
+        println!("{}", String::from_utf8_lossy(&line));
+    }
+
+    Ok(())
+}
+
+/// Compares two files byte by byte and reports the first difference. Returns
+/// true if the files are identical.
+fn compare_files(path_a: &str, path_b: &str) -> Result<bool, IoError> {
+    let fd_a = vfs::get().open(path_a, FileMode::Read)?;
+    let _close_a = defer_handle!({
+        let _ = vfs::get().close(fd_a);
+    });
+
+    let fd_b = vfs::get().open(path_b, FileMode::Read)?;
+    let _close_b = defer_handle!({
+        let _ = vfs::get().close(fd_b);
+    });
+
+    let mut a = ByteReader::new(fd_a);
+    let mut b = ByteReader::new(fd_b);
+
+    let mut offset = 0;
+    let mut line = 1;
+
+    loop {
+        // Devices can be infinite, so give the user a way out
+        if keyboard::take_interrupt() {
+            println!("^C");
+            return Ok(false);
+        }
+
+        match (a.next_byte()?, b.next_byte()?) {
+            (None, None) => return Ok(true),
+            (Some(_), None) => {
+                println!("cmp: EOF on {} after byte {}", path_b, offset);
+                return Ok(false);
+            }
+            (None, Some(_)) => {
+                println!("cmp: EOF on {} after byte {}", path_a, offset);
+                return Ok(false);
+            }
+            (Some(x), Some(y)) if x != y => {
+                println!(
+                    "{} {} differ: byte {}, line {}",
+                    path_a,
+                    path_b,
+                    offset + 1,
+                    line
+                );
+                return Ok(false);
+            }
+            (Some(x), Some(_)) => {
+                offset += 1;
+
+                if x == b'\n' {
+                    line += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Describes the mount which contains the provided entry as the name of the
+/// file system type and the mount id (i.e. `ramfs:0`)
+fn mount_label(entry: &DirectoryEntry) -> String {
+    let fs = entry.node.file_system();
+    let metadata = fs.metadata();
+
+    let mut label = format!(
+        "{}:{}",
+        metadata.file_system_type.metadata().name,
+        entry.node.mount_id.as_u64()
+    );
+
+    if let Some(device) = metadata.device {
+        label.push_str(&format!("@{}", device));
+    }
+
+    label
+}
+
+/// Reads the entire contents of a regular file into memory
+fn read_file(path: &str) -> Result<Vec<u8>, IoError> {
+    // Devices can be infinite, so only regular files are read in full
+    if vfs::get().stat(path)?.node.kind != FsNodeKind::File {
+        return Err(IoError::NotAFile);
+    }
+
+    let fd = vfs::get().open(path, FileMode::Read)?;
+    let mut reader = ByteReader::new(fd);
+
+    let mut contents = Vec::new();
+    let result = loop {
+        match reader.next_byte() {
+            Ok(Some(byte)) => contents.push(byte),
+            Ok(None) => break Ok(contents),
+            Err(e) => break Err(e),
+        }
+    };
+
+    vfs::get().close(fd)?;
+    result
+}
+
+/// Prints the lines which differ between two files. This is a positional
+/// comparison rather than a real diff algorithm, so a single inserted line will
+/// show every following line as changed. Returns true if no lines differ.
+fn diff_files(path_a: &str, path_b: &str) -> Result<bool, IoError> {
+    let a = read_file(path_a)?;
+    let b = read_file(path_b)?;
+
+    let a = String::from_utf8_lossy(&a);
+    let b = String::from_utf8_lossy(&b);
+
+    let lines_a = a.lines().collect::<Vec<_>>();
+    let lines_b = b.lines().collect::<Vec<_>>();
+
+    for i in 0..lines_a.len().max(lines_b.len()) {
+        match (lines_a.get(i), lines_b.get(i)) {
+            (Some(x), Some(y)) if x == y => {}
+            (Some(x), Some(y)) => {
+                println!("{}c{}", i + 1, i + 1);
+                println!("< {}", x);
+                println!("---");
+                println!("> {}", y);
+            }
+            (Some(x), None) => {
+                println!("{}d{}", i + 1, lines_b.len());
+                println!("< {}", x);
+            }
+            (None, Some(y)) => {
+                println!("{}a{}", lines_a.len(), i + 1);
+                println!("> {}", y);
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+
+    Ok(lines_a == lines_b)
+}
+
+/// Evaluates the expression passed to the `test` builtin. Only negation and the
+/// file type checks (`-e`, `-f` and `-d`) are supported.
+fn evaluate_test(args: &[&str]) -> Result<bool, String> {
+    match args {
+        [] => Ok(false),
+        ["!", rest @ ..] => evaluate_test(rest).map(|result| !result),
+        [s] => Ok(!s.is_empty()),
+        [op @ ("-e" | "-f" | "-d"), path] => {
+            let result = match *op {
+                "-e" => vfs::get().exists(path),
+                "-f" => vfs::get().is_file(path),
+                _ => vfs::get().is_dir(path),
+            };
+
+            result.map_err(|e| format!("{}: {}", path, e))
+        }
+        [op, _] => Err(format!("{}: unary operator expected", op)),
+        _ => Err("too many arguments".into()),
+    }
+}
+
+/// Replaces the backslash escapes `\n`, `\t`, `\\` and `\xHH` with the bytes
+/// they stand for. Anything else following a backslash, including a `\x`
+/// without two hex digits, is left as is.
+///
+/// The result is bytes rather than text since `\xHH` can produce any byte, so
+/// a character can be spelled out as its UTF-8 encoding (`\xc3\xa9` is "é").
+fn expand_escapes(text: &str) -> Vec<u8> {
+    let mut expanded = Vec::new();
+    // Every escape is ASCII, so the text can be scanned byte by byte without
+    // splitting the characters in between
+    let mut bytes = text.as_bytes().iter();
+
+    while let Some(&b) = bytes.next() {
+        if b != b'\\' {
+            expanded.push(b);
+            continue;
+        }
+
+        let escaped = match bytes.clone().next() {
+            Some(b'n') => b'\n',
+            Some(b't') => b'\t',
+            Some(b'\\') => b'\\',
+            Some(b'x') => match bytes
+                .as_slice()
+                .get(1..3)
+                .filter(|d| d.iter().all(u8::is_ascii_hexdigit))
+            {
+                Some(digits) => {
+                    let byte = u8::from_str_radix(core::str::from_utf8(digits).unwrap(), 16);
+
+                    // Skip the `x` and the first digit, the second one is
+                    // skipped below
+                    bytes.nth(1);
+                    byte.unwrap()
+                }
+                _ => {
+                    expanded.push(b);
+                    continue;
+                }
+            },
+            // Unknown escapes keep their backslash
+            _ => {
+                expanded.push(b);
+                continue;
+            }
+        };
+
+        expanded.push(escaped);
+        bytes.next();
+    }
+
+    expanded
+}
+
+/// Guesses whether the provided data is binary rather than text. Text may
+/// contain whitespace and escape sequences but any other control characters
+/// (especially NUL bytes) are a strong sign of binary data.
+fn looks_binary(data: &[u8]) -> bool {
+    data.iter().any(|&b| {
+        b == 0x7f || (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x08 | 0x0c | 0x1b))
+    })
+}
+
+/// Matches a name against a glob pattern, where `*` matches any number of
+/// characters and `?` matches exactly one
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    let (mut p, mut n) = (0, 0);
+
+    // The position of the most recent `*` and how much of the name it has
+    // matched so far, so that it can be extended if the rest doesn't match
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => {
+                let Some((star, matched)) = backtrack else {
+                    return false;
+                };
+
+                backtrack = Some((star, matched + 1));
+                p = star + 1;
+                n = matched + 1;
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
 }
 
-fn without_flags<'a>(args: &[&'a str]) -> Vec<&'a str> {
-    args.iter()
-        .filter(|a| !a.starts_with("-"))
-        .cloned()
-        .collect()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch_directory;
+
+    /// Creates a file with the given contents in the scratch directory and
+    /// returns its path
+    fn write_file(scratch: &str, name: &str, contents: &[u8]) -> String {
+        let path = format!("{}/{}", scratch, name);
+        let fd = vfs::get().open(&path, FileMode::Write).unwrap();
+        vfs::get().write(fd, contents).unwrap();
+        vfs::get().close(fd).unwrap();
+
+        path
+    }
+
+    #[test_case]
+    fn compare_files_finds_differences() {
+        let scratch = scratch_directory("compare_files");
+        let a = write_file(&scratch, "a", b"one\ntwo\n");
+        let same = write_file(&scratch, "same", b"one\ntwo\n");
+        let changed = write_file(&scratch, "changed", b"one\nTwo\n");
+        let shorter = write_file(&scratch, "shorter", b"one\n");
+
+        assert_eq!(compare_files(&a, &same), Ok(true));
+        assert_eq!(compare_files(&a, &changed), Ok(false));
+        assert_eq!(compare_files(&a, &shorter), Ok(false));
+        assert_eq!(compare_files(&shorter, &a), Ok(false));
+        assert_eq!(
+            compare_files(&a, &format!("{}/missing", scratch)),
+            Err(IoError::EntryNotFound)
+        );
+    }
+
+    #[test_case]
+    fn diff_files_finds_differences() {
+        let scratch = scratch_directory("diff_files");
+        let a = write_file(&scratch, "a", b"one\ntwo\n");
+        let same = write_file(&scratch, "same", b"one\ntwo\n");
+        let changed = write_file(&scratch, "changed", b"one\nTwo\n");
+        let longer = write_file(&scratch, "longer", b"one\ntwo\nthree\n");
+
+        assert_eq!(diff_files(&a, &same), Ok(true));
+        assert_eq!(diff_files(&a, &changed), Ok(false));
+        assert_eq!(diff_files(&a, &longer), Ok(false));
+        assert_eq!(diff_files(&longer, &a), Ok(false));
+    }
+
+    #[test_case]
+    fn files_with_nul_bytes_look_binary() {
+        let scratch = scratch_directory("looks_binary");
+        let binary = write_file(&scratch, "binary", b"\x7fELF\x02\x01\x01\0\0\0");
+        let text = write_file(&scratch, "text", b"one\ttwo\r\n\x1b[1mbold\x1b[0m\n");
+
+        let mut data = [0u8; 64];
+        for (path, binary) in [(&binary, true), (&text, false)] {
+            let fd = vfs::get().open(path, FileMode::Read).unwrap();
+            let n = vfs::get().read(fd, &mut data).unwrap();
+            vfs::get().close(fd).unwrap();
+
+            assert_eq!(looks_binary(&data[..n]), binary);
+        }
+
+        assert!(looks_binary(b"text\0"));
+        assert!(!looks_binary("café\n".as_bytes()));
+    }
+
+    #[test_case]
+    fn glob_matches_wildcards() {
+        assert!(glob_matches("*.txt", "notes.txt"));
+        assert!(glob_matches("*.txt", ".txt"));
+        assert!(glob_matches("a?c", "abc"));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("caf?", "café"));
+
+        assert!(!glob_matches("*.txt", "notes.txt.bak"));
+        assert!(!glob_matches("a?c", "ac"));
+        assert!(!glob_matches("abc", "abcd"));
+        assert!(!glob_matches("?", ""));
+    }
+
+    #[test_case]
+    fn find_entries_matches_nested_entries() {
+        let root = format!("{}/mnt", scratch_directory("find"));
+        vfs::get()
+            .mount(
+                "",
+                &root,
+                Some("ramfs"),
+                MountFlags::READ | MountFlags::WRITE,
+            )
+            .unwrap();
+
+        vfs::get().create_directory(&format!("{}/a", root)).unwrap();
+        vfs::get()
+            .create_directory(&format!("{}/a/b", root))
+            .unwrap();
+        for name in ["top.txt", "a/one.txt", "a/two.rs", "a/b/three.txt"] {
+            write_file(&root, name, b"");
+        }
+
+        let find = |name: Option<&str>, kind: Option<FsNodeKind>| {
+            let query = FindQuery { name, kind };
+            let start = Path::from_str(&root).unwrap();
+
+            let mut found = Vec::new();
+            let result = find_entries(
+                &start,
+                "mnt",
+                FsNodeKind::Directory,
+                &query,
+                &mut |path: &Path| found.push(path.to_string()[root.len()..].to_string()),
+            );
+            assert!(result.is_continue());
+
+            found.sort();
+            found
+        };
+
+        assert_eq!(
+            find(Some("*.txt"), None),
+            ["/a/b/three.txt", "/a/one.txt", "/top.txt"]
+        );
+        assert_eq!(
+            find(Some("t*"), Some(FsNodeKind::File)),
+            ["/a/b/three.txt", "/a/two.rs", "/top.txt"]
+        );
+        assert_eq!(find(Some("b"), Some(FsNodeKind::Directory)), ["/a/b"]);
+        // The starting point is matched too
+        assert_eq!(find(None, Some(FsNodeKind::Directory)), ["", "/a", "/a/b"]);
+        assert!(find(Some("*.md"), None).is_empty());
+
+        vfs::get().unmount(&root).unwrap();
+    }
+
+    #[test_case]
+    fn input_column_counts_characters() {
+        assert_eq!(input_column("", 0), 0);
+        assert_eq!(input_column("ls", 2), 2);
+        assert_eq!(input_column("echo café", 8), 8);
+        assert_eq!(input_column("echo café", "echo café".len()), 9);
+    }
+
+    #[test_case]
+    fn expand_escapes_replaces_known_escapes() {
+        assert_eq!(expand_escapes("a\\nb\\tc\\\\d"), b"a\nb\tc\\d");
+        assert_eq!(expand_escapes("\\x41\\x7a!"), b"Az!");
+        assert_eq!(expand_escapes("café"), "café".as_bytes());
+    }
+
+    #[test_case]
+    fn expand_escapes_produces_raw_bytes() {
+        assert_eq!(expand_escapes("\\xff\\x00"), [0xff, 0x00]);
+        assert_eq!(expand_escapes("\\xc3\\xa9"), "é".as_bytes());
+    }
+
+    #[test_case]
+    fn expand_escapes_keeps_unknown_escapes() {
+        assert_eq!(expand_escapes("\\q"), b"\\q");
+        assert_eq!(expand_escapes("\\x4"), b"\\x4");
+        assert_eq!(expand_escapes("\\xg1"), b"\\xg1");
+        assert_eq!(expand_escapes("\\x+f"), b"\\x+f");
+        assert_eq!(expand_escapes("end\\"), b"end\\");
+    }
+
+    #[test_case]
+    fn utf8_decoder_joins_split_characters() {
+        let mut decoder = Utf8Decoder::default();
+        let text = "é€".as_bytes();
+
+        assert_eq!(decoder.decode(&text[..1]), "");
+        assert_eq!(decoder.decode(&text[1..3]), "é");
+        assert_eq!(decoder.decode(&text[3..]), "€");
+        assert_eq!(decoder.finish(), "");
+    }
+
+    #[test_case]
+    fn utf8_decoder_replaces_invalid_bytes() {
+        let mut decoder = Utf8Decoder::default();
+
+        assert_eq!(decoder.decode(b"a\xffb"), "a\u{FFFD}b");
+        // A lead byte followed by something other than a continuation byte
+        assert_eq!(decoder.decode(b"\xc3"), "");
+        assert_eq!(decoder.decode(b"x"), "\u{FFFD}x");
+
+        // A character cut short by the end of the input
+        assert_eq!(decoder.decode(b"\xe2\x82"), "");
+        assert_eq!(decoder.finish(), "\u{FFFD}");
+    }
 }
@@ -1,117 +1,140 @@
-use core::str;
+use alloc::string::String;
+use core::{iter::Peekable, str::Chars};
 
 pub struct Parser<'source> {
-    input: &'source [u8],
-    position: usize,
+    input: Peekable<Chars<'source>>,
+}
+
+/// A single token of shell input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// A word with quotes removed and escape sequences resolved. Since escapes
+    /// and quotes collapse characters, this can't borrow from the input.
+    Word(String),
+    /// A `;` which separates two commands
+    Separator,
 }
 
 impl<'source> Parser<'source> {
+    /// Creates a parser over the given input. Only ASCII characters have a
+    /// special meaning, anything else is passed through as part of a word.
     pub fn new(input: &'source str) -> Self {
-        assert!(input.is_ascii(), "parser input contained non-ascii text");
-
         Self {
-            input: input.as_bytes(),
-            position: 0,
+            input: input.chars().peekable(),
         }
     }
 }
 
-impl<'source> Iterator for Parser<'source> {
-    type Item = &'source str;
+impl Iterator for Parser<'_> {
+    type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut start = self.position;
-        let mut parsing_string = false;
-
-        for char in self.input[self.position..].iter() {
-            match *char {
-                // Start or end of string
-                b'"' => {
-                    // If we are not already parsing a string, move the start up
-                    // by one to point to the inside of the string
-                    if !parsing_string {
-                        // If we already have some characters, return that so on
-                        // the next iteration we start right on the string
-                        if self.position > start {
-                            // SAFETY: we know this string slice is valid and has
-                            // the same lifetime as the input since it points
-                            // directly into the input buffer
-                            return Some(unsafe {
-                                str::from_raw_parts(
-                                    self.input.as_ptr().add(start),
-                                    self.position - start,
-                                )
-                            });
-                        }
-
-                        start += 1;
-                        parsing_string = true;
-
-                        self.position += 1;
-                        continue;
-                    }
-
-                    // Otherwise, this is the end of a string, so we need to
-                    // return the slice from the beginning of the string to the
-                    // current char and then move the position up
-
-                    // SAFETY: we know this string slice is valid and has
-                    // the same lifetime as the input since it points
-                    // directly into the input buffer
-                    let ret = unsafe {
-                        str::from_raw_parts(self.input.as_ptr().add(start), self.position - start)
-                    };
-                    self.position += 1;
-
-                    return Some(ret);
-                }
-                // Whitespace
-                b' ' | b'\t' => {
-                    // If we are in the middle of parsing a string, just munch
-                    // the space.
-                    if parsing_string {
-                        self.position += 1;
-                        continue;
-                    }
-
-                    // Otherwise, this is the end of a token so we need to
-                    // return the string slice if there is anything in it.
-
-                    // SAFETY: we know this string slice is valid and has
-                    // the same lifetime as the input since it points
-                    // directly into the input buffer
-                    let ret = unsafe {
-                        str::from_raw_parts(self.input.as_ptr().add(start), self.position - start)
-                    };
-                    self.position += 1;
-
-                    if !ret.is_empty() {
-                        return Some(ret);
-                    } else {
-                        start = self.position;
-                        continue;
-                    }
-                }
-                // Any other character
-                _ => {
-                    // Munch the character
-                    self.position += 1;
-                }
-            }
+        // Skip any whitespace before the start of the next word
+        while self.input.next_if(|c| matches!(c, ' ' | '\t')).is_some() {}
+
+        // We reached the end of the input and there are no more words
+        self.input.peek()?;
+
+        // Separators are their own token, even without whitespace around them
+        if self.input.next_if_eq(&';').is_some() {
+            return Some(Token::Separator);
         }
 
-        // We reached the end of the input. If we have any remaining characters,
-        // return the buffer. Otherwise return none.
-
-        if self.position > start {
-            // SAFETY: we know this string slice is valid and has
-            // the same lifetime as the input since it points
-            // directly into the input buffer
-            Some(unsafe {
-                str::from_raw_parts(self.input.as_ptr().add(start), self.position - start)
-            })
-        } else {
-            None
+        // Words are assembled character by character since quoted and unquoted
+        // runs which are not separated by whitespace join into a single word
+        // (i.e. `pre"mid"post` is the word `premidpost`).
+        let mut word = String::new();
+        // The quote character of the string we are currently in, if any
+        let mut quote = None;
+
+        // An unquoted separator ends the word but is left for the next token
+        while let Some(char) = self.input.next_if(|&c| quote.is_some() || c != ';') {
+            match (quote, char) {
+                // Outside of strings a backslash makes the next character
+                // literal (i.e. `a\ b` is the single word `a b`). A trailing
+                // backslash is kept as is.
+                (None, '\\') => word.push(self.input.next().unwrap_or('\\')),
+                // Within double quotes only quotes and backslashes can be
+                // escaped. Any other backslash is literal.
+                (Some('"'), '\\') => match self.input.next_if(|c| matches!(c, '"' | '\\')) {
+                    Some(escaped) => word.push(escaped),
+                    None => word.push('\\'),
+                },
+                // Start of a string. Either kind of quote may be used and the
+                // other kind has no special meaning inside of it. The quote
+                // itself is not part of the word.
+                (None, '"' | '\'') => quote = Some(char),
+                // End of the current string
+                (Some(q), _) if q == char => quote = None,
+                // Whitespace ends the word unless we are in the middle of
+                // parsing a string
+                (None, ' ' | '\t') => break,
+                // Any other character (including whitespace within a string)
+                _ => word.push(char),
+            }
         }
+
+        // NOTE: an unterminated string just runs to the end of the input
+        Some(Token::Word(word))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token> {
+        Parser::new(input).collect()
+    }
+
+    fn word(text: &str) -> Token {
+        Token::Word(text.into())
+    }
+
+    #[test_case]
+    fn non_ascii_input_does_not_panic() {
+        // Characters of every UTF-8 length, including where the parser looks
+        // for delimiters and quotes
+        assert_eq!(
+            tokens("ü\u{20AC}\u{1F600};\"\u{A0}\"'ß'\\é"),
+            [
+                word("ü\u{20AC}\u{1F600}"),
+                Token::Separator,
+                word("\u{A0}ßé")
+            ]
+        );
+    }
+
+    #[test_case]
+    fn joins_adjacent_quoted_runs() {
+        assert_eq!(tokens("a\"b\"c"), [word("abc")]);
+        assert_eq!(tokens("pre\"mid\"post"), [word("premidpost")]);
+        assert_eq!(tokens("'a b'\"c d\"e"), [word("a bc de")]);
+    }
+
+    #[test_case]
+    fn empty_quotes_are_an_empty_word() {
+        assert_eq!(tokens("\"\""), [word("")]);
+        assert_eq!(tokens("echo '' x"), [word("echo"), word(""), word("x")]);
+        assert_eq!(tokens("a\"\"b"), [word("ab")]);
+    }
+
+    #[test_case]
+    fn quotes_keep_separators() {
+        assert_eq!(
+            tokens("\"a;b\";c"),
+            [word("a;b"), Token::Separator, word("c")]
+        );
+    }
+
+    #[test_case]
+    fn non_ascii_words_round_trip() {
+        assert_eq!(tokens("echo café"), [word("echo"), word("café")]);
+        assert_eq!(
+            tokens("echo 'naïve café'"),
+            [word("echo"), word("naïve café")]
+        );
     }
 }
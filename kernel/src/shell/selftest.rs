@@ -0,0 +1,96 @@
+//! Runs the built-in commands with paths which can't be resolved to make sure
+//! that they report the error rather than panicking
+//!
+//! The kernel can't run tests on its own yet, so this is started from the
+//! shell with `selftest`. A panic halts the kernel, so getting back to the
+//! prompt means that every command handled every path.
+//!
+//! The commands are run non-interactively (see [`INTERACTIVE`]), like a script
+//! would run them. Afterwards, the commands which report an exit status are
+//! checked to report the right one.
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::sync::atomic::Ordering;
+
+use super::{COMMANDS, INTERACTIVE, LAST_STATUS, execute};
+use crate::{fs::path::MAX_NAME_LENGTH, vga::println};
+
+/// Commands which don't return to the shell, would wipe the output or take no
+/// paths at all
+const SKIPPED: &[&str] = &[
+    "exit", "halt", "reboot", "shutdown", "usertest", "selftest", "clear", "reset",
+];
+
+/// Paths which none of the commands can resolve or create. Each of them fails
+/// in a different way.
+fn bad_paths() -> [String; 4] {
+    [
+        // A parent which doesn't exist, which also keeps commands like
+        // `mkdir` and `touch` from creating anything
+        "/nonexistent/child".to_string(),
+        // A file used as a directory
+        "/dev/null/child".to_string(),
+        // A name which is longer than any file system allows
+        "a".repeat(MAX_NAME_LENGTH + 1),
+        String::new(),
+    ]
+}
+
+/// Commands along with the exit status they must leave in [`LAST_STATUS`]
+const STATUS_CHECKS: &[(&[&str], u8)] = &[
+    (&["cmp", "/dev/null", "/dev/null"], 0),
+    (&["cmp", "/proc/version", "/proc/version"], 0),
+    (&["cmp", "/proc/version", "/dev/null"], 1),
+    (&["cmp", "/nonexistent", "/dev/null"], 2),
+    (&["diff", "/proc/version", "/proc/version"], 0),
+    (&["diff", "/proc/version", "/dev/null"], 1),
+    (&["diff", "/proc/version"], 2),
+];
+
+/// Runs every command with each bad path as its only operand and then as both
+/// of two operands (for commands like `cp` and `mv`)
+pub async fn run() {
+    let mut runs = 0;
+
+    INTERACTIVE.store(false, Ordering::Relaxed);
+
+    for command in COMMANDS.iter().filter(|c| !SKIPPED.contains(&c.name)) {
+        for path in bad_paths() {
+            let one = vec![command.name.to_string(), path.clone()];
+            let two = vec![command.name.to_string(), path.clone(), path];
+
+            for words in [one, two] {
+                println!("$ {}", words.join(" "));
+
+                // `execute` runs this, so the recursion has to be boxed
+                let exited = Box::pin(execute(&words)).await;
+                assert!(!exited, "{} exited the shell", command.name);
+
+                runs += 1;
+            }
+        }
+    }
+
+    for (words, expected) in STATUS_CHECKS {
+        println!("$ {}", words.join(" "));
+
+        let words = words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        Box::pin(execute(&words)).await;
+
+        let status = LAST_STATUS.load(Ordering::Relaxed);
+        assert_eq!(status, *expected, "{} reported the wrong status", words[0]);
+    }
+
+    INTERACTIVE.store(true, Ordering::Relaxed);
+
+    println!(
+        "selftest: {} runs, no command panicked, {} statuses checked",
+        runs,
+        STATUS_CHECKS.len()
+    );
+}
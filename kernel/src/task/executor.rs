@@ -1,10 +1,48 @@
-use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
-use core::task::{Context, Poll, Waker};
+use alloc::{collections::BTreeMap, sync::Arc, task::Wake, vec::Vec};
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
 
 use crossbeam_queue::ArrayQueue;
+use spin::Mutex;
 
 use super::{Task, TaskId};
 
+/// The number of times a task has been polled since boot
+static POLLS: AtomicU64 = AtomicU64::new(0);
+/// The number of times the executor halted the CPU because no tasks were ready
+static HALTS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of times a task has been polled since boot
+pub fn polls() -> u64 {
+    POLLS.load(Ordering::Relaxed)
+}
+
+/// Returns the number of times the executor has gone idle since boot. Each
+/// halt lasts until the next interrupt, so this should grow by roughly one per
+/// timer tick or keypress while nothing else is running.
+pub fn halts() -> u64 {
+    HALTS.load(Ordering::Relaxed)
+}
+
+/// Information about a task which has been spawned and hasn't completed yet
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: Option<&'static str>,
+}
+
+/// Every task which is currently alive. This is kept separate from the
+/// executor so that it can be read by running tasks, and is only locked while
+/// spawning or removing a task so that reading it never holds up polling.
+static LIVE_TASKS: Mutex<BTreeMap<TaskId, TaskInfo>> = Mutex::new(BTreeMap::new());
+
+/// Returns a snapshot of all tasks which are currently alive, ordered by ID
+pub fn tasks() -> Vec<TaskInfo> {
+    LIVE_TASKS.lock().values().cloned().collect()
+}
+
 pub struct Executor {
     tasks: BTreeMap<TaskId, Task>,
     task_queue: Arc<ArrayQueue<TaskId>>,
@@ -22,9 +60,14 @@ impl Executor {
 
     pub fn spawn(&mut self, task: Task) {
         let task_id = task.id;
+        let info = TaskInfo {
+            id: task.id,
+            name: task.name,
+        };
         if self.tasks.insert(task.id, task).is_some() {
             panic!("task with same ID already in tasks");
         }
+        LIVE_TASKS.lock().insert(task_id, info);
         self.task_queue.push(task_id).expect("queue full");
     }
 
@@ -45,11 +88,13 @@ impl Executor {
                 .entry(task_id)
                 .or_insert_with(|| TaskWaker::create(task_id, task_queue.clone()));
             let mut context = Context::from_waker(waker);
+            POLLS.fetch_add(1, Ordering::Relaxed);
             match task.poll(&mut context) {
                 Poll::Ready(()) => {
                     // task done -> remove it and its cached waker
                     tasks.remove(&task_id);
                     waker_cache.remove(&task_id);
+                    LIVE_TASKS.lock().remove(&task_id);
                 }
                 Poll::Pending => {}
             }
@@ -63,10 +108,18 @@ impl Executor {
         }
     }
 
+    /// Halts the CPU until the next interrupt if there are no tasks ready to
+    /// run.
+    ///
+    /// Interrupts are disabled while checking the queue so that a wakeup from
+    /// an interrupt handler can't slip in between the check and the `hlt`.
+    /// `sti` only takes effect after the following instruction, so
+    /// `sti; hlt` re-enables interrupts and halts atomically.
     fn sleep_if_idle(&self) {
         x86_64::instructions::interrupts::disable();
 
         if self.task_queue.is_empty() {
+            HALTS.fetch_add(1, Ordering::Relaxed);
             x86_64::instructions::interrupts::enable_and_hlt();
         } else {
             x86_64::instructions::interrupts::enable();
@@ -7,9 +7,14 @@ use core::{
 };
 
 pub mod executor;
+pub mod timer;
+
+pub use timer::sleep;
 
 pub struct Task {
     id: TaskId,
+    /// Shown in task listings (i.e. `ps`) to tell tasks apart
+    name: Option<&'static str>,
     future: Pin<Box<dyn Future<Output = ()>>>,
 }
 
@@ -17,21 +22,34 @@ impl Task {
     pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
         Task {
             id: TaskId::new(),
+            name: None,
             future: Box::pin(future),
         }
     }
 
+    /// Creates a task with a name which identifies it in task listings
+    pub fn with_name(name: &'static str, future: impl Future<Output = ()> + 'static) -> Task {
+        Task {
+            name: Some(name),
+            ..Task::new(future)
+        }
+    }
+
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
         self.future.as_mut().poll(context)
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct TaskId(u64);
+pub struct TaskId(u64);
 
 impl TaskId {
     fn new() -> Self {
         static NEXT_ID: AtomicU64 = AtomicU64::new(0);
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
 }
@@ -0,0 +1,131 @@
+//! Lets tasks sleep until a later timer tick
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use spin::Mutex;
+
+use crate::util::time;
+
+/// The number of sleeping tasks which can be woken by the timer interrupt.
+/// Sleeps past this limit still work but keep the executor busy until they
+/// expire.
+const MAX_SLEEPERS: usize = 32;
+
+struct Sleeper {
+    /// Identifies the [`Sleep`] future which registered this entry
+    id: u64,
+    wake_tick: u64,
+    waker: Waker,
+}
+
+/// The sleeping tasks which are waiting on the timer interrupt. This list has
+/// a fixed capacity so that the interrupt handler never has to allocate or
+/// free memory.
+///
+/// Tasks must only lock it with interrupts disabled, otherwise the timer
+/// interrupt could fire while the lock is held and spin forever.
+static SLEEPERS: Mutex<heapless::Vec<Sleeper, MAX_SLEEPERS>> = Mutex::new(heapless::Vec::new());
+
+/// Returns a future which completes once at least the given duration has
+/// passed
+pub fn sleep(duration: Duration) -> Sleep {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    Sleep {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        wake_tick: time::ticks().saturating_add(time::ticks_for(duration)),
+    }
+}
+
+/// A future which completes at a certain timer tick. Created with [`sleep`].
+pub struct Sleep {
+    id: u64,
+    wake_tick: u64,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if time::ticks() >= self.wake_tick {
+            return Poll::Ready(());
+        }
+
+        // If the deadline passes between the check above and registering, the
+        // next tick still wakes us since expired sleepers are woken every tick
+        if !register(self.id, self.wake_tick, context.waker()) {
+            // The list is full, so fall back to polling until the deadline
+            context.waker().wake_by_ref();
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        // Make sure a cancelled sleep doesn't keep its slot
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut sleepers = SLEEPERS.lock();
+
+            if let Some(i) = sleepers.iter().position(|s| s.id == self.id) {
+                sleepers.swap_remove(i);
+            }
+        });
+    }
+}
+
+/// Adds the sleeper to the list or updates its existing entry. Returns false if
+/// the list is full.
+fn register(id: u64, wake_tick: u64, waker: &Waker) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut sleepers = SLEEPERS.lock();
+
+        if let Some(sleeper) = sleepers.iter_mut().find(|s| s.id == id) {
+            sleeper.wake_tick = wake_tick;
+
+            if !sleeper.waker.will_wake(waker) {
+                sleeper.waker = waker.clone();
+            }
+
+            return true;
+        }
+
+        sleepers
+            .push(Sleeper {
+                id,
+                wake_tick,
+                waker: waker.clone(),
+            })
+            .is_ok()
+    })
+}
+
+/// Wakes every task whose sleep has expired. Called by the timer interrupt
+/// handler.
+///
+/// Must not block or allocate.
+pub(crate) fn wake_expired() {
+    let now = time::ticks();
+
+    // Tasks only hold the lock with interrupts disabled, so it is always free
+    // here. Skipping a tick is harmless if that ever changes.
+    let Some(mut sleepers) = SLEEPERS.try_lock() else {
+        return;
+    };
+
+    let mut i = 0;
+    while i < sleepers.len() {
+        if sleepers[i].wake_tick <= now {
+            sleepers.swap_remove(i).waker.wake();
+        } else {
+            i += 1;
+        }
+    }
+}
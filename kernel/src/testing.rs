@@ -0,0 +1,107 @@
+//! The test runner for `cargo test`
+//!
+//! The kernel can't link against the standard test harness, so tests are
+//! collected with `custom_test_frameworks` instead. Each test is a plain
+//! function marked with `#[test_case]`, usually in a `tests` module at the end
+//! of the file it covers:
+//!
+//! ```ignore
+//! #[cfg(test)]
+//! mod tests {
+//!     #[test_case]
+//!     fn adds() {
+//!         assert_eq!(1 + 1, 2);
+//!     }
+//! }
+//! ```
+//!
+//! The test kernel boots like the normal one up to the point where the shell
+//! would be started, so tests can use the heap, the VFS and the devices. All
+//! tests share that one kernel, so anything they create in the VFS should go
+//! below their own [`scratch_directory`].
+//!
+//! Results are written to the serial port, which `bootimage` connects to the
+//! terminal, and QEMU is exited through its `isa-debug-exit` device once all
+//! tests passed or one of them panicked (see `package.metadata.bootimage` in
+//! Cargo.toml).
+
+use alloc::{format, string::String};
+use core::panic::PanicInfo;
+
+use x86_64::instructions::port::Port;
+
+use crate::{
+    drivers::char::serial::{serial_print, serial_println},
+    fs::vfs,
+    power,
+};
+
+/// The I/O port of the `isa-debug-exit` device. QEMU exits with `(value << 1)
+/// | 1` when a value is written to it.
+const QEMU_EXIT_PORT: u16 = 0xF4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    /// Makes QEMU exit with 33, which is configured as success
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+/// Exits QEMU with the given code. Halts if the kernel isn't running under
+/// QEMU (or the device is missing).
+pub fn exit_qemu(code: QemuExitCode) -> ! {
+    // SAFETY: the port belongs to the exit device, which only ends QEMU
+    unsafe { Port::<u32>::new(QEMU_EXIT_PORT).write(code as u32) };
+
+    power::halt()
+}
+
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        serial_print!("{}...\t", core::any::type_name::<T>());
+        self();
+        serial_println!("[ok]");
+    }
+}
+
+/// Runs every test. Called through the `test_main` function which is
+/// generated by `custom_test_frameworks`.
+pub fn run(tests: &[&dyn Testable]) {
+    serial_println!("Running {} tests", tests.len());
+
+    for test in tests {
+        test.run();
+    }
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// Reports the test which was running as failed. Called by the panic handler.
+pub fn fail(info: &PanicInfo) -> ! {
+    serial_println!("[failed]");
+    serial_println!();
+
+    match info.location() {
+        Some(loc) => serial_println!("Error (at {}): {}", loc, info.message()),
+        None => serial_println!("Error: {}", info.message()),
+    }
+
+    exit_qemu(QemuExitCode::Failed);
+}
+
+/// Creates an empty directory for a test to work in and returns its path.
+/// Every test should use a different name.
+pub fn scratch_directory(name: &str) -> String {
+    let path = format!("/scratch/{}", name);
+
+    vfs::get()
+        .create_directory_all(&path)
+        .expect("failed to create the scratch directory");
+
+    path
+}
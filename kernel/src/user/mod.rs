@@ -0,0 +1,536 @@
+//! This module runs code in ring 3 (user mode)
+//!
+//! Only one program runs in ring 3 at a time, and it runs to completion before
+//! [`run`] returns. The program shares the kernel's address space, but it can
+//! only access pages which are mapped as accessible from ring 3 (see
+//! [`UserPages`]). It talks to the kernel through system calls (see
+//! [`syscall`]).
+//!
+//! The entry point is entered as if it had been called with the same
+//! signature that [`exec`](crate::exec) documents, so `rdi`, `rsi` and `rdx`
+//! hold `argc`, `argv` and `envp`, and the strings they point to are on the
+//! stack of the program. Returning from the entry point lands in a small
+//! trampoline which passes the returned value to `exit`, so a program ends
+//! either by returning or by calling `exit` itself.
+//!
+//! # Entering and leaving ring 3
+//!
+//! [`enter_user_mode`] saves the callee saved registers and the flags on the
+//! current kernel stack and records the resulting stack pointer. It then builds
+//! the frame which `iretq` expects (stack segment, stack pointer, flags, code
+//! segment and instruction pointer of the program) from an [`EntryContext`]
+//! and executes `iretq`, which switches to ring 3 along with the stack of the
+//! program.
+//!
+//! While the program runs, interrupts and system calls arrive on the stack in
+//! `rsp0` of the TSS instead (see [`syscall`]), so the kernel stack which
+//! [`run`] was called on stays untouched. The `exit` system call abandons the
+//! `rsp0` stack and calls [`return_to_kernel`], which switches back to the
+//! recorded stack pointer and restores what was saved there. This makes
+//! [`enter_user_mode`] return to its caller with the exit status, as if it had
+//! been an ordinary function call.
+
+use alloc::vec::Vec;
+use core::{
+    fmt::Display,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use spin::Mutex;
+use x86_64::{
+    VirtAddr,
+    structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB},
+};
+
+use crate::{
+    fs::{FileDescriptor, vfs},
+    gdt, memory,
+};
+
+pub mod syscall;
+
+/// Addresses from here on belong to the higher half, which programs must never
+/// be given access to
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// The top of the stack which programs start with
+const USER_STACK_TOP: u64 = 0x0000_7FFF_FFFF_0000;
+/// The number of pages of the program stack
+const USER_STACK_PAGES: u64 = 4;
+
+/// Where the exit trampoline is copied to, right above the stack
+const EXIT_TRAMPOLINE_ADDRESS: u64 = USER_STACK_TOP;
+
+/// Where the test program of [`run_test_program`] is copied to
+const TEST_PROGRAM_ADDRESS: u64 = 0x0000_0000_4000_0000;
+
+/// The flags the program starts with. Only the interrupt flag is set (bit 1
+/// is reserved and always set), so interrupts keep working in ring 3.
+const USER_RFLAGS: u64 = 0x202;
+
+/// The most files a program can have open at once
+const MAX_OPEN_FILES: usize = 16;
+
+/// Set while a program is running in ring 3
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// The kernel stack pointer which [`return_to_kernel`] switches back to
+static KERNEL_STACK_POINTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserError {
+    /// Some page of the range is already mapped
+    AddressInUse,
+    /// There are no frames left to map the pages with
+    OutOfMemory,
+    /// The arguments don't fit on the stack of the program
+    ArgumentsTooLong,
+}
+
+impl Display for UserError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let message = match self {
+            UserError::AddressInUse => "program memory overlaps memory which is already in use",
+            UserError::OutOfMemory => "out of memory",
+            UserError::ArgumentsTooLong => "argument list too long",
+        };
+
+        write!(f, "{}", message)
+    }
+}
+
+/// The state a program starts with, in the order [`enter_user_mode`] reads it.
+/// The fields are only read by the assembly, hence the `allow`.
+#[repr(C)]
+#[allow(dead_code)]
+struct EntryContext {
+    rip: u64,
+    code_selector: u64,
+    rsp: u64,
+    data_selector: u64,
+    rdi: u64,
+    rsi: u64,
+    rdx: u64,
+}
+
+/// Pages which are mapped as accessible from ring 3. The pages are unmapped
+/// again once this is dropped.
+pub struct UserPages {
+    pages: Vec<Page<Size4KiB>>,
+}
+
+impl UserPages {
+    /// Maps `count` zeroed, writable pages starting at the page containing
+    /// `start`
+    pub fn map(start: VirtAddr, count: u64) -> Result<Self, UserError> {
+        // Pages are recorded as soon as they are mapped so that they are
+        // released if a later page fails to map
+        let mut mapped = Self { pages: Vec::new() };
+        let first = Page::<Size4KiB>::containing_address(start);
+
+        // The unmapped part of the heap looks free to the page tables
+        if memory::is_reserved(first.start_address(), (first + count).start_address()) {
+            return Err(UserError::AddressInUse);
+        }
+
+        for page in Page::range(first, first + count) {
+            // The page tables are only locked while mapping a single page.
+            // The list of pages is kept outside of the lock since growing it
+            // could fault on a heap page which is mapped on demand, which
+            // can't be mapped while the page tables are locked.
+            memory::with_mapper(|mapper, frame_allocator| {
+                if mapper.translate_page(page).is_ok() {
+                    return Err(UserError::AddressInUse);
+                }
+
+                let frame = frame_allocator
+                    .allocate_frame()
+                    .ok_or(UserError::OutOfMemory)?;
+
+                let flags = PageTableFlags::PRESENT
+                    | PageTableFlags::WRITABLE
+                    | PageTableFlags::USER_ACCESSIBLE;
+
+                unsafe {
+                    mapper
+                        .map_to(page, frame, flags, frame_allocator)
+                        .map_err(|_| UserError::OutOfMemory)?
+                        .flush();
+                }
+
+                Ok(())
+            })?;
+
+            mapped.pages.push(page);
+
+            // Fresh frames contain whatever was left in physical memory, which
+            // must not leak to the program
+            unsafe {
+                core::ptr::write_bytes(page.start_address().as_mut_ptr::<u8>(), 0, 4096);
+            }
+        }
+
+        Ok(mapped)
+    }
+
+    /// Maps enough pages at `address` to hold `code` and copies it there. The
+    /// pages are read-only afterwards.
+    pub fn map_code(address: VirtAddr, code: &[u8]) -> Result<Self, UserError> {
+        let pages = Self::map(address, (code.len() as u64).div_ceil(4096))?;
+
+        // SAFETY: the pages were just mapped as writable and are large enough
+        unsafe {
+            core::ptr::copy_nonoverlapping(code.as_ptr(), address.as_mut_ptr::<u8>(), code.len());
+        }
+
+        pages.make_read_only();
+        Ok(pages)
+    }
+
+    /// Stops the program from writing to the pages (i.e. once code has been
+    /// copied into them)
+    fn make_read_only(&self) {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+
+        memory::with_mapper(|mapper, _| {
+            for page in &self.pages {
+                unsafe {
+                    mapper
+                        .update_flags(*page, flags)
+                        .expect("user pages were just mapped")
+                        .flush();
+                }
+            }
+        });
+    }
+}
+
+impl Drop for UserPages {
+    fn drop(&mut self) {
+        // FIXME: the frames backing these pages are leaked since the frame
+        // allocator does not support freeing yet
+        memory::with_mapper(|mapper, _| {
+            for page in self.pages.drain(..) {
+                if let Ok((_, flush)) = mapper.unmap(page) {
+                    flush.flush();
+                }
+            }
+        });
+    }
+}
+
+/// The files opened by the running program. Programs refer to them by their
+/// index, so they can't use the descriptors of files the kernel has open.
+struct FileTable;
+
+static OPEN_FILES: Mutex<Vec<Option<FileDescriptor>>> = Mutex::new(Vec::new());
+
+impl FileTable {
+    fn get(fd: u64) -> Result<FileDescriptor, i64> {
+        OPEN_FILES
+            .lock()
+            .get(fd as usize)
+            .copied()
+            .flatten()
+            .ok_or(syscall::errno::EBADF)
+    }
+
+    /// Stores the descriptor at the lowest free index and returns the index
+    fn insert(fd: FileDescriptor) -> Result<u64, i64> {
+        let mut files = OPEN_FILES.lock();
+
+        let index = match files.iter().position(Option::is_none) {
+            Some(index) => index,
+            None if files.len() < MAX_OPEN_FILES => {
+                files.push(None);
+                files.len() - 1
+            }
+            None => return Err(syscall::errno::EMFILE),
+        };
+
+        files[index] = Some(fd);
+        Ok(index as u64)
+    }
+
+    fn remove(fd: u64) -> Result<FileDescriptor, i64> {
+        OPEN_FILES
+            .lock()
+            .get_mut(fd as usize)
+            .and_then(Option::take)
+            .ok_or(syscall::errno::EBADF)
+    }
+
+    /// Closes every file which the program left open
+    fn close_all() {
+        let files = core::mem::take(&mut *OPEN_FILES.lock());
+
+        for fd in files.into_iter().flatten() {
+            let _ = vfs::get().close(fd);
+        }
+    }
+}
+
+/// Runs the code at `entry` in ring 3 on a fresh stack until it exits,
+/// returning its exit status. `argv[0]` is `path`, followed by `args`. The code
+/// must be mapped with [`UserPages`].
+///
+/// Panics if another program is already running in ring 3.
+pub fn run(entry: VirtAddr, path: &str, args: &[&str]) -> Result<i64, UserError> {
+    let stack_bottom = USER_STACK_TOP - USER_STACK_PAGES * 4096;
+    let _stack = UserPages::map(VirtAddr::new(stack_bottom), USER_STACK_PAGES)?;
+    let _trampoline =
+        UserPages::map_code(VirtAddr::new(EXIT_TRAMPOLINE_ADDRESS), exit_trampoline())?;
+
+    /* Copy the arguments onto the stack */
+
+    let strings = core::iter::once(path).chain(args.iter().copied());
+
+    // The strings go to the top, then the argv and envp arrays below them.
+    // Keep at least a page free for the program itself.
+    let strings_size = strings.clone().map(|s| s.len() as u64 + 1).sum::<u64>();
+    let argc = args.len() as u64 + 1;
+    let arrays_size = (argc + 2) * 8;
+
+    if strings_size + arrays_size + 64 > (USER_STACK_PAGES - 1) * 4096 {
+        return Err(UserError::ArgumentsTooLong);
+    }
+
+    let mut string_address = USER_STACK_TOP - strings_size;
+    let argv = (string_address & !0x7) - arrays_size;
+    let envp = argv + (argc + 1) * 8;
+
+    // SAFETY: everything is written within the stack pages, which were just
+    // mapped as writable, as checked above
+    unsafe {
+        for (i, s) in strings.enumerate() {
+            let destination = string_address as *mut u8;
+            core::ptr::copy_nonoverlapping(s.as_ptr(), destination, s.len());
+            destination.add(s.len()).write(0);
+
+            (argv as *mut u64).add(i).write(string_address);
+            string_address += s.len() as u64 + 1;
+        }
+
+        // Both arrays end with a null pointer and the environment is empty
+        (argv as *mut u64).add(argc as usize).write(0);
+        (envp as *mut u64).write(0);
+    }
+
+    // The System V ABI requires the stack to be 16 byte aligned at the call,
+    // which then pushes the return address
+    let stack_pointer = (argv & !0xF) - 8;
+
+    // SAFETY: same as above
+    unsafe { (stack_pointer as *mut u64).write(EXIT_TRAMPOLINE_ADDRESS) };
+
+    /* Enter the program */
+
+    assert!(
+        !RUNNING.swap(true, Ordering::Acquire),
+        "a program is already running in ring 3"
+    );
+
+    let selectors = gdt::selectors();
+    let context = EntryContext {
+        rip: entry.as_u64(),
+        code_selector: selectors.user_code.0 as u64,
+        rsp: stack_pointer,
+        data_selector: selectors.user_data.0 as u64,
+        rdi: argc,
+        rsi: argv,
+        rdx: envp,
+    };
+
+    // SAFETY: the stack was just set up for the program, and the code must be
+    // mapped according to the documentation of this function
+    let status = unsafe { enter_user_mode(&context) };
+
+    FileTable::close_all();
+    RUNNING.store(false, Ordering::Release);
+
+    Ok(status)
+}
+
+/// Ends the running program with the given status. Must only be called while
+/// handling a system call of the program.
+fn exit(status: i64) -> ! {
+    // SAFETY: the program was started by `enter_user_mode`, which recorded the
+    // stack to return to
+    unsafe { return_to_kernel(status) }
+}
+
+/// Switches to ring 3 with the state in the context. Returns once the program
+/// exits. See the module documentation for how this works.
+///
+/// SAFETY: the instruction and stack pointer must point into pages which are
+/// mapped as accessible from ring 3, and the selectors must be the ring 3 code
+/// and data segments
+#[unsafe(naked)]
+unsafe extern "sysv64" fn enter_user_mode(context: &EntryContext) -> i64 {
+    core::arch::naked_asm!(
+        // Restored by `return_to_kernel`
+        "push rbx",
+        "push rbp",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "pushfq",
+        "mov [rip + {kernel_stack_pointer}], rsp",
+        // The frame for iretq: ss, rsp, rflags, cs and rip
+        "push qword ptr [rdi + 24]",
+        "push qword ptr [rdi + 16]",
+        "push {rflags}",
+        "push qword ptr [rdi + 8]",
+        "push qword ptr [rdi]",
+        // Don't leak kernel values to the program
+        "xor eax, eax",
+        "xor ebx, ebx",
+        "xor ecx, ecx",
+        "xor ebp, ebp",
+        "xor r8d, r8d",
+        "xor r9d, r9d",
+        "xor r10d, r10d",
+        "xor r11d, r11d",
+        "xor r12d, r12d",
+        "xor r13d, r13d",
+        "xor r14d, r14d",
+        "xor r15d, r15d",
+        // The arguments of the entry point, rdi last since it holds the
+        // context
+        "mov rsi, [rdi + 40]",
+        "mov rdx, [rdi + 48]",
+        "mov rdi, [rdi + 32]",
+        "iretq",
+        kernel_stack_pointer = sym KERNEL_STACK_POINTER,
+        rflags = const USER_RFLAGS,
+    );
+}
+
+/// Makes [`enter_user_mode`] return `status` to its caller
+///
+/// SAFETY: a program must have been started by [`enter_user_mode`] and not
+/// have exited yet
+#[unsafe(naked)]
+unsafe extern "sysv64" fn return_to_kernel(status: i64) -> ! {
+    core::arch::naked_asm!(
+        "mov rsp, [rip + {kernel_stack_pointer}]",
+        "mov rax, rdi",
+        // Also turns interrupts back on, which the system call gate disabled
+        "popfq",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbp",
+        "pop rbx",
+        "ret",
+        kernel_stack_pointer = sym KERNEL_STACK_POINTER,
+    );
+}
+
+/* Programs written in assembly */
+
+// Passes the value returned from the entry point of a program to `exit`. The
+// entry point returns here since this is the return address on its stack.
+core::arch::global_asm!(
+    ".pushsection .rodata.user_exit_trampoline, \"a\"",
+    ".global user_exit_trampoline_start",
+    ".global user_exit_trampoline_end",
+    "user_exit_trampoline_start:",
+    "    mov rdi, rax",
+    "    mov eax, {SYS_EXIT}",
+    "    int {SYSCALL_INTERRUPT}",
+    "    ud2",
+    "user_exit_trampoline_end:",
+    ".popsection",
+    SYS_EXIT = const syscall::SYS_EXIT,
+    SYSCALL_INTERRUPT = const syscall::SYSCALL_INTERRUPT,
+);
+
+// Opens /dev/console, writes a greeting to it and exits. The code only uses
+// addresses relative to itself, so it can be copied anywhere.
+core::arch::global_asm!(
+    ".pushsection .rodata.user_test_program, \"a\"",
+    ".global user_test_program_start",
+    ".global user_test_program_end",
+    "user_test_program_start:",
+    "    mov eax, {SYS_OPEN}",
+    "    lea rdi, [rip + .Luser_test_program_path]",
+    "    mov esi, 1",
+    "    int {SYSCALL_INTERRUPT}",
+    "    test rax, rax",
+    "    js .Luser_test_program_exit",
+    "    mov rbx, rax",
+    "    mov rdi, rax",
+    "    mov eax, {SYS_WRITE}",
+    "    lea rsi, [rip + .Luser_test_program_message]",
+    "    mov rdx, [rip + .Luser_test_program_message_length]",
+    "    int {SYSCALL_INTERRUPT}",
+    "    test rax, rax",
+    "    js .Luser_test_program_exit",
+    "    mov rdi, rbx",
+    "    mov eax, {SYS_CLOSE}",
+    "    int {SYSCALL_INTERRUPT}",
+    "    xor eax, eax",
+    ".Luser_test_program_exit:",
+    "    mov rdi, rax",
+    "    mov eax, {SYS_EXIT}",
+    "    int {SYSCALL_INTERRUPT}",
+    "    ud2",
+    ".Luser_test_program_message_length:",
+    "    .quad .Luser_test_program_message_end - .Luser_test_program_message",
+    ".Luser_test_program_path:",
+    "    .asciz \"/dev/console\"",
+    ".Luser_test_program_message:",
+    "    .ascii \"Hello from ring 3!\\n\"",
+    ".Luser_test_program_message_end:",
+    "user_test_program_end:",
+    ".popsection",
+    SYS_OPEN = const syscall::SYS_OPEN,
+    SYS_WRITE = const syscall::SYS_WRITE,
+    SYS_CLOSE = const syscall::SYS_CLOSE,
+    SYS_EXIT = const syscall::SYS_EXIT,
+    SYSCALL_INTERRUPT = const syscall::SYSCALL_INTERRUPT,
+);
+
+unsafe extern "C" {
+    static user_exit_trampoline_start: u8;
+    static user_exit_trampoline_end: u8;
+    static user_test_program_start: u8;
+    static user_test_program_end: u8;
+}
+
+/// Returns the code between two symbols defined by the assembly above
+///
+/// SAFETY: both symbols must be defined around the same piece of code
+unsafe fn code_between(start: *const u8, end: *const u8) -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts(start, end.offset_from(start) as usize) }
+}
+
+fn exit_trampoline() -> &'static [u8] {
+    // SAFETY: both symbols are defined around the trampoline
+    unsafe {
+        code_between(
+            &raw const user_exit_trampoline_start,
+            &raw const user_exit_trampoline_end,
+        )
+    }
+}
+
+/// Runs a small built in program in ring 3 which writes a greeting to
+/// `/dev/console` through system calls, returning its exit status
+pub fn run_test_program() -> Result<i64, UserError> {
+    // SAFETY: both symbols are defined around the test program
+    let code = unsafe {
+        code_between(
+            &raw const user_test_program_start,
+            &raw const user_test_program_end,
+        )
+    };
+
+    let address = VirtAddr::new(TEST_PROGRAM_ADDRESS);
+    let _pages = UserPages::map_code(address, code)?;
+
+    run(address, "usertest", &[])
+}
@@ -0,0 +1,313 @@
+//! The system call interface for programs running in ring 3
+//!
+//! A program makes a system call by putting its number in `rax` and the
+//! arguments in `rdi`, `rsi`, `rdx`, `r10`, `r8` and `r9` (like Linux) and then
+//! executing `int 0x80`. The result comes back in `rax`, where a negative value
+//! is an error number (see [`errno`]). All other registers are preserved.
+//!
+//! The numbers match the ones used by Linux on x86_64:
+//!
+//! | Number | Name    | Arguments                                   |
+//! |--------|---------|---------------------------------------------|
+//! | 0      | `read`  | `fd`, `buffer`, `length`                    |
+//! | 1      | `write` | `fd`, `buffer`, `length`                    |
+//! | 2      | `open`  | `path` (NUL terminated), `mode` (see below) |
+//! | 3      | `close` | `fd`                                        |
+//! | 60     | `exit`  | `status`                                    |
+//!
+//! The mode of `open` is 0 for reading, 1 for writing (which truncates) and 2
+//! for appending.
+//!
+//! # Stack switching
+//!
+//! The `int 0x80` gate is the only one which ring 3 is allowed to use. When it
+//! is raised from ring 3, the CPU loads the stack pointer from `rsp0` of the
+//! TSS (see [`gdt::init`](crate::gdt::init)) before pushing anything, so the
+//! kernel never runs on the stack of the program. It then pushes the stack
+//! segment and stack pointer of the program, followed by `rflags`, `cs` and
+//! `rip`. [`syscall_entry`] pushes the caller saved registers on top of that,
+//! which makes the whole stack a [`SyscallFrame`] that is handed to
+//! [`dispatch`]. Returning pops the registers again and `iretq` restores the
+//! stack, flags and privilege level of the program from the frame.
+//!
+//! The gate is an interrupt gate, so interrupts stay disabled while a system
+//! call is handled. Every system call is short and never waits, so this only
+//! delays the timer and keyboard slightly.
+
+use alloc::string::String;
+
+use x86_64::{
+    VirtAddr,
+    structures::paging::{PageTableFlags, Translate, mapper::TranslateResult},
+};
+
+use super::{FileTable, USER_SPACE_END};
+use crate::{
+    fs::{
+        FileMode,
+        vfs::{self, IoError},
+    },
+    memory,
+};
+
+/// The interrupt vector which programs use to make system calls
+pub const SYSCALL_INTERRUPT: u8 = 0x80;
+
+pub const SYS_READ: u64 = 0;
+pub const SYS_WRITE: u64 = 1;
+pub const SYS_OPEN: u64 = 2;
+pub const SYS_CLOSE: u64 = 3;
+pub const SYS_EXIT: u64 = 60;
+
+/// The longest path which is accepted by `open`, including the NUL byte
+const MAX_PATH_LENGTH: usize = 4096;
+
+/// Error numbers which are returned (negated) from system calls. They match
+/// the Linux ones.
+pub mod errno {
+    pub const ENOENT: i64 = 2;
+    pub const EIO: i64 = 5;
+    pub const EBADF: i64 = 9;
+    pub const EACCES: i64 = 13;
+    pub const EFAULT: i64 = 14;
+    pub const EBUSY: i64 = 16;
+    pub const EEXIST: i64 = 17;
+    pub const ENODEV: i64 = 19;
+    pub const ENOTDIR: i64 = 20;
+    pub const EISDIR: i64 = 21;
+    pub const EINVAL: i64 = 22;
+    pub const EMFILE: i64 = 24;
+    pub const EFBIG: i64 = 27;
+    pub const ENOSPC: i64 = 28;
+    pub const ESPIPE: i64 = 29;
+    pub const ENAMETOOLONG: i64 = 36;
+    pub const ENOSYS: i64 = 38;
+    pub const ENOTEMPTY: i64 = 39;
+    pub const ELOOP: i64 = 40;
+    pub const EOPNOTSUPP: i64 = 95;
+}
+
+/// The registers which [`syscall_entry`] pushed, from the lowest address. The
+/// frame pushed by the CPU follows right after.
+///
+/// The frame is only ever built by the assembly, and some of the registers are
+/// only saved so that they can be restored, hence the `allow`.
+#[repr(C)]
+#[allow(dead_code)]
+struct SyscallFrame {
+    r11: u64,
+    r10: u64,
+    r9: u64,
+    r8: u64,
+    rdi: u64,
+    rsi: u64,
+    rdx: u64,
+    rcx: u64,
+    rax: u64,
+}
+
+/// The handler of the system call gate. See the module documentation for the
+/// layout of the stack.
+#[unsafe(naked)]
+pub extern "C" fn syscall_entry() {
+    core::arch::naked_asm!(
+        "push rax",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        // The CPU aligned the stack to 16 bytes before pushing its 5 values,
+        // so the 9 pushes above leave it aligned again for the call
+        "mov rdi, rsp",
+        // The program may have set the direction flag, which the System V ABI
+        // requires to be clear
+        "cld",
+        "call {dispatch}",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        // Holds the result now
+        "pop rax",
+        "iretq",
+        dispatch = sym dispatch,
+    );
+}
+
+/// Carries out the system call described by the frame and stores the result
+/// in its `rax`
+extern "sysv64" fn dispatch(frame: &mut SyscallFrame) {
+    let (a0, a1, a2) = (frame.rdi, frame.rsi, frame.rdx);
+
+    let result = match frame.rax {
+        SYS_READ => sys_read(a0, a1, a2),
+        SYS_WRITE => sys_write(a0, a1, a2),
+        SYS_OPEN => sys_open(a0, a1),
+        SYS_CLOSE => sys_close(a0),
+        SYS_EXIT => super::exit(a0 as i64),
+        _ => Err(errno::ENOSYS),
+    };
+
+    frame.rax = match result {
+        Ok(value) => value,
+        Err(e) => (-e) as u64,
+    };
+}
+
+type SyscallResult = Result<u64, i64>;
+
+fn sys_read(fd: u64, buffer: u64, length: u64) -> SyscallResult {
+    let fd = FileTable::get(fd)?;
+    let buffer = user_slice_mut(buffer, length)?;
+
+    let n = vfs::get().read(fd, buffer).map_err(error_number)?;
+    Ok(n as u64)
+}
+
+fn sys_write(fd: u64, buffer: u64, length: u64) -> SyscallResult {
+    let fd = FileTable::get(fd)?;
+    let buffer = user_slice(buffer, length)?;
+
+    let n = vfs::get().write(fd, buffer).map_err(error_number)?;
+    Ok(n as u64)
+}
+
+fn sys_open(path: u64, mode: u64) -> SyscallResult {
+    let path = user_string(path)?;
+
+    let mode = match mode {
+        0 => FileMode::Read,
+        1 => FileMode::Write,
+        2 => FileMode::Append,
+        _ => return Err(errno::EINVAL),
+    };
+
+    let fd = vfs::get().open(&path, mode).map_err(error_number)?;
+
+    FileTable::insert(fd).inspect_err(|_| {
+        let _ = vfs::get().close(fd);
+    })
+}
+
+fn sys_close(fd: u64) -> SyscallResult {
+    let fd = FileTable::remove(fd)?;
+
+    vfs::get().close(fd).map_err(error_number)?;
+    Ok(0)
+}
+
+/// Converts a VFS error into the error number which is returned to programs
+fn error_number(error: IoError) -> i64 {
+    match error {
+        IoError::OperationNotSupported => errno::EOPNOTSUPP,
+        IoError::EntryNotFound => errno::ENOENT,
+        IoError::AlreadyExists => errno::EEXIST,
+        IoError::NotADirectory => errno::ENOTDIR,
+        IoError::NotAFile => errno::EISDIR,
+        IoError::InvalidPath => errno::EINVAL,
+        IoError::InvalidFile => errno::EBADF,
+        IoError::InvalidMode => errno::EBADF,
+        IoError::FileSystemTypeNotFound => errno::ENODEV,
+        IoError::NoRootDirectory => errno::ENOENT,
+        IoError::NameTooLong => errno::ENAMETOOLONG,
+        IoError::NoSpaceLeft => errno::ENOSPC,
+        IoError::DeviceError => errno::EIO,
+        IoError::InvalidSeek => errno::ESPIPE,
+        IoError::NotEmpty => errno::ENOTEMPTY,
+        IoError::Busy => errno::EBUSY,
+        IoError::TooManySymlinks => errno::ELOOP,
+        IoError::FileTooLarge => errno::EFBIG,
+        IoError::PermissionDenied => errno::EACCES,
+        IoError::SameFile => errno::EINVAL,
+    }
+}
+
+/* Access to program memory */
+
+/// Makes sure that every page of the given range is mapped and accessible
+/// from ring 3 (and writable if requested) so that a program can't make the
+/// kernel touch memory which the program itself couldn't
+fn check_user_range(address: u64, length: u64, writable: bool) -> Result<(), i64> {
+    if length == 0 {
+        return Ok(());
+    }
+
+    let end = address.checked_add(length).ok_or(errno::EFAULT)?;
+    if end > USER_SPACE_END {
+        return Err(errno::EFAULT);
+    }
+
+    let mut required = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if writable {
+        required |= PageTableFlags::WRITABLE;
+    }
+
+    memory::with_mapper(|mapper, _| {
+        let first_page = address & !0xFFF;
+
+        for page in (first_page..end).step_by(4096) {
+            match mapper.translate(VirtAddr::new(page)) {
+                TranslateResult::Mapped { flags, .. } if flags.contains(required) => {}
+                _ => return Err(errno::EFAULT),
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn user_slice<'a>(address: u64, length: u64) -> Result<&'a [u8], i64> {
+    check_user_range(address, length, false)?;
+
+    if length == 0 {
+        return Ok(&[]);
+    }
+
+    // SAFETY: the whole range is mapped and belongs to the program, which
+    // can't run while the system call is handled
+    Ok(unsafe { core::slice::from_raw_parts(address as *const u8, length as usize) })
+}
+
+fn user_slice_mut<'a>(address: u64, length: u64) -> Result<&'a mut [u8], i64> {
+    check_user_range(address, length, true)?;
+
+    if length == 0 {
+        return Ok(&mut []);
+    }
+
+    // SAFETY: same as in `user_slice`, and the pages are writable
+    Ok(unsafe { core::slice::from_raw_parts_mut(address as *mut u8, length as usize) })
+}
+
+/// Copies a NUL terminated UTF-8 string out of program memory
+fn user_string(address: u64) -> Result<String, i64> {
+    let mut bytes = alloc::vec::Vec::new();
+
+    // Checked a byte at a time since the length isn't known up front
+    loop {
+        let byte_address = address
+            .checked_add(bytes.len() as u64)
+            .ok_or(errno::EFAULT)?;
+        let byte = user_slice(byte_address, 1)?[0];
+
+        if byte == 0 {
+            break;
+        }
+
+        if bytes.len() + 1 >= MAX_PATH_LENGTH {
+            return Err(errno::ENAMETOOLONG);
+        }
+
+        bytes.push(byte);
+    }
+
+    String::from_utf8(bytes).map_err(|_| errno::EINVAL)
+}
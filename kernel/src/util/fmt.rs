@@ -0,0 +1,83 @@
+//! Helpers for formatting values for people to read
+
+use core::fmt::Write;
+
+/// The suffixes of each power of 1024, starting at 1024 itself
+const UNITS: [char; 6] = ['K', 'M', 'G', 'T', 'P', 'E'];
+
+/// Long enough for the widest output, such as `1023K`
+pub type HumanBytes = heapless::String<8>;
+
+/// Formats a number of bytes with the largest unit which keeps it below 1024,
+/// like `ls -h` does. Values below 10 of a unit get one decimal place, so
+/// 1536 bytes is `1.5K` and 15360 bytes is `15K`. Values are rounded up so
+/// that a size is never shown smaller than it is.
+pub fn human_bytes(n: usize) -> HumanBytes {
+    let mut s = HumanBytes::new();
+
+    // Plain byte counts have no suffix
+    if n < 1024 {
+        write!(s, "{n}").unwrap();
+        return s;
+    }
+
+    let n = n as u128;
+    let mut divisor = 1024;
+
+    for (i, unit) in UNITS.iter().enumerate() {
+        let tenths = (n * 10).div_ceil(divisor);
+
+        if tenths < 100 {
+            write!(s, "{}.{}{unit}", tenths / 10, tenths % 10).unwrap();
+            return s;
+        }
+
+        // Rounding up may reach 1024, which is shown as 1.0 of the next unit
+        let whole = n.div_ceil(divisor);
+
+        if whole < 1024 || i == UNITS.len() - 1 {
+            write!(s, "{whole}{unit}").unwrap();
+            return s;
+        }
+
+        divisor *= 1024;
+    }
+
+    unreachable!("the last unit always returns")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn plain_bytes_below_1024() {
+        assert_eq!(human_bytes(0), "0");
+        assert_eq!(human_bytes(1), "1");
+        assert_eq!(human_bytes(1023), "1023");
+    }
+
+    #[test_case]
+    fn one_decimal_below_10() {
+        assert_eq!(human_bytes(1024), "1.0K");
+        assert_eq!(human_bytes(1536), "1.5K");
+        assert_eq!(human_bytes(10 * 1024 - 1), "10K");
+        assert_eq!(human_bytes(10 * 1024), "10K");
+        assert_eq!(human_bytes(15 * 1024), "15K");
+    }
+
+    #[test_case]
+    fn rounds_up_into_the_next_unit() {
+        assert_eq!(human_bytes(1024 * 1024 - 1), "1.0M");
+        assert_eq!(human_bytes(1024 * 1024), "1.0M");
+        assert_eq!(human_bytes(1024 * 1024 + 1), "1.1M");
+        assert_eq!(human_bytes(5 * 1024 * 1024 + 1), "5.1M");
+        assert_eq!(human_bytes(1023 * 1024 * 1024), "1023M");
+        assert_eq!(human_bytes(1 << 30), "1.0G");
+    }
+
+    #[test_case]
+    fn largest_values_fit() {
+        assert_eq!(human_bytes(usize::MAX), "16E");
+    }
+}
@@ -1,3 +1,5 @@
-pub mod sync_cell;
 pub mod defer;
-
+pub mod fmt;
+pub mod slice_io;
+pub mod sync_cell;
+pub mod time;
@@ -0,0 +1,94 @@
+//! Reading and writing at byte offsets of in-memory file contents
+
+use alloc::vec::Vec;
+
+/// Copies as much of `data` starting at `offset` into the buffer as fits and
+/// returns the number of bytes copied. Reading at or past the end copies
+/// nothing.
+pub fn read_at(data: &[u8], offset: usize, buffer: &mut [u8]) -> usize {
+    let Some(remaining) = data.get(offset..) else {
+        return 0;
+    };
+
+    let length = buffer.len().min(remaining.len());
+    buffer[..length].copy_from_slice(&remaining[..length]);
+
+    length
+}
+
+/// Returns the length the data would have after writing `length` bytes at
+/// `offset`
+pub fn length_after_write(data: &[u8], offset: usize, length: usize) -> usize {
+    data.len().max(offset + length)
+}
+
+/// Copies the buffer into `data` at `offset`, growing `data` if the buffer
+/// reaches past its end. Any gap between the old end and the offset is filled
+/// with 0s.
+pub fn write_at(data: &mut Vec<u8>, offset: usize, buffer: &[u8]) {
+    let end = offset + buffer.len();
+
+    if end > data.len() {
+        data.resize(end, 0);
+    }
+
+    data[offset..end].copy_from_slice(buffer);
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test_case]
+    fn read_past_end_copies_nothing() {
+        let mut buffer = [0xAA; 4];
+
+        assert_eq!(read_at(b"abc", 3, &mut buffer), 0);
+        assert_eq!(read_at(b"abc", 10, &mut buffer), 0);
+        assert_eq!(buffer, [0xAA; 4]);
+    }
+
+    #[test_case]
+    fn read_stops_at_end() {
+        let mut buffer = [0xAA; 4];
+
+        assert_eq!(read_at(b"abcdef", 4, &mut buffer), 2);
+        assert_eq!(buffer, [b'e', b'f', 0xAA, 0xAA]);
+    }
+
+    #[test_case]
+    fn read_stops_at_buffer_length() {
+        let mut buffer = [0; 2];
+
+        assert_eq!(read_at(b"abcdef", 1, &mut buffer), 2);
+        assert_eq!(&buffer, b"bc");
+    }
+
+    #[test_case]
+    fn write_past_end_fills_hole() {
+        let mut data = vec![1, 2];
+
+        assert_eq!(length_after_write(&data, 4, 2), 6);
+        write_at(&mut data, 4, &[5, 6]);
+        assert_eq!(data, [1, 2, 0, 0, 5, 6]);
+    }
+
+    #[test_case]
+    fn write_inside_keeps_length() {
+        let mut data = vec![1, 2, 3, 4];
+
+        assert_eq!(length_after_write(&data, 1, 2), 4);
+        write_at(&mut data, 1, &[8, 9]);
+        assert_eq!(data, [1, 8, 9, 4]);
+    }
+
+    #[test_case]
+    fn write_across_end_grows() {
+        let mut data = vec![1, 2, 3];
+
+        write_at(&mut data, 2, &[7, 8, 9]);
+        assert_eq!(data, [1, 2, 7, 8, 9]);
+    }
+}
@@ -0,0 +1,145 @@
+//! A minimal time source for the kernel, driven by the timer interrupt and
+//! anchored to wall-clock time by the RTC at boot
+
+use core::{
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::{drivers::rtc, interrupts};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// The number of timer interrupts which have fired since boot
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// The wall-clock time at boot as a Unix timestamp
+static BOOT_TIME: AtomicU64 = AtomicU64::new(0);
+
+/// Reads the wall-clock time from the RTC. Until this is called, [`now`]
+/// counts from the Unix epoch.
+pub fn init() {
+    let boot_time = rtc::now().timestamp().saturating_sub(uptime_ms() / 1000);
+    BOOT_TIME.store(boot_time, Ordering::Relaxed);
+}
+
+/// Advances the clock by a single tick. Must only be called from the timer
+/// interrupt handler.
+pub(crate) fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the number of timer interrupts which have fired since boot
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Returns the time since boot in milliseconds, based on the rate the timer is
+/// programmed with (see [`interrupts::set_timer_frequency`])
+pub fn uptime_ms() -> u64 {
+    // Use the exact divisor rather than the rounded frequency so that the
+    // uptime doesn't drift
+    ticks() * interrupts::timer_divisor() as u64 * 1000 / interrupts::PIT_BASE_FREQUENCY_HZ as u64
+}
+
+/// Returns the smallest number of timer ticks which spans at least the given
+/// duration at the rate the timer is programmed with
+pub fn ticks_for(duration: Duration) -> u64 {
+    let divisor = interrupts::timer_divisor() as u128;
+    let base_frequency = interrupts::PIT_BASE_FREQUENCY_HZ as u128;
+
+    (duration.as_nanos() * base_frequency)
+        .div_ceil(divisor * 1_000_000_000)
+        .try_into()
+        .unwrap_or(u64::MAX)
+}
+
+/// Returns the current wall-clock time as a Unix timestamp. The time is read
+/// from the RTC only once at boot and advanced by the timer after that, so it
+/// is monotonic.
+pub fn now() -> u64 {
+    BOOT_TIME.load(Ordering::Relaxed) + uptime_ms() / 1000
+}
+
+/// A calendar date and time of day. There is no notion of time zones, so this
+/// is UTC as long as the RTC is set to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Converts a Unix timestamp into a date in the proleptic Gregorian
+    /// calendar
+    pub fn from_timestamp(timestamp: u64) -> Self {
+        let days = timestamp / SECONDS_PER_DAY;
+        let seconds = timestamp % SECONDS_PER_DAY;
+
+        // Count from 0000-03-01 so that a leap day is the last day of its year
+        // and the calendar repeats every 400 years (146097 days)
+        let days = days + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        // Months are counted from March here
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = era * 400 + year_of_era + (month <= 2) as u64;
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+
+    /// Converts the date into a Unix timestamp. Dates before 1970 are not
+    /// supported.
+    pub fn timestamp(&self) -> u64 {
+        let (month, day) = (self.month as u64, self.day as u64);
+
+        // Same as in `from_timestamp`, January and February belong to the
+        // previous year when counting from March
+        let year = self.year as u64 - (month <= 2) as u64;
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = (era * 146_097 + day_of_era).saturating_sub(719_468);
+
+        days * SECONDS_PER_DAY
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64
+    }
+}
+
+impl Display for DateTime {
+    /// Formats the date like ISO 8601, with a space instead of the `T`
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
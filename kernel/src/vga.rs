@@ -8,8 +8,24 @@ struct Writer {
     column_position: usize,
     color_code: ColorCode,
     buffer: &'static mut Buffer,
+    escape_state: EscapeState,
+    escape_parameters: heapless::Vec<u16, MAX_ESCAPE_PARAMETERS>,
 }
 
+/// Where the writer is within an ANSI escape sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EscapeState {
+    /// Printing characters normally
+    Ground,
+    /// Saw an ESC byte and expecting the `[` which starts a control sequence
+    Escape,
+    /// Inside a control sequence, collecting parameters until the final byte
+    ControlSequence,
+}
+
+/// Parameters past this many are dropped from a control sequence
+const MAX_ESCAPE_PARAMETERS: usize = 8;
+
 pub const BUFFER_HEIGHT: usize = 25;
 pub const BUFFER_WIDTH: usize = 80;
 
@@ -33,8 +49,16 @@ impl ColorCode {
     pub const fn new(foreground: Color, background: Color) -> Self {
         Self((background as u8) << 4 | (foreground as u8))
     }
+
+    /// Returns this color code with the foreground replaced
+    pub const fn with_foreground(self, foreground: Color) -> Self {
+        Self((self.0 & 0xF0) | (foreground as u8))
+    }
 }
 
+/// The color code the writer starts out with
+pub const DEFAULT_COLOR_CODE: ColorCode = ColorCode::new(Color::White, Color::Black);
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -57,6 +81,30 @@ pub enum Color {
     White = 15,
 }
 
+/// The colors selected by the SGR codes 30-37, in order
+const ANSI_COLORS: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Brown,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightGray,
+];
+
+/// The colors selected by the SGR codes 90-97, in order
+const ANSI_BRIGHT_COLORS: [Color; 8] = [
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::Yellow,
+    Color::LightBlue,
+    Color::Pink,
+    Color::LightCyan,
+    Color::White,
+];
+
 impl From<Color> for ColorCode {
     fn from(value: Color) -> Self {
         Self::new(value, Color::Black)
@@ -67,6 +115,9 @@ impl Writer {
     pub fn write_byte(&mut self, byte: u8) {
         match byte {
             b'\n' => self.new_line(),
+            // Return to the start of the line so it can be overwritten in place
+            // (i.e. for progress indicators)
+            b'\r' => self.column_position = 0,
             byte => {
                 if self.column_position >= BUFFER_WIDTH {
                     self.new_line();
@@ -84,17 +135,96 @@ impl Writer {
         }
     }
 
+    /// Writes the string to the screen. ANSI SGR sequences (i.e. `\x1b[31m`)
+    /// change the foreground color, and any other escape sequence is dropped.
     pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                // printable ASCII byte or newline
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                // not part of printable ASCII range
-                _ => self.write_byte(0xfe),
+        for c in s.chars() {
+            if !c.is_ascii() {
+                // Escape sequences only consist of ASCII, so anything else
+                // ends the one in progress
+                self.escape_state = EscapeState::Ground;
+                self.write_byte(code_page_437(c).unwrap_or(0xfe));
+                continue;
+            }
+
+            let byte = c as u8;
+
+            match self.escape_state {
+                EscapeState::Ground => match byte {
+                    0x1b => self.escape_state = EscapeState::Escape,
+                    // printable ASCII byte, newline or carriage return
+                    0x20..=0x7e | b'\n' | b'\r' => self.write_byte(byte),
+                    // not part of printable ASCII range
+                    _ => self.write_byte(0xfe),
+                },
+                EscapeState::Escape => {
+                    // We only understand control sequences, so any other
+                    // escape sequence ends here with its byte swallowed
+                    if byte == b'[' {
+                        self.escape_parameters.clear();
+                        self.escape_state = EscapeState::ControlSequence;
+                    } else {
+                        self.escape_state = EscapeState::Ground;
+                    }
+                }
+                EscapeState::ControlSequence => match byte {
+                    b'0'..=b'9' => {
+                        if self.escape_parameters.is_empty() {
+                            let _ = self.escape_parameters.push(0);
+                        }
+
+                        if let Some(parameter) = self.escape_parameters.last_mut() {
+                            *parameter = parameter
+                                .saturating_mul(10)
+                                .saturating_add((byte - b'0') as u16);
+                        }
+                    }
+                    b';' => {
+                        // An omitted parameter is the same as a 0
+                        if self.escape_parameters.is_empty() {
+                            let _ = self.escape_parameters.push(0);
+                        }
+
+                        let _ = self.escape_parameters.push(0);
+                    }
+                    // The final byte of the sequence
+                    0x40..=0x7e => {
+                        if byte == b'm' {
+                            self.select_graphic_rendition();
+                        }
+
+                        self.escape_state = EscapeState::Ground;
+                    }
+                    // Intermediate bytes and anything unexpected are ignored
+                    _ => {}
+                },
             }
         }
     }
 
+    /// Applies the parameters of an SGR (`ESC [ ... m`) sequence to the color
+    /// code. Parameters other than colors are ignored.
+    fn select_graphic_rendition(&mut self) {
+        // `ESC [ m` is the same as `ESC [ 0 m`
+        if self.escape_parameters.is_empty() {
+            self.color_code = DEFAULT_COLOR_CODE;
+        }
+
+        for &parameter in &self.escape_parameters {
+            self.color_code = match parameter {
+                0 => DEFAULT_COLOR_CODE,
+                30..=37 => self
+                    .color_code
+                    .with_foreground(ANSI_COLORS[(parameter - 30) as usize]),
+                39 => self.color_code.with_foreground(Color::White),
+                90..=97 => self
+                    .color_code
+                    .with_foreground(ANSI_BRIGHT_COLORS[(parameter - 90) as usize]),
+                _ => continue,
+            };
+        }
+    }
+
     fn new_line(&mut self) {
         for row in 1..BUFFER_HEIGHT {
             for col in 0..BUFFER_WIDTH {
@@ -119,6 +249,28 @@ impl Writer {
     }
 }
 
+/// Returns the byte which draws the character in code page 437, the character
+/// set of the VGA text mode font. Only the line drawing characters are mapped
+/// for now.
+fn code_page_437(c: char) -> Option<u8> {
+    let byte = match c {
+        '│' => 0xB3,
+        '┤' => 0xB4,
+        '┐' => 0xBF,
+        '└' => 0xC0,
+        '┴' => 0xC1,
+        '┬' => 0xC2,
+        '├' => 0xC3,
+        '─' => 0xC4,
+        '┼' => 0xC5,
+        '┘' => 0xD9,
+        '┌' => 0xDA,
+        _ => return None,
+    };
+
+    Some(byte)
+}
+
 impl core::fmt::Write for Writer {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
         self.write_string(s);
@@ -129,8 +281,10 @@ impl core::fmt::Write for Writer {
 lazy_static::lazy_static! {
     static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
         column_position: 0,
-        color_code: ColorCode::new(Color::White, Color::Black),
+        color_code: DEFAULT_COLOR_CODE,
         buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+        escape_state: EscapeState::Ground,
+        escape_parameters: heapless::Vec::new(),
     });
 }
 
@@ -146,6 +300,23 @@ pub fn _print(args: core::fmt::Arguments) {
     });
 }
 
+/// Blanks every row of the screen using the current color code and moves the
+/// writer and the hardware cursor back to the start of the line. Since the
+/// writer always prints on the bottom row, that is where the cursor ends up.
+pub fn clear_screen() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+
+        for row in 0..BUFFER_HEIGHT {
+            writer.clear_row(row);
+        }
+
+        writer.column_position = 0;
+    });
+
+    set_cursor_position(0, BUFFER_HEIGHT as u8 - 1);
+}
+
 /// Changes the current color code of the VGA writer
 pub fn set_color_code(color: ColorCode) {
     x86_64::instructions::interrupts::without_interrupts(|| {
@@ -195,6 +366,27 @@ pub fn set_column_position(position: u8) {
 const VGA_CMD_PORT: u16 = 0x3D4;
 const VGA_DATA_PORT: u16 = 0x3D5;
 
+/* CRT controller registers, selected by writing their index to the command port */
+
+/// Bits 0-4 hold the first scanline of the cursor, bit 5 hides it
+const CRTC_CURSOR_START: u8 = 0x0A;
+/// Bits 0-4 hold the last scanline of the cursor
+const CRTC_CURSOR_END: u8 = 0x0B;
+const CRTC_CURSOR_LOCATION_HIGH: u8 = 0x0E;
+const CRTC_CURSOR_LOCATION_LOW: u8 = 0x0F;
+
+/// The last scanline of a character cell in the default 80x25 text mode
+const MAX_SCANLINE: u8 = 15;
+
+/// A cursor which fills the entire character cell
+pub const CURSOR_BLOCK: (u8, u8) = (0, MAX_SCANLINE);
+/// A cursor which only covers the bottom of the character cell
+pub const CURSOR_UNDERLINE: (u8, u8) = (13, MAX_SCANLINE);
+
+/// The scanlines of the cursor which was last chosen with [`set_cursor_shape`],
+/// or None if it was hidden with [`hide_cursor`]
+static CURSOR_SHAPE: Mutex<Option<(u8, u8)>> = Mutex::new(Some(CURSOR_UNDERLINE));
+
 /// Moves the cursor on the current line
 pub fn set_cursor_position(x: u8, y: u8) {
     x86_64::instructions::interrupts::without_interrupts(|| {
@@ -204,39 +396,76 @@ pub fn set_cursor_position(x: u8, y: u8) {
         let pos = y as u16 * BUFFER_WIDTH as u16 + x as u16;
 
         unsafe {
-            cmd_port.write(0x0F);
+            cmd_port.write(CRTC_CURSOR_LOCATION_LOW);
             data_port.write((pos & 0xFF) as u8);
-            cmd_port.write(0x0E);
+            cmd_port.write(CRTC_CURSOR_LOCATION_HIGH);
             data_port.write(((pos >> 8) & 0xFF) as u8);
         }
     });
 }
 
+/// Shows the cursor spanning the given scanlines of the character cell without
+/// changing the stored cursor shape
 pub fn enable_cursor(start: u8, end: u8) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let mut cmd_port = Port::<u8>::new(VGA_CMD_PORT);
         let mut data_port = Port::<u8>::new(VGA_DATA_PORT);
 
         unsafe {
-            cmd_port.write(0x0A);
+            cmd_port.write(CRTC_CURSOR_START);
             let s = data_port.read();
-            data_port.write((s & 0xC0) | start);
+            data_port.write((s & 0xC0) | (start & 0x1F));
 
-            cmd_port.write(0x0B);
+            cmd_port.write(CRTC_CURSOR_END);
             let e = data_port.read();
-            data_port.write((e & 0xE0) | end);
+            data_port.write((e & 0xE0) | (end & 0x1F));
         }
     });
 }
 
+/// Hides the cursor without changing the stored cursor shape
 pub fn disable_cursor() {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let mut cmd_port = Port::<u8>::new(VGA_CMD_PORT);
         let mut data_port = Port::<u8>::new(VGA_DATA_PORT);
 
         unsafe {
-            cmd_port.write(0x0A);
+            cmd_port.write(CRTC_CURSOR_START);
             data_port.write(0x20);
         }
     });
 }
+
+/// Shows the cursor spanning the given scanlines (0 being the top of the
+/// character cell) and remembers the shape so that it can be reinstated with
+/// [`restore_cursor`]. The scanlines are clamped to the height of a cell.
+pub fn set_cursor_shape(start: u8, end: u8) {
+    let end = end.min(MAX_SCANLINE);
+    let start = start.min(end);
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        *CURSOR_SHAPE.lock() = Some((start, end));
+    });
+
+    enable_cursor(start, end);
+}
+
+/// Hides the cursor until the next call to [`set_cursor_shape`]
+pub fn hide_cursor() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        *CURSOR_SHAPE.lock() = None;
+    });
+
+    disable_cursor();
+}
+
+/// Reapplies the stored cursor shape (i.e. after the cursor was disabled or the
+/// terminal was reset)
+pub fn restore_cursor() {
+    let shape = x86_64::instructions::interrupts::without_interrupts(|| *CURSOR_SHAPE.lock());
+
+    match shape {
+        Some((start, end)) => enable_cursor(start, end),
+        None => disable_cursor(),
+    }
+}